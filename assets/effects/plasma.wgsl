@@ -0,0 +1,9 @@
+// Example effect, color bands drifting with time and pulsing with the bass
+fn main_image(uv: vec2<f32>, pixel: vec2<f32>) -> vec4<f32> {
+    let t = effect.time;
+    let wave = sin(pixel.x * 0.2 + t) + sin(pixel.y * 0.3 - t * 1.3)
+        + sin((pixel.x + pixel.y) * 0.1 + t * 0.7);
+    let hue = wave * 0.5 + audio_band(0u) * 2.0;
+    let color = 0.5 + 0.5 * cos(vec3<f32>(0.0, 2.094, 4.189) + hue);
+    return vec4<f32>(color * (0.6 + 0.4 * effect.level), 1.0);
+}
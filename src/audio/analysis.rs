@@ -0,0 +1,273 @@
+use bevy::prelude::*;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Arc};
+
+/// Number of log spaced frequency bands, from `LOWEST_BAND_HZ` up to 8kHz
+pub const AUDIO_BANDS: usize = 8;
+const LOWEST_BAND_HZ: f32 = 80.0;
+const HIGHEST_BAND_HZ: f32 = 8000.0;
+const WINDOW_LEN: usize = 1024;
+
+/// Rough mouth shapes, enough to tell talking apart from humming or hissing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize)]
+pub enum Viseme {
+    #[default]
+    Rest,
+    // Open vowels, energy in the low mids
+    Aa,
+    // Rounded vowels, energy in the lows
+    Oh,
+    // Spread vowels, energy in the upper mids
+    Ee,
+    // Sibilants, energy in the highs
+    Ss,
+}
+
+impl Viseme {
+    pub const ALL: [Viseme; 5] = [Viseme::Rest, Viseme::Aa, Viseme::Oh, Viseme::Ee, Viseme::Ss];
+
+    fn index(self) -> usize {
+        Viseme::ALL.iter().position(|v| *v == self).unwrap()
+    }
+}
+
+/// Attack/release smoothing and scaling of the analysis
+#[derive(Debug, Clone, Resource)]
+pub struct AudioSmoothing {
+    // Seconds to rise most of the way to a louder level
+    pub attack: f32,
+    // Seconds to fall most of the way to a quieter level
+    pub release: f32,
+    // Multiplies the RMS amplitude before clamping the level to 0..1
+    pub gain: f32,
+    // RMS amplitude under which the input counts as silence
+    pub silence_threshold: f32,
+}
+
+impl Default for AudioSmoothing {
+    fn default() -> Self {
+        AudioSmoothing {
+            attack: 0.03,
+            release: 0.12,
+            gain: 6.0,
+            silence_threshold: 0.01,
+        }
+    }
+}
+
+impl AudioSmoothing {
+    /// One pole filter step with separate rise and fall time constants
+    fn smooth(&self, current: f32, target: f32, delta: f32) -> f32 {
+        let time_constant = if target > current {
+            self.attack
+        } else {
+            self.release
+        };
+        if time_constant <= 0.0 {
+            return target;
+        }
+        current + (target - current) * (1.0 - (-delta / time_constant).exp())
+    }
+}
+
+/// Raw analysis of the latest window
+pub struct Analysis {
+    pub rms: f32,
+    pub bands: [f32; AUDIO_BANDS],
+}
+
+/// Smoothed audio features of the current frame
+#[derive(Debug, Clone, Default, Resource)]
+pub struct AudioLevels {
+    // Unsmoothed RMS amplitude of the latest window
+    pub amplitude: f32,
+    // Smoothed, gain adjusted level in 0..1
+    pub level: f32,
+    // Smoothed band magnitudes, lowest band first
+    pub bands: [f32; AUDIO_BANDS],
+    // Dominant mouth shape
+    pub viseme: Viseme,
+    // Smoothed activation of every viseme, indexed like `Viseme::ALL`
+    pub viseme_weights: [f32; Viseme::ALL.len()],
+}
+
+impl AudioLevels {
+    pub fn viseme_weight(&self, viseme: Viseme) -> f32 {
+        self.viseme_weights[viseme.index()]
+    }
+
+    pub fn update(&mut self, analysis: &Analysis, smoothing: &AudioSmoothing, delta: f32) {
+        self.amplitude = analysis.rms;
+        let level = (analysis.rms * smoothing.gain).clamp(0.0, 1.0);
+        self.level = smoothing.smooth(self.level, level, delta);
+        for (band, target) in self.bands.iter_mut().zip(analysis.bands) {
+            *band = smoothing.smooth(*band, target, delta);
+        }
+
+        let viseme = classify(analysis, smoothing.silence_threshold);
+        for (weight, candidate) in self.viseme_weights.iter_mut().zip(Viseme::ALL) {
+            let target = match candidate {
+                _ if candidate != viseme => 0.0,
+                Viseme::Rest => 1.0,
+                _ => level,
+            };
+            *weight = smoothing.smooth(*weight, target, delta);
+        }
+        self.viseme = viseme;
+    }
+}
+
+/// Picks a viseme from where the spectral energy sits
+fn classify(analysis: &Analysis, silence_threshold: f32) -> Viseme {
+    if analysis.rms < silence_threshold {
+        return Viseme::Rest;
+    }
+    // 80-250Hz, 250-800Hz, 800-2500Hz and 2500-8000Hz with the default bands
+    let low: f32 = analysis.bands[..2].iter().sum();
+    let mid: f32 = analysis.bands[2..4].iter().sum();
+    let upper: f32 = analysis.bands[4..6].iter().sum();
+    let high: f32 = analysis.bands[6..].iter().sum();
+    let total = low + mid + upper + high;
+    if total <= f32::EPSILON {
+        return Viseme::Rest;
+    }
+
+    if high / total > 0.45 {
+        Viseme::Ss
+    } else if low / total > 0.55 {
+        Viseme::Oh
+    } else if upper / total > mid / total {
+        Viseme::Ee
+    } else {
+        Viseme::Aa
+    }
+}
+
+/// Sliding window FFT over the most recent samples
+pub struct SpectrumAnalyzer {
+    window: VecDeque<f32>,
+    hann: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    // Band edges as FFT bin indices, `AUDIO_BANDS + 1` entries
+    band_bins: Vec<usize>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(sample_rate: u32) -> SpectrumAnalyzer {
+        let bin_hz = sample_rate as f32 / WINDOW_LEN as f32;
+        let highest = HIGHEST_BAND_HZ.min(sample_rate as f32 / 2.0);
+        let band_bins = (0..=AUDIO_BANDS)
+            .map(|i| {
+                let hz =
+                    LOWEST_BAND_HZ * (highest / LOWEST_BAND_HZ).powf(i as f32 / AUDIO_BANDS as f32);
+                ((hz / bin_hz).round() as usize).clamp(1, WINDOW_LEN / 2)
+            })
+            .collect();
+
+        SpectrumAnalyzer {
+            window: VecDeque::from(vec![0.0; WINDOW_LEN]),
+            hann: (0..WINDOW_LEN)
+                .map(|i| {
+                    0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / (WINDOW_LEN - 1) as f32).cos()
+                })
+                .collect(),
+            fft: FftPlanner::new().plan_fft_forward(WINDOW_LEN),
+            band_bins,
+        }
+    }
+
+    pub fn window_len(&self) -> usize {
+        WINDOW_LEN
+    }
+
+    pub fn push(&mut self, samples: impl IntoIterator<Item = f32>) {
+        self.window.extend(samples);
+        let excess = self.window.len().saturating_sub(WINDOW_LEN);
+        self.window.drain(..excess);
+    }
+
+    pub fn analyze(&self) -> Analysis {
+        let rms = (self.window.iter().map(|s| s * s).sum::<f32>() / WINDOW_LEN as f32).sqrt();
+
+        let mut spectrum = self
+            .window
+            .iter()
+            .zip(self.hann.iter())
+            .map(|(sample, hann)| Complex::new(sample * hann, 0.0))
+            .collect::<Vec<_>>();
+        self.fft.process(&mut spectrum);
+
+        let mut bands = [0.0; AUDIO_BANDS];
+        for (band, edges) in bands.iter_mut().zip(self.band_bins.windows(2)) {
+            let bins = &spectrum[edges[0]..edges[1].max(edges[0] + 1)];
+            // Average magnitude, scaled so the bin of a full scale sine reads about 1
+            *band = bins.iter().map(|c| c.norm()).sum::<f32>() / bins.len() as f32 * 4.0
+                / WINDOW_LEN as f32;
+        }
+        Analysis { rms, bands }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16000;
+
+    fn sine(hz: f32, amplitude: f32) -> impl Iterator<Item = f32> {
+        (0..WINDOW_LEN).map(move |i| {
+            amplitude * (std::f32::consts::TAU * hz * i as f32 / SAMPLE_RATE as f32).sin()
+        })
+    }
+
+    fn analyze(samples: impl IntoIterator<Item = f32>) -> Analysis {
+        let mut analyzer = SpectrumAnalyzer::new(SAMPLE_RATE);
+        analyzer.push(samples);
+        analyzer.analyze()
+    }
+
+    #[test]
+    fn rms_of_a_sine() {
+        let analysis = analyze(sine(500.0, 0.5));
+        assert!((analysis.rms - 0.5 / 2.0f32.sqrt()).abs() < 0.01);
+        assert_eq!(analyze(std::iter::repeat_n(0.0, WINDOW_LEN)).rms, 0.0);
+    }
+
+    #[test]
+    fn visemes_follow_the_spectrum() {
+        let threshold = AudioSmoothing::default().silence_threshold;
+        assert_eq!(classify(&analyze(sine(150.0, 0.5)), threshold), Viseme::Oh);
+        assert_eq!(classify(&analyze(sine(500.0, 0.5)), threshold), Viseme::Aa);
+        assert_eq!(classify(&analyze(sine(1500.0, 0.5)), threshold), Viseme::Ee);
+        assert_eq!(classify(&analyze(sine(5000.0, 0.5)), threshold), Viseme::Ss);
+        // Too quiet to count as voice
+        assert_eq!(
+            classify(&analyze(sine(500.0, 0.005)), threshold),
+            Viseme::Rest
+        );
+    }
+
+    #[test]
+    fn levels_rise_while_talking_and_fall_in_silence() {
+        let smoothing = AudioSmoothing::default();
+        let mut levels = AudioLevels::default();
+        let talking = analyze(sine(500.0, 0.5));
+        for _ in 0..60 {
+            levels.update(&talking, &smoothing, 1.0 / 60.0);
+        }
+        assert_eq!(levels.viseme, Viseme::Aa);
+        assert!(levels.level > 0.99);
+        assert!(levels.viseme_weight(Viseme::Aa) > 0.99);
+        assert!(levels.viseme_weight(Viseme::Rest) < 0.01);
+
+        let silence = analyze(std::iter::repeat_n(0.0, WINDOW_LEN));
+        for _ in 0..120 {
+            levels.update(&silence, &smoothing, 1.0 / 60.0);
+        }
+        assert_eq!(levels.viseme, Viseme::Rest);
+        assert!(levels.level < 0.01);
+        assert!(levels.viseme_weight(Viseme::Aa) < 0.01);
+        assert!(levels.viseme_weight(Viseme::Rest) > 0.99);
+    }
+}
@@ -0,0 +1,311 @@
+use bevy::prelude::*;
+use crossbeam_channel::{Receiver, Sender};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufReader, Read},
+    path::PathBuf,
+};
+
+use crate::audio::{
+    AudioLevels, AudioSmoothing, VisemeShapes,
+    analysis::SpectrumAnalyzer,
+    audio_mouth::{drive_mouth_from_audio, record_audio_levels},
+};
+
+/// Where PCM samples come from. Raw sources carry signed 16 bit little endian mono samples,
+/// e.g. `arecord -f S16_LE -c 1 -r 16000 -t raw > /tmp/protogen_audio`.
+#[derive(Debug, Clone)]
+pub enum PcmSource {
+    Wav(PathBuf),
+    Stdin { sample_rate: u32 },
+    // Regular file or named pipe with raw samples, pipes are reopened when the producer goes away
+    Raw { path: PathBuf, sample_rate: u32 },
+}
+
+impl PcmSource {
+    /// `-` selects stdin, `.wav` files are decoded, anything else is read as raw samples
+    pub fn from_arg(arg: &str, sample_rate: u32) -> PcmSource {
+        let path = PathBuf::from(arg);
+        match arg {
+            "-" => PcmSource::Stdin { sample_rate },
+            _ if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("wav")) =>
+            {
+                PcmSource::Wav(path)
+            }
+            _ => PcmSource::Raw { path, sample_rate },
+        }
+    }
+}
+
+/// Reads PCM audio, analyzes it into [`AudioLevels`] and moves the mouth with it
+#[derive(Debug, Clone)]
+pub struct AudioInputPlugin {
+    pub source: PcmSource,
+    pub smoothing: AudioSmoothing,
+}
+
+// Chunks in flight between the reader thread and the main world,
+// a WAV file is read ahead at most this far
+const CHANNEL_CHUNKS: usize = 32;
+const CHUNK_SAMPLES: usize = 512;
+
+/// Samples from the reader thread, the first message is the sample rate
+enum PcmMessage {
+    SampleRate(u32),
+    Samples(Vec<f32>),
+}
+
+#[derive(Resource)]
+struct PcmReceiver {
+    receiver: Receiver<PcmMessage>,
+    sample_rate: u32,
+    // Samples received but not consumed by simulated time yet
+    pending: VecDeque<f32>,
+    // Fractional sample left over from the previous frame
+    carry: f64,
+    live: bool,
+}
+
+impl Plugin for AudioInputPlugin {
+    fn build(&self, app: &mut App) {
+        let (s, r) = crossbeam_channel::bounded(CHANNEL_CHUNKS);
+        let source = self.source.clone();
+        let live = !matches!(source, PcmSource::Wav(_));
+        // Reading blocks, so it gets its own thread instead of a system
+        std::thread::Builder::new()
+            .name(String::from("audio input"))
+            .spawn(move || read_pcm(source, s))
+            .expect("Failed to spawn audio input thread");
+
+        app.insert_resource(PcmReceiver {
+            receiver: r,
+            sample_rate: 0,
+            pending: VecDeque::new(),
+            carry: 0.0,
+            live,
+        })
+        .insert_resource(self.smoothing.clone())
+        .init_resource::<AudioLevels>()
+        .init_resource::<VisemeShapes>()
+        .add_systems(PreUpdate, (analyze_audio, drive_mouth_from_audio).chain())
+        .add_systems(PostUpdate, record_audio_levels);
+    }
+}
+
+fn read_pcm(source: PcmSource, sender: Sender<PcmMessage>) {
+    let result = match source {
+        PcmSource::Wav(path) => read_wav(&path, &sender),
+        PcmSource::Stdin { sample_rate } => {
+            read_raw(std::io::stdin().lock(), sample_rate, &sender).map(|_| ())
+        }
+        PcmSource::Raw { path, sample_rate } => loop {
+            let result = File::open(&path)
+                .map_err(|e| format!("{path:?}: {e}"))
+                .and_then(|file| read_raw(BufReader::new(file), sample_rate, &sender));
+            // A named pipe reaches end of file whenever its producer closes it,
+            // opening it again waits for the next producer
+            match result {
+                Ok(true) if is_pipe(&path) => {}
+                result => break result.map(|_| ()),
+            }
+        },
+    };
+    if let Err(e) = result {
+        error!("Audio input stopped: {e}");
+    }
+}
+
+fn read_wav(path: &PathBuf, sender: &Sender<PcmMessage>) -> Result<(), String> {
+    let mut reader = hound::WavReader::open(path).map_err(|e| format!("{path:?}: {e}"))?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    if sender
+        .send(PcmMessage::SampleRate(spec.sample_rate))
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    // Everything is downmixed to mono floats in -1..1
+    let samples: Box<dyn Iterator<Item = f32>> = match spec.sample_format {
+        hound::SampleFormat::Float => Box::new(reader.samples::<f32>().map_while(Result::ok)),
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            Box::new(
+                reader
+                    .samples::<i32>()
+                    .map_while(Result::ok)
+                    .map(move |sample| sample as f32 * scale),
+            )
+        }
+    };
+
+    let mut chunk = Vec::with_capacity(CHUNK_SAMPLES);
+    let mut frame_sum = 0.0;
+    for (i, sample) in samples.enumerate() {
+        frame_sum += sample;
+        if (i + 1) % channels == 0 {
+            chunk.push(frame_sum / channels as f32);
+            frame_sum = 0.0;
+        }
+        if chunk.len() == CHUNK_SAMPLES {
+            // Fails only when the app is exiting
+            if sender
+                .send(PcmMessage::Samples(std::mem::take(&mut chunk)))
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
+    }
+    let _ = sender.send(PcmMessage::Samples(chunk));
+    info!("Audio input reached the end of {path:?}");
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_pipe(path: &PathBuf) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path)
+        .map(|metadata| metadata.file_type().is_fifo())
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_pipe(_path: &PathBuf) -> bool {
+    false
+}
+
+/// Forwards raw samples until the end of the input, `true`, or until the app stops
+/// listening, `false`
+fn read_raw(
+    mut reader: impl Read,
+    sample_rate: u32,
+    sender: &Sender<PcmMessage>,
+) -> Result<bool, String> {
+    if sender.send(PcmMessage::SampleRate(sample_rate)).is_err() {
+        return Ok(false);
+    }
+    let mut bytes = vec![0u8; CHUNK_SAMPLES * 2];
+    loop {
+        // A pipe may deliver odd byte counts, so always read whole samples
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(true),
+            Err(e) => return Err(e.to_string()),
+        }
+        let chunk = bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect();
+        if sender.send(PcmMessage::Samples(chunk)).is_err() {
+            return Ok(false);
+        }
+    }
+}
+
+/// Consumes exactly the samples covered by this frame's simulated time and analyzes them
+fn analyze_audio(
+    time: Res<Time>,
+    mut pcm: ResMut<PcmReceiver>,
+    mut analyzer: Local<Option<SpectrumAnalyzer>>,
+    smoothing: Res<AudioSmoothing>,
+    mut levels: ResMut<AudioLevels>,
+) {
+    let pcm = &mut *pcm;
+    // A file is read ahead, waiting for the chunks this frame needs keeps playback
+    // tied to simulated time instead of to how fast the thread reads
+    let needed = |pcm: &PcmReceiver| {
+        (pcm.carry + time.delta_secs_f64() * pcm.sample_rate as f64).floor() as usize
+    };
+    loop {
+        let message = if pcm.live {
+            pcm.receiver.try_recv().ok()
+        } else if pcm.sample_rate == 0 || pcm.pending.len() < needed(pcm) {
+            // Blocks until the reader caught up, `None` at the end of the file
+            pcm.receiver.recv().ok()
+        } else {
+            None
+        };
+        match message {
+            Some(PcmMessage::SampleRate(sample_rate)) => pcm.sample_rate = sample_rate,
+            Some(PcmMessage::Samples(samples)) => pcm.pending.extend(samples),
+            None => break,
+        }
+    }
+    if pcm.sample_rate == 0 {
+        return;
+    }
+
+    let frame_samples = time.delta_secs_f64() * pcm.sample_rate as f64 + pcm.carry;
+    let count = (frame_samples.floor() as usize).min(pcm.pending.len());
+    pcm.carry = frame_samples.fract();
+
+    let analyzer = analyzer.get_or_insert_with(|| SpectrumAnalyzer::new(pcm.sample_rate));
+    analyzer.push(pcm.pending.drain(..count));
+    if pcm.live {
+        // Live input must not lag behind, anything older than one window is dropped
+        let excess = pcm.pending.len().saturating_sub(analyzer.window_len());
+        pcm.pending.drain(..excess);
+    }
+
+    let analysis = analyzer.analyze();
+    levels.update(&analysis, &smoothing, time.delta_secs());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::Viseme;
+
+    #[test]
+    fn raw_samples_reach_the_analysis() {
+        // Two windows of a 5kHz hiss as signed 16 bit little endian
+        let sample_rate = 16000;
+        let bytes = (0..CHUNK_SAMPLES * 4)
+            .map(|i| {
+                let phase = std::f32::consts::TAU * 5000.0 * i as f32 / sample_rate as f32;
+                (phase.sin() * 16384.0) as i16
+            })
+            .flat_map(i16::to_le_bytes)
+            .collect::<Vec<_>>();
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        assert_eq!(read_raw(bytes.as_slice(), sample_rate, &sender), Ok(true));
+        drop(sender);
+
+        let mut messages = receiver.iter();
+        assert!(matches!(
+            messages.next(),
+            Some(PcmMessage::SampleRate(16000))
+        ));
+        let mut analyzer = SpectrumAnalyzer::new(sample_rate);
+        let mut count = 0;
+        for message in messages {
+            let PcmMessage::Samples(samples) = message else {
+                panic!("sample rate sent twice");
+            };
+            count += samples.len();
+            analyzer.push(samples);
+        }
+        assert_eq!(count, CHUNK_SAMPLES * 4);
+
+        let smoothing = AudioSmoothing::default();
+        let mut levels = AudioLevels::default();
+        let analysis = analyzer.analyze();
+        assert!((analysis.rms - 0.5 / 2.0f32.sqrt()).abs() < 0.01);
+        levels.update(&analysis, &smoothing, 1.0);
+        assert_eq!(levels.viseme, Viseme::Ss);
+        assert!(levels.viseme_weight(Viseme::Ss) > 0.99);
+    }
+
+    #[test]
+    fn raw_input_stops_when_nobody_listens() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        drop(receiver);
+        assert_eq!(read_raw([0u8; 8].as_slice(), 16000, &sender), Ok(false));
+    }
+}
@@ -0,0 +1,56 @@
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::{
+    audio::{AudioLevels, Viseme},
+    face::{FaceMouth, MorphControls},
+    image_grab::FrameMetadata,
+};
+
+/// Mouth morph target weights for each viseme at full level
+#[derive(Debug, Clone, Resource, Deref, DerefMut)]
+pub struct VisemeShapes(pub HashMap<Viseme, Vec<(String, f32)>>);
+
+impl Default for VisemeShapes {
+    fn default() -> Self {
+        let shape = |weights: &[(&str, f32)]| {
+            weights
+                .iter()
+                .map(|(target, weight)| (target.to_string(), *weight))
+                .collect::<Vec<_>>()
+        };
+        VisemeShapes(HashMap::from_iter([
+            (Viseme::Rest, Vec::new()),
+            (Viseme::Aa, shape(&[("open", 1.0)])),
+            (Viseme::Oh, shape(&[("open", 0.7)])),
+            (Viseme::Ee, shape(&[("open", 0.4), ("smile", 0.6)])),
+            (Viseme::Ss, shape(&[("open", 0.15), ("smile", 0.3)])),
+        ]))
+    }
+}
+
+/// Blends the viseme shapes by their smoothed activation and adds them on top of the mouth
+/// weights, so an expression keeps its shape while talking and gets it back in silence.
+/// Sprite based mouths can read `AudioLevels::viseme` instead.
+pub fn drive_mouth_from_audio(
+    levels: Res<AudioLevels>,
+    shapes: Res<VisemeShapes>,
+    mut mouths: Query<&mut MorphControls, With<FaceMouth>>,
+) {
+    let mut weights = HashMap::<&str, f32>::new();
+    for (viseme, targets) in shapes.iter() {
+        for (target, weight) in targets {
+            *weights.entry(target.as_str()).or_default() += weight * levels.viseme_weight(*viseme);
+        }
+    }
+
+    for mut controls in mouths.iter_mut() {
+        for (target, weight) in weights.iter() {
+            controls.set_offset(target, *weight);
+        }
+    }
+}
+
+pub fn record_audio_levels(levels: Res<AudioLevels>, mut frame_metadata: ResMut<FrameMetadata>) {
+    frame_metadata.audio_level = Some(levels.level);
+    frame_metadata.viseme = Some(levels.viseme);
+}
@@ -0,0 +1,6 @@
+mod analysis;
+pub use analysis::{AudioLevels, AudioSmoothing, Viseme};
+mod audio_input;
+pub use audio_input::{AudioInputPlugin, PcmSource};
+mod audio_mouth;
+pub use audio_mouth::VisemeShapes;
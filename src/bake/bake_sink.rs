@@ -0,0 +1,126 @@
+use bevy::log::{error, info};
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    bake::{
+        LoopPoints,
+        format::{BakeHeader, FrameEncoder},
+    },
+    output::{OutputSink, PanelFrame},
+};
+
+/// Writes frames to a baked animation file, the header is completed once output ends
+pub struct BakeSink {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    encoder: FrameEncoder,
+    width: u32,
+    height: u32,
+    loop_points: Option<LoopPoints>,
+    frame_count: u32,
+    finished: bool,
+}
+
+impl BakeSink {
+    pub fn create(
+        path: &Path,
+        width: u32,
+        height: u32,
+        loop_points: Option<LoopPoints>,
+    ) -> Result<BakeSink, String> {
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(format!("{width}x{height} is too large to bake"));
+        }
+        let mut writer = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+        // Placeholder until the frame count is known
+        BakeHeader {
+            width: width as u16,
+            height: height as u16,
+            frame_count: 0,
+            loop_points: None,
+        }
+        .write(&mut writer)
+        .map_err(|e| e.to_string())?;
+
+        Ok(BakeSink {
+            path: path.to_path_buf(),
+            writer,
+            encoder: FrameEncoder::new(),
+            width,
+            height,
+            loop_points,
+            frame_count: 0,
+            finished: false,
+        })
+    }
+}
+
+impl OutputSink for BakeSink {
+    fn write_frame(&mut self, frame: &PanelFrame, duration: Duration) -> Result<(), String> {
+        if frame.width != self.width || frame.height != self.height {
+            return Err(format!(
+                "frame is {}x{}, the bake is {}x{}",
+                frame.width, frame.height, self.width, self.height
+            ));
+        }
+        // Playback jumps back to the loop start, so it must not depend on the frame before it
+        let key_frame = self
+            .loop_points
+            .is_some_and(|loop_points| loop_points.start == self.frame_count);
+        self.encoder
+            .write_frame(&mut self.writer, frame, duration, key_frame)
+            .map_err(|e| e.to_string())?;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    // Baked animations stay uncalibrated, playback calibrates them for the panels it drives
+    fn calibrated(&self) -> bool {
+        false
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        let loop_points = self
+            .loop_points
+            .map(|loop_points| LoopPoints {
+                start: loop_points.start,
+                end: loop_points.end.min(self.frame_count),
+            })
+            .filter(|loop_points| loop_points.start < loop_points.end);
+        let header = BakeHeader {
+            width: self.width as u16,
+            height: self.height as u16,
+            frame_count: self.frame_count,
+            loop_points,
+        };
+        self.writer
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| header.write(&mut self.writer))
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| e.to_string())?;
+        info!(
+            "Baked {} frames of {}x{} to {:?}",
+            self.frame_count, self.width, self.height, self.path
+        );
+        Ok(())
+    }
+}
+
+impl Drop for BakeSink {
+    // Keeps the file playable when the app exits without finishing its outputs
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            error!("Failed to finish baked animation {:?}: {e}", self.path);
+        }
+    }
+}
@@ -0,0 +1,466 @@
+//! Baked animation file, panel resolution frames compressed for playback on weak hardware.
+//!
+//! All integers are little endian.
+//!
+//! ```text
+//! header   "PBAK"  magic
+//!          u8      version, 1
+//!          u8      reserved, 0
+//!          u16     width
+//!          u16     height
+//!          u32     frame count
+//!          u32     loop start, first frame of the loop
+//!          u32     loop end, frame after the loop, equal to loop start without a loop
+//! frame    u32     duration in microseconds
+//!          u8      kind, 0 key frame, 1 delta from the previous frame
+//!          u32     payload length
+//!          ...     payload
+//! ```
+//!
+//! A key frame payload is every pixel, run length encoded. A delta payload is a list of
+//! spans, each a varint count of unchanged pixels to skip, a varint count of changed pixels
+//! and those pixels run length encoded. Pixels after the last span are unchanged.
+//!
+//! Run length encoding works on whole RGB pixels: a control byte below 128 is followed by
+//! that many plus one literal pixels, a control byte of 128 or more by a single pixel
+//! repeated `control - 126` times. A payload never takes more than a control byte per pixel
+//! on top of the pixels, longer ones are rejected as corrupt.
+//!
+//! The loop start frame is always a key frame, so playback can jump back to it.
+
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use crate::output::PanelFrame;
+
+const MAGIC: &[u8; 4] = b"PBAK";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 22;
+
+const KEY_FRAME: u8 = 0;
+const DELTA_FRAME: u8 = 1;
+
+const MAX_LITERAL: usize = 128;
+const MAX_RUN: usize = 129;
+
+/// Frames `start..end` repeat, everything before `start` is an intro played once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopPoints {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl std::str::FromStr for LoopPoints {
+    type Err = String;
+
+    /// `start..end` in frames, `start..` loops up to the last frame
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("`{s}` is not a frame range like `30..90`"))?;
+        let start = start
+            .parse()
+            .map_err(|_| format!("`{start}` is not a frame number"))?;
+        let end = match end {
+            "" => u32::MAX,
+            end => end
+                .parse()
+                .map_err(|_| format!("`{end}` is not a frame number"))?,
+        };
+        Ok(LoopPoints { start, end })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BakeHeader {
+    pub width: u16,
+    pub height: u16,
+    pub frame_count: u32,
+    // `None` when the animation plays once
+    pub loop_points: Option<LoopPoints>,
+}
+
+impl BakeHeader {
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let loop_points = self.loop_points.unwrap_or(LoopPoints { start: 0, end: 0 });
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, 0])?;
+        writer.write_all(&self.width.to_le_bytes())?;
+        writer.write_all(&self.height.to_le_bytes())?;
+        writer.write_all(&self.frame_count.to_le_bytes())?;
+        writer.write_all(&loop_points.start.to_le_bytes())?;
+        writer.write_all(&loop_points.end.to_le_bytes())
+    }
+
+    pub fn read(reader: &mut impl Read) -> Result<BakeHeader, String> {
+        let mut bytes = [0u8; HEADER_LEN];
+        reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+        if &bytes[..4] != MAGIC {
+            return Err(String::from("not a baked animation"));
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("unsupported baked animation version {}", bytes[4]));
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let (start, end) = (u32_at(14), u32_at(18));
+        Ok(BakeHeader {
+            width: u16_at(6),
+            height: u16_at(8),
+            frame_count: u32_at(10),
+            loop_points: (end > start).then_some(LoopPoints { start, end }),
+        })
+    }
+}
+
+/// Compresses frames, each one against the previous
+pub struct FrameEncoder {
+    previous: Option<PanelFrame>,
+}
+
+impl FrameEncoder {
+    pub fn new() -> FrameEncoder {
+        FrameEncoder { previous: None }
+    }
+
+    /// Writes the frame record, as a delta unless `key_frame` or a key frame is smaller
+    pub fn write_frame(
+        &mut self,
+        writer: &mut impl Write,
+        frame: &PanelFrame,
+        duration: Duration,
+        key_frame: bool,
+    ) -> io::Result<()> {
+        let key = encode_pixels(&frame.rgb);
+        let (kind, payload) = match &self.previous {
+            Some(previous) if !key_frame => {
+                let delta = encode_delta(&previous.rgb, &frame.rgb);
+                if delta.len() < key.len() {
+                    (DELTA_FRAME, delta)
+                } else {
+                    (KEY_FRAME, key)
+                }
+            }
+            _ => (KEY_FRAME, key),
+        };
+
+        let micros = duration.as_micros().min(u32::MAX as u128) as u32;
+        writer.write_all(&micros.to_le_bytes())?;
+        writer.write_all(&[kind])?;
+        writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+        writer.write_all(&payload)?;
+        self.previous = Some(frame.clone());
+        Ok(())
+    }
+}
+
+/// Decompresses frame records into the current frame
+pub struct FrameDecoder {
+    pub frame: PanelFrame,
+    payload: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new(width: u32, height: u32) -> FrameDecoder {
+        FrameDecoder {
+            frame: PanelFrame::new(width, height),
+            payload: Vec::new(),
+        }
+    }
+
+    /// Applies the next frame record, returns how long it is shown
+    pub fn read_frame(&mut self, reader: &mut impl Read) -> Result<Duration, String> {
+        let mut record = [0u8; 9];
+        reader.read_exact(&mut record).map_err(|e| e.to_string())?;
+        let micros = u32::from_le_bytes(record[..4].try_into().unwrap());
+        let kind = record[4];
+        let len = u32::from_le_bytes(record[5..].try_into().unwrap()) as usize;
+        // A control byte per pixel on top of the pixels is more than either kind ever takes
+        if len > self.frame.rgb.len() / 3 * 4 {
+            return Err(format!(
+                "frame payload of {len} bytes is too long for the panel"
+            ));
+        }
+
+        self.payload.resize(len, 0);
+        reader
+            .read_exact(&mut self.payload)
+            .map_err(|e| e.to_string())?;
+        match kind {
+            KEY_FRAME => {
+                let (_, written) = decode_pixels(&self.payload, &mut self.frame.rgb)?;
+                if written != self.frame.rgb.len() {
+                    return Err(String::from("key frame does not cover the panel"));
+                }
+            }
+            DELTA_FRAME => decode_delta(&self.payload, &mut self.frame.rgb)?,
+            other => return Err(format!("unknown frame kind {other}")),
+        }
+        Ok(Duration::from_micros(micros as u64))
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> Result<usize, String> {
+    let mut value = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let byte = *bytes
+            .get(*position)
+            .ok_or("delta frame ends inside a span")?;
+        *position += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(String::from("varint too long"))
+}
+
+fn encode_pixels(rgb: &[u8]) -> Vec<u8> {
+    let pixels = rgb.chunks_exact(3).collect::<Vec<_>>();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = pixels[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|pixel| **pixel == pixels[i])
+            .count();
+        if run >= 2 {
+            out.push((run + 126) as u8);
+            out.extend_from_slice(pixels[i]);
+            i += run;
+            continue;
+        }
+
+        // Literals up to the next run of two or more
+        let start = i;
+        while i < pixels.len()
+            && i - start < MAX_LITERAL
+            && !(i + 1 < pixels.len() && pixels[i] == pixels[i + 1])
+        {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        for pixel in &pixels[start..i] {
+            out.extend_from_slice(pixel);
+        }
+    }
+    out
+}
+
+/// Decodes pixels into `out` until either runs out, returns bytes read and written
+fn decode_pixels(encoded: &[u8], out: &mut [u8]) -> Result<(usize, usize), String> {
+    let mut read = 0;
+    let mut written = 0;
+    while read < encoded.len() && written < out.len() {
+        let control = encoded[read] as usize;
+        read += 1;
+        let (count, literal) = match control {
+            0..MAX_LITERAL => (control + 1, true),
+            _ => (control - 126, false),
+        };
+        let bytes = if literal { count * 3 } else { 3 };
+        let source = encoded
+            .get(read..read + bytes)
+            .ok_or("pixel data ends inside a run")?;
+        let target = out
+            .get_mut(written..written + count * 3)
+            .ok_or("run goes past the end of the panel")?;
+        if literal {
+            target.copy_from_slice(source);
+        } else {
+            for pixel in target.chunks_exact_mut(3) {
+                pixel.copy_from_slice(source);
+            }
+        }
+        read += bytes;
+        written += count * 3;
+    }
+    Ok((read, written))
+}
+
+fn encode_delta(previous: &[u8], rgb: &[u8]) -> Vec<u8> {
+    let previous = previous.chunks_exact(3).collect::<Vec<_>>();
+    let pixels = rgb.chunks_exact(3).collect::<Vec<_>>();
+    let changed = |i: usize| previous[i] != pixels[i];
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    let mut span_end = 0;
+    while i < pixels.len() {
+        if !changed(i) {
+            i += 1;
+            continue;
+        }
+        // A short gap of unchanged pixels is cheaper to repeat than to start a new span
+        let start = i;
+        let mut end = i + 1;
+        let mut gap = 0;
+        while end + gap < pixels.len() && gap < 4 {
+            if changed(end + gap) {
+                end += gap + 1;
+                gap = 0;
+            } else {
+                gap += 1;
+            }
+        }
+        write_varint(&mut out, start - span_end);
+        write_varint(&mut out, end - start);
+        out.extend(encode_pixels(&rgb[start * 3..end * 3]));
+        span_end = end;
+        i = end;
+    }
+    out
+}
+
+fn decode_delta(encoded: &[u8], rgb: &mut [u8]) -> Result<(), String> {
+    let mut read = 0;
+    let mut pixel = 0usize;
+    while read < encoded.len() {
+        let past_end = || String::from("delta span goes past the end of the panel");
+        pixel = pixel
+            .checked_add(read_varint(encoded, &mut read)?)
+            .ok_or_else(past_end)?;
+        let count = read_varint(encoded, &mut read)?;
+        let end = pixel
+            .checked_add(count)
+            .and_then(|end| end.checked_mul(3))
+            .ok_or_else(past_end)?;
+        let span = rgb.get_mut(pixel * 3..end).ok_or_else(past_end)?;
+        let (consumed, written) = decode_pixels(&encoded[read..], span)?;
+        if written != span.len() {
+            return Err(String::from("delta span is missing pixels"));
+        }
+        read += consumed;
+        pixel += count;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs, literals and both longer than fit in one control byte
+    fn test_pixels() -> Vec<u8> {
+        let mut rgb = Vec::new();
+        rgb.extend([10, 20, 30].repeat(300));
+        rgb.extend((0..200u32).flat_map(|i| [i as u8, (i * 7) as u8, (i * 13) as u8]));
+        rgb.extend([1, 2, 3, 1, 2, 3, 4, 5, 6]);
+        rgb.extend([0; 3]);
+        rgb
+    }
+
+    #[test]
+    fn pixels_round_trip() {
+        let rgb = test_pixels();
+        let encoded = encode_pixels(&rgb);
+        let mut decoded = vec![0; rgb.len()];
+        assert_eq!(
+            decode_pixels(&encoded, &mut decoded),
+            Ok((encoded.len(), rgb.len()))
+        );
+        assert_eq!(decoded, rgb);
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let previous = test_pixels();
+        let mut rgb = previous.clone();
+        rgb[0] = 255;
+        rgb[30..60].fill(7);
+        for offset in (600..900).step_by(5) {
+            rgb[offset] ^= 0xff;
+        }
+        let last = rgb.len() - 1;
+        rgb[last] = 99;
+
+        let encoded = encode_delta(&previous, &rgb);
+        let mut decoded = previous.clone();
+        decode_delta(&encoded, &mut decoded).unwrap();
+        assert_eq!(decoded, rgb);
+
+        assert!(encode_delta(&rgb, &rgb).is_empty());
+    }
+
+    #[test]
+    fn corrupt_delta_is_an_error() {
+        let mut rgb = test_pixels();
+        let mut huge = Vec::new();
+        write_varint(&mut huge, usize::MAX);
+        write_varint(&mut huge, usize::MAX);
+        assert!(decode_delta(&huge, &mut rgb).is_err());
+
+        let mut past_end = Vec::new();
+        write_varint(&mut past_end, rgb.len() / 3);
+        write_varint(&mut past_end, 1);
+        past_end.extend([0, 1, 2, 3]);
+        assert!(decode_delta(&past_end, &mut rgb).is_err());
+    }
+
+    #[test]
+    fn frames_round_trip_with_a_key_frame_at_the_loop_start() {
+        let (width, height) = (50, 20);
+        let frames = (0..6u8)
+            .map(|index| {
+                let mut frame = PanelFrame::new(width, height);
+                frame.rgb[..index as usize * 30].fill(index * 40);
+                frame
+            })
+            .collect::<Vec<_>>();
+        let loop_start = 3;
+
+        let mut encoder = FrameEncoder::new();
+        let mut file = Vec::new();
+        let mut kinds = Vec::new();
+        for (index, frame) in frames.iter().enumerate() {
+            kinds.push(file.len() + 4);
+            encoder
+                .write_frame(
+                    &mut file,
+                    frame,
+                    Duration::from_millis(40),
+                    index == loop_start,
+                )
+                .unwrap();
+        }
+        assert_eq!(file[kinds[loop_start]], KEY_FRAME);
+        assert_eq!(file[kinds[loop_start + 1]], DELTA_FRAME);
+
+        let mut decoder = FrameDecoder::new(width, height);
+        let mut reader = file.as_slice();
+        for frame in frames.iter() {
+            assert_eq!(
+                decoder.read_frame(&mut reader),
+                Ok(Duration::from_millis(40))
+            );
+            assert_eq!(&decoder.frame, frame);
+        }
+
+        // Jumping back to the loop start decodes it on its own
+        let mut decoder = FrameDecoder::new(width, height);
+        let mut reader = &file[kinds[loop_start] - 4..];
+        decoder.read_frame(&mut reader).unwrap();
+        assert_eq!(decoder.frame, frames[loop_start]);
+    }
+
+    #[test]
+    fn oversized_payload_is_an_error() {
+        let mut record = Vec::new();
+        record.extend(0u32.to_le_bytes());
+        record.push(KEY_FRAME);
+        record.extend(u32::MAX.to_le_bytes());
+        let mut decoder = FrameDecoder::new(4, 4);
+        assert!(decoder.read_frame(&mut record.as_slice()).is_err());
+    }
+}
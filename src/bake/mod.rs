@@ -0,0 +1,6 @@
+mod bake_sink;
+pub use bake_sink::BakeSink;
+mod format;
+pub use format::LoopPoints;
+mod playback;
+pub use playback::BakePlaybackPlugin;
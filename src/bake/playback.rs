@@ -0,0 +1,174 @@
+use bevy::{app::AppExit, platform::time::Instant, prelude::*};
+use std::{
+    fs::File,
+    io::{BufReader, Seek},
+    path::PathBuf,
+};
+
+use crate::{
+    bake::format::{BakeHeader, FrameDecoder},
+    output::{Calibration, OutputSinks, SinkConfig},
+};
+
+/// Streams a baked animation to the output sinks in real time.
+///
+/// Only needs `MinimalPlugins`, nothing of the render pipeline is initialized. Frames up to
+/// the loop end play once, then the loop repeats `loops` times (forever when `None`) before
+/// the rest of the animation plays and the app exits.
+pub struct BakePlaybackPlugin {
+    pub path: PathBuf,
+    pub sinks: Vec<SinkConfig>,
+    pub loops: Option<u32>,
+    pub calibration: Option<Calibration>,
+}
+
+#[derive(Resource)]
+struct PlaybackConfig {
+    path: PathBuf,
+    sinks: Vec<SinkConfig>,
+    loops: Option<u32>,
+    calibration: Option<Calibration>,
+}
+
+#[derive(Resource)]
+struct BakePlayback {
+    reader: BufReader<File>,
+    header: BakeHeader,
+    decoder: FrameDecoder,
+    sinks: OutputSinks,
+    // Index of the next frame to read
+    frame_index: u32,
+    // File offset of the loop start frame, known once it was read
+    loop_offset: Option<u64>,
+    loops_left: Option<u32>,
+    next_deadline: Option<Instant>,
+}
+
+impl Plugin for BakePlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PlaybackConfig {
+            path: self.path.clone(),
+            sinks: self.sinks.clone(),
+            loops: self.loops,
+            calibration: self.calibration.clone(),
+        })
+        .add_systems(Startup, open_baked_animation)
+        .add_systems(Update, play_baked_frame);
+    }
+}
+
+fn open_baked_animation(
+    mut commands: Commands,
+    config: Res<PlaybackConfig>,
+    mut app_exit_writer: MessageWriter<AppExit>,
+) {
+    let playback = File::open(&config.path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| {
+            let mut reader = BufReader::new(file);
+            let header = BakeHeader::read(&mut reader)?;
+            let (width, height) = (header.width as u32, header.height as u32);
+            let sinks = OutputSinks::open(width, height, &config.sinks)?
+                .with_calibration(config.calibration.clone());
+            if sinks.is_empty() {
+                return Err(String::from("no output sinks to play to"));
+            }
+            Ok(BakePlayback {
+                reader,
+                header,
+                decoder: FrameDecoder::new(width, height),
+                sinks,
+                frame_index: 0,
+                loop_offset: None,
+                loops_left: config.loops,
+                next_deadline: None,
+            })
+        });
+
+    match playback {
+        Ok(playback) => {
+            info!(
+                "Playing {:?}: {} frames of {}x{}, loop {:?}",
+                config.path,
+                playback.header.frame_count,
+                playback.header.width,
+                playback.header.height,
+                playback.header.loop_points
+            );
+            commands.insert_resource(playback);
+        }
+        Err(e) => {
+            error!("Failed to play {:?}: {e}", config.path);
+            app_exit_writer.write(AppExit::error());
+        }
+    }
+}
+
+/// Shows one frame per update, sleeping until it is due
+fn play_baked_frame(
+    playback: Option<ResMut<BakePlayback>>,
+    mut app_exit_writer: MessageWriter<AppExit>,
+) {
+    let Some(mut playback) = playback else {
+        return;
+    };
+    let playback = &mut *playback;
+
+    if let Some(loop_points) = playback.header.loop_points
+        && playback.frame_index == loop_points.end
+        && playback.loops_left != Some(0)
+        && let Some(loop_offset) = playback.loop_offset
+    {
+        if let Err(e) = playback.reader.seek(std::io::SeekFrom::Start(loop_offset)) {
+            error!("Failed to loop baked animation: {e}");
+            app_exit_writer.write(AppExit::error());
+            return;
+        }
+        playback.frame_index = loop_points.start;
+        if let Some(loops_left) = playback.loops_left.as_mut() {
+            *loops_left -= 1;
+        }
+    }
+
+    if playback.frame_index >= playback.header.frame_count {
+        for e in playback.sinks.finish() {
+            error!("{e}");
+        }
+        info!("Playback finished");
+        app_exit_writer.write(AppExit::Success);
+        return;
+    }
+
+    if playback
+        .header
+        .loop_points
+        .is_some_and(|loop_points| loop_points.start == playback.frame_index)
+    {
+        playback.loop_offset = playback.reader.stream_position().ok();
+    }
+    let duration = match playback.decoder.read_frame(&mut playback.reader) {
+        Ok(duration) => duration,
+        Err(e) => {
+            error!("Baked frame {} is corrupt: {e}", playback.frame_index);
+            app_exit_writer.write(AppExit::error());
+            return;
+        }
+    };
+    playback.frame_index += 1;
+
+    // Deadlines accumulate frame durations, so slow frames do not make playback drift
+    let deadline = *playback.next_deadline.get_or_insert_with(Instant::now);
+    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    playback.next_deadline = Some(deadline + duration);
+
+    for e in playback
+        .sinks
+        .write_frame(&playback.decoder.frame, duration)
+    {
+        error!("{e}");
+    }
+    if playback.sinks.is_empty() {
+        error!("Every output sink failed, stopping playback");
+        app_exit_writer.write(AppExit::error());
+    }
+}
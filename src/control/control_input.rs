@@ -0,0 +1,474 @@
+use bevy::{math::curve::easing::EaseFunction, prelude::*};
+use crossbeam_channel::{Receiver, Sender};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    str::SplitWhitespace,
+};
+
+use crate::{patterns::TestPattern, scene::PlaylistAction, transition::TransitionKind};
+
+/// Runtime command read from the control input, one per line. A `#` starting the line or
+/// following whitespace starts a comment, except in the message of `text`, which runs to the
+/// end of the line.
+///
+/// ```text
+/// expression happy 0.3        # switch expression, easing over 0.3s
+/// morph open 0.8 0.1          # set a shape key on every node that has it
+/// morph Mouth/smile 1 0.5     # set a shape key on a single node
+/// look 0.5 -0.2               # look right and down, -1..1 on both axes
+/// look reset                  # look straight ahead again
+/// text banner Hello #1 fan
+/// scene cat.glb wipe 1.5      # switch to another face, crossfade over 1s by default
+/// playlist next               # skip to the next playlist entry, also `pause` and `resume`
+/// pattern chase               # show another test pattern, with `--pattern` only
+/// ```
+#[derive(Debug, Clone, PartialEq, Message)]
+pub enum ControlCommand {
+    Expression {
+        name: String,
+        duration: Option<f32>,
+    },
+    Morph {
+        // Only the node with this name, every node having the target otherwise
+        node: Option<String>,
+        target: String,
+        weight: f32,
+        duration: f32,
+        easing: EaseFunction,
+    },
+    Look {
+        // `None` looks straight ahead
+        target: Option<Vec2>,
+    },
+    Text {
+        region: String,
+        text: String,
+    },
+    Scene {
+        path: String,
+        kind: TransitionKind,
+        duration: f32,
+    },
+    Playlist {
+        action: PlaylistAction,
+    },
+    Pattern {
+        pattern: TestPattern,
+    },
+}
+
+impl ControlCommand {
+    /// Parses one command line, `None` for empty lines and comments
+    pub fn parse(line: &str) -> Option<Result<ControlCommand, String>> {
+        let line = strip_comment(line).trim();
+        let mut words = line.split_whitespace();
+        let command = words.next()?;
+        Some(match command {
+            "expression" => Self::parse_expression(words),
+            "morph" => Self::parse_morph(words),
+            "look" => Self::parse_look(words),
+            // The message is kept as written, spaces and all
+            "text" => Self::parse_text(&line[command.len()..]),
+            "scene" => Self::parse_scene(words),
+            "playlist" => Self::parse_playlist(words),
+            "pattern" => Self::parse_pattern(words),
+            other => Err(format!("unknown command `{other}`")),
+        })
+    }
+
+    fn parse_expression(mut words: SplitWhitespace) -> Result<ControlCommand, String> {
+        let name = words.next().ok_or("expression expects a name")?.to_string();
+        let duration = words.next().map(parse_number).transpose()?;
+        Ok(ControlCommand::Expression { name, duration })
+    }
+
+    fn parse_morph(mut words: SplitWhitespace) -> Result<ControlCommand, String> {
+        let target = words.next().ok_or("morph expects a target")?;
+        let (node, target) = match target.split_once('/') {
+            Some((node, target)) => (Some(node.to_string()), target.to_string()),
+            None => (None, target.to_string()),
+        };
+        let weight = parse_number(words.next().ok_or("morph expects a weight")?)?;
+        let duration = words.next().map(parse_number).transpose()?.unwrap_or(0.0);
+        Ok(ControlCommand::Morph {
+            node,
+            target,
+            weight,
+            duration,
+            easing: EaseFunction::SmoothStep,
+        })
+    }
+
+    fn parse_look(mut words: SplitWhitespace) -> Result<ControlCommand, String> {
+        let x = words.next().ok_or("look expects `x y` or `reset`")?;
+        if x == "reset" {
+            return Ok(ControlCommand::Look { target: None });
+        }
+        let y = words.next().ok_or("look expects a y coordinate")?;
+        Ok(ControlCommand::Look {
+            target: Some(Vec2::new(parse_number(x)?, parse_number(y)?)),
+        })
+    }
+
+    fn parse_text(rest: &str) -> Result<ControlCommand, String> {
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return Err(String::from("text expects a region"));
+        }
+        let (region, text) = rest
+            .split_once(char::is_whitespace)
+            .map(|(region, text)| (region, text.trim_start()))
+            .filter(|(_, text)| !text.is_empty())
+            .ok_or("text expects a message")?;
+        Ok(ControlCommand::Text {
+            region: region.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    fn parse_scene(mut words: SplitWhitespace) -> Result<ControlCommand, String> {
+        let path = words.next().ok_or("scene expects a path")?.to_string();
+        let kind = words
+            .next()
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+        let duration = words.next().map(parse_number).transpose()?.unwrap_or(1.0);
+        Ok(ControlCommand::Scene {
+            path,
+            kind,
+            duration,
+        })
+    }
+
+    fn parse_playlist(mut words: SplitWhitespace) -> Result<ControlCommand, String> {
+        let action = words
+            .next()
+            .ok_or("playlist expects next, pause or resume")?
+            .parse()?;
+        Ok(ControlCommand::Playlist { action })
+    }
+
+    fn parse_pattern(mut words: SplitWhitespace) -> Result<ControlCommand, String> {
+        let pattern = words.next().ok_or("pattern expects a name")?.parse()?;
+        Ok(ControlCommand::Pattern { pattern })
+    }
+}
+
+/// The line up to its comment, `text` messages are kept whole
+fn strip_comment(line: &str) -> &str {
+    if line.split_whitespace().next() == Some("text") {
+        return line;
+    }
+    let comment = line.char_indices().find(|&(index, character)| {
+        character == '#'
+            && line[..index]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace)
+    });
+    match comment {
+        Some((index, _)) => &line[..index],
+        None => line,
+    }
+}
+
+fn parse_number(word: &str) -> Result<f32, String> {
+    word.parse()
+        .map_err(|_| format!("`{word}` is not a number"))
+}
+
+/// Where control commands are read from
+#[derive(Debug, Clone)]
+pub enum ControlSource {
+    Stdin,
+    // Regular file or named pipe, pipes are reopened when the writer goes away
+    Path(PathBuf),
+}
+
+impl ControlSource {
+    /// `-` selects stdin, anything else is a path
+    pub fn from_arg(arg: &str) -> ControlSource {
+        match arg {
+            "-" => ControlSource::Stdin,
+            path => ControlSource::Path(PathBuf::from(path)),
+        }
+    }
+
+    #[cfg(unix)]
+    fn is_pipe(&self) -> bool {
+        use std::os::unix::fs::FileTypeExt;
+        match self {
+            ControlSource::Stdin => false,
+            ControlSource::Path(path) => std::fs::metadata(path)
+                .map(|metadata| metadata.file_type().is_fifo())
+                .unwrap_or(false),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn is_pipe(&self) -> bool {
+        false
+    }
+}
+
+/// Receives parsed commands from the reader thread
+#[derive(Resource, Deref)]
+struct ControlReceiver(Receiver<ControlCommand>);
+
+/// Reads line commands from stdin, a file or a named pipe and publishes them as
+/// [`ControlCommand`] messages. Without a source only in-process messages are seen.
+#[derive(Default)]
+pub struct ControlPlugin {
+    pub source: Option<ControlSource>,
+}
+
+impl Plugin for ControlPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<ControlCommand>();
+
+        let Some(source) = self.source.clone() else {
+            return;
+        };
+        let (s, r) = crossbeam_channel::unbounded();
+        // Reading blocks, so it gets its own thread instead of a system
+        std::thread::Builder::new()
+            .name(String::from("control input"))
+            .spawn(move || read_commands(source, s))
+            .expect("Failed to spawn control input thread");
+
+        app.insert_resource(ControlReceiver(r))
+            .add_systems(PreUpdate, forward_commands);
+    }
+}
+
+fn read_commands(source: ControlSource, sender: Sender<ControlCommand>) {
+    loop {
+        let reader: Box<dyn BufRead> = match &source {
+            ControlSource::Stdin => Box::new(std::io::stdin().lock()),
+            ControlSource::Path(path) => match File::open(path) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(e) => {
+                    error!("Failed to open control input {path:?}: {e}");
+                    return;
+                }
+            },
+        };
+
+        for line in reader.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let command = match ControlCommand::parse(&line) {
+                Some(Ok(command)) => command,
+                Some(Err(e)) => {
+                    warn!("Ignoring control command `{line}`: {e}");
+                    continue;
+                }
+                None => continue,
+            };
+            // Fails only when the app is exiting
+            if sender.send(command).is_err() {
+                return;
+            }
+        }
+
+        // A named pipe reaches end of file whenever its writer closes it,
+        // opening it again waits for the next writer
+        if !source.is_pipe() {
+            return;
+        }
+    }
+}
+
+fn forward_commands(receiver: Res<ControlReceiver>, mut commands: MessageWriter<ControlCommand>) {
+    commands.write_batch(receiver.try_iter());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> ControlCommand {
+        match ControlCommand::parse(line) {
+            Some(Ok(command)) => command,
+            other => panic!("`{line}` parsed as {other:?}"),
+        }
+    }
+
+    fn parse_error(line: &str) -> String {
+        match ControlCommand::parse(line) {
+            Some(Err(e)) => e,
+            other => panic!("`{line}` parsed as {other:?}"),
+        }
+    }
+
+    fn text(region: &str, text: &str) -> ControlCommand {
+        ControlCommand::Text {
+            region: region.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn blank_lines_and_comments() {
+        assert_eq!(ControlCommand::parse(""), None);
+        assert_eq!(ControlCommand::parse(" \t "), None);
+        assert_eq!(ControlCommand::parse("# look 1 1"), None);
+        assert_eq!(ControlCommand::parse("   # a comment"), None);
+        assert_eq!(
+            parse("look 0.5 -0.2 # look right and down"),
+            ControlCommand::Look {
+                target: Some(Vec2::new(0.5, -0.2))
+            }
+        );
+        // Only a `#` after whitespace starts a comment
+        assert_eq!(
+            parse("expression happy#sad 0.3"),
+            ControlCommand::Expression {
+                name: String::from("happy#sad"),
+                duration: Some(0.3)
+            }
+        );
+        assert_eq!(
+            parse_error("frobnicate now"),
+            "unknown command `frobnicate`"
+        );
+    }
+
+    #[test]
+    fn expression() {
+        assert_eq!(
+            parse("expression happy"),
+            ControlCommand::Expression {
+                name: String::from("happy"),
+                duration: None
+            }
+        );
+        assert_eq!(parse_error("expression"), "expression expects a name");
+        assert_eq!(
+            parse_error("expression happy soon"),
+            "`soon` is not a number"
+        );
+    }
+
+    #[test]
+    fn morph() {
+        assert_eq!(
+            parse("morph Mouth/smile 1 0.5"),
+            ControlCommand::Morph {
+                node: Some(String::from("Mouth")),
+                target: String::from("smile"),
+                weight: 1.0,
+                duration: 0.5,
+                easing: EaseFunction::SmoothStep,
+            }
+        );
+        assert_eq!(
+            parse("morph open 0.8"),
+            ControlCommand::Morph {
+                node: None,
+                target: String::from("open"),
+                weight: 0.8,
+                duration: 0.0,
+                easing: EaseFunction::SmoothStep,
+            }
+        );
+        assert_eq!(parse_error("morph"), "morph expects a target");
+        assert_eq!(parse_error("morph open"), "morph expects a weight");
+        assert_eq!(parse_error("morph open wide"), "`wide` is not a number");
+    }
+
+    #[test]
+    fn look() {
+        assert_eq!(parse("look reset"), ControlCommand::Look { target: None });
+        assert_eq!(parse_error("look"), "look expects `x y` or `reset`");
+        assert_eq!(parse_error("look 0.5"), "look expects a y coordinate");
+        assert_eq!(parse_error("look left up"), "`left` is not a number");
+    }
+
+    #[test]
+    fn text_messages_are_kept_verbatim() {
+        assert_eq!(
+            parse("text banner Hello there"),
+            text("banner", "Hello there")
+        );
+        assert_eq!(
+            parse("text\tbanner   two  spaces\tand a tab"),
+            text("banner", "two  spaces\tand a tab")
+        );
+        assert_eq!(
+            parse("text banner #1 FAN # not a comment"),
+            text("banner", "#1 FAN # not a comment")
+        );
+        // There is no quoting, quotes are part of the message
+        assert_eq!(
+            parse(r#"text banner "quoted" 'text'"#),
+            text("banner", r#""quoted" 'text'"#)
+        );
+        assert_eq!(parse_error("text"), "text expects a region");
+        assert_eq!(parse_error("text banner"), "text expects a message");
+        assert_eq!(parse_error("text banner   "), "text expects a message");
+    }
+
+    #[test]
+    fn scene() {
+        assert_eq!(
+            parse("scene cat.glb"),
+            ControlCommand::Scene {
+                path: String::from("cat.glb"),
+                kind: TransitionKind::default(),
+                duration: 1.0,
+            }
+        );
+        assert_eq!(
+            parse("scene cat.glb wipe 1.5"),
+            ControlCommand::Scene {
+                path: String::from("cat.glb"),
+                kind: TransitionKind::Wipe,
+                duration: 1.5,
+            }
+        );
+        assert_eq!(parse_error("scene"), "scene expects a path");
+        assert!(parse_error("scene cat.glb spin").starts_with("unknown transition `spin`"));
+        assert_eq!(
+            parse_error("scene cat.glb wipe fast"),
+            "`fast` is not a number"
+        );
+    }
+
+    #[test]
+    fn playlist() {
+        assert_eq!(
+            parse("playlist next"),
+            ControlCommand::Playlist {
+                action: PlaylistAction::Next
+            }
+        );
+        assert_eq!(
+            parse("playlist pause"),
+            ControlCommand::Playlist {
+                action: PlaylistAction::Pause
+            }
+        );
+        assert_eq!(
+            parse_error("playlist"),
+            "playlist expects next, pause or resume"
+        );
+        assert!(parse_error("playlist shuffle").starts_with("unknown playlist action"));
+    }
+
+    #[test]
+    fn pattern() {
+        assert_eq!(
+            parse("pattern gray:128"),
+            ControlCommand::Pattern {
+                pattern: TestPattern::Solid([128; 3])
+            }
+        );
+        assert_eq!(parse_error("pattern"), "pattern expects a name");
+        assert!(parse_error("pattern plaid").starts_with("unknown pattern `plaid`"));
+    }
+}
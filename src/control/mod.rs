@@ -0,0 +1,2 @@
+mod control_input;
+pub use control_input::{ControlCommand, ControlPlugin, ControlSource};
@@ -0,0 +1,391 @@
+use bevy::{math::curve::easing::EaseFunction, prelude::*};
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    control::ControlCommand,
+    face::{EaseMorphWeights, FaceEye, MorphControls},
+    image_grab::FrameMetadata,
+    scene::RandomSeed,
+};
+
+/// Distribution random intervals are drawn from, in seconds
+#[derive(Debug, Clone, Copy)]
+pub enum IntervalDistribution {
+    Fixed(f32),
+    Uniform { min: f32, max: f32 },
+    // Clamped to `min` so the tail never produces back to back events
+    Normal { mean: f32, std_dev: f32, min: f32 },
+    // Memoryless, like a Poisson process, offset by `min`
+    Exponential { mean: f32, min: f32 },
+}
+
+impl std::str::FromStr for IntervalDistribution {
+    type Err = String;
+
+    /// `4`, `uniform:2:6`, `normal:4:1.5:1` (mean, deviation, minimum) or `exp:0.8:0.2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let kind = parts.next().unwrap_or_default();
+        let numbers = parts
+            .map(|part| {
+                part.parse::<f32>()
+                    .map_err(|_| format!("`{part}` is not a number"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let distribution = match (kind, numbers.as_slice()) {
+            ("uniform", &[min, max]) => IntervalDistribution::Uniform { min, max },
+            ("normal", &[mean, std_dev, min]) => {
+                IntervalDistribution::Normal { mean, std_dev, min }
+            }
+            ("exp", &[mean, min]) => IntervalDistribution::Exponential { mean, min },
+            (interval, &[]) => interval
+                .parse()
+                .map(IntervalDistribution::Fixed)
+                .map_err(|_| format!("unknown interval distribution `{s}`"))?,
+            _ => return Err(format!("unknown interval distribution `{s}`")),
+        };
+        distribution
+            .validate()
+            .map_err(|e| format!("interval distribution `{s}`: {e}"))?;
+        Ok(distribution)
+    }
+}
+
+impl IntervalDistribution {
+    /// Every interval drawn must be finite and positive, or events would fire every frame
+    fn validate(&self) -> Result<(), String> {
+        let (numbers, shortest): (&[f32], f32) = match self {
+            IntervalDistribution::Fixed(interval) => (&[*interval], *interval),
+            IntervalDistribution::Uniform { min, max } => (&[*min, *max], *min),
+            IntervalDistribution::Normal { mean, std_dev, min } => (&[*mean, *std_dev, *min], *min),
+            IntervalDistribution::Exponential { mean, min } => (&[*mean, *min], *min),
+        };
+        if numbers.iter().any(|number| !number.is_finite()) {
+            return Err(String::from("intervals must be finite"));
+        }
+        if shortest <= 0.0 {
+            return Err(String::from("the shortest interval must be positive"));
+        }
+        match *self {
+            IntervalDistribution::Uniform { min, max } if max < min => {
+                Err(String::from("uniform max is below min"))
+            }
+            IntervalDistribution::Normal { std_dev, .. } if std_dev < 0.0 => {
+                Err(String::from("normal deviation can not be negative"))
+            }
+            IntervalDistribution::Exponential { mean, min } if mean < min => {
+                Err(String::from("exp mean is below min"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        match *self {
+            IntervalDistribution::Fixed(interval) => interval,
+            IntervalDistribution::Uniform { min, max } => rng.random_range(min..=max.max(min)),
+            IntervalDistribution::Normal { mean, std_dev, min } => {
+                // Box-Muller transform
+                let u1 = rng.random::<f32>().max(f32::MIN_POSITIVE);
+                let u2 = rng.random::<f32>();
+                let z = (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos();
+                (mean + z * std_dev).max(min)
+            }
+            IntervalDistribution::Exponential { mean, min } => {
+                let u = rng.random::<f32>().max(f32::MIN_POSITIVE);
+                min - u.ln() * (mean - min).max(0.0)
+            }
+        }
+    }
+}
+
+/// Procedural eye motion settings
+#[derive(Debug, Clone, Resource)]
+pub struct EyeBehaviour {
+    pub blink_interval: IntervalDistribution,
+    // Seconds from open to closed and back
+    pub blink_duration: f32,
+    // Chance that a blink is immediately followed by a second one
+    pub double_blink_chance: f32,
+    pub saccade_interval: IntervalDistribution,
+    // Largest micro-saccade offset, in the same units as `look_range`
+    pub saccade_amplitude: f32,
+    // Eye translation at a look target of 1, in scene units
+    pub look_range: Vec2,
+    // Seconds to move most of the way to a new look target
+    pub look_smoothing: f32,
+}
+
+impl Default for EyeBehaviour {
+    fn default() -> Self {
+        EyeBehaviour {
+            blink_interval: IntervalDistribution::Normal {
+                mean: 4.0,
+                std_dev: 1.5,
+                min: 1.0,
+            },
+            blink_duration: 0.18,
+            double_blink_chance: 0.15,
+            saccade_interval: IntervalDistribution::Exponential {
+                mean: 0.8,
+                min: 0.2,
+            },
+            saccade_amplitude: 0.04,
+            look_range: Vec2::new(0.25, 0.15),
+            look_smoothing: 0.08,
+        }
+    }
+}
+
+/// Where the eyes look, -1..1 on both axes, `None` looks straight ahead
+#[derive(Debug, Clone, Copy, Default, Resource)]
+pub struct LookTarget(pub Option<Vec2>);
+
+/// Shared by both eyes so they blink and move together
+#[derive(Debug, Resource)]
+struct EyeTimeline {
+    rng: ChaCha8Rng,
+    next_blink: f32,
+    // Seconds into the current blink
+    blink: Option<f32>,
+    next_saccade: f32,
+    saccade: Vec2,
+    look: Vec2,
+}
+
+impl EyeTimeline {
+    fn new(behaviour: &EyeBehaviour, mut rng: ChaCha8Rng) -> EyeTimeline {
+        EyeTimeline {
+            next_blink: behaviour.blink_interval.sample(&mut rng),
+            next_saccade: behaviour.saccade_interval.sample(&mut rng),
+            rng,
+            blink: None,
+            saccade: Vec2::ZERO,
+            look: Vec2::ZERO,
+        }
+    }
+
+    /// Eyelid closure in 0..1
+    fn blink_weight(&self, behaviour: &EyeBehaviour) -> f32 {
+        let Some(elapsed) = self.blink else {
+            return 0.0;
+        };
+        // Lids close faster than they open
+        let t = (elapsed / behaviour.blink_duration).clamp(0.0, 1.0);
+        let closure = if t < 0.4 {
+            t / 0.4
+        } else {
+            1.0 - (t - 0.4) / 0.6
+        };
+        EaseFunction::SineInOut.sample_clamped(closure)
+    }
+}
+
+/// Transform of the eye as authored, offsets are applied on top of it
+#[derive(Debug, Clone, Copy, Component)]
+struct EyeRest(Transform);
+
+/// Blinks, micro-saccades and a look-at target for the face's eye nodes
+pub struct EyeBehaviourPlugin;
+
+impl Plugin for EyeBehaviourPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EyeBehaviour>()
+            .init_resource::<RandomSeed>()
+            .init_resource::<LookTarget>()
+            .add_systems(Startup, init_eye_timeline)
+            .add_systems(
+                Update,
+                (
+                    apply_look_commands,
+                    store_eye_rest,
+                    advance_eye_timeline,
+                    animate_eyes,
+                )
+                    .chain()
+                    .before(EaseMorphWeights),
+            )
+            .add_systems(PostUpdate, record_eye_state);
+    }
+}
+
+/// Same seed, same blinks and saccades on every run
+fn init_eye_timeline(
+    mut commands: Commands,
+    behaviour: Res<EyeBehaviour>,
+    random_seed: Res<RandomSeed>,
+) {
+    commands.insert_resource(EyeTimeline::new(&behaviour, random_seed.rng("eyes")));
+}
+
+fn apply_look_commands(
+    mut control_commands: MessageReader<ControlCommand>,
+    mut look_target: ResMut<LookTarget>,
+) {
+    for command in control_commands.read() {
+        if let ControlCommand::Look { target } = command {
+            look_target.0 = target.map(|target| target.clamp(Vec2::NEG_ONE, Vec2::ONE));
+        }
+    }
+}
+
+type NewEyeFilter = (With<FaceEye>, Without<EyeRest>);
+
+fn store_eye_rest(mut commands: Commands, eyes: Query<(Entity, &Transform), NewEyeFilter>) {
+    for (entity, transform) in eyes.iter() {
+        commands.entity(entity).insert(EyeRest(*transform));
+    }
+}
+
+fn advance_eye_timeline(
+    time: Res<Time>,
+    behaviour: Res<EyeBehaviour>,
+    look_target: Res<LookTarget>,
+    mut timeline: ResMut<EyeTimeline>,
+) {
+    let delta = time.delta_secs();
+    let timeline = &mut *timeline;
+
+    timeline.next_blink -= delta;
+    if let Some(elapsed) = timeline.blink.as_mut() {
+        *elapsed += delta;
+        if *elapsed >= behaviour.blink_duration {
+            timeline.blink = None;
+        }
+    }
+    if timeline.next_blink <= 0.0 && timeline.blink.is_none() {
+        timeline.blink = Some(0.0);
+        timeline.next_blink = if timeline.rng.random::<f32>() < behaviour.double_blink_chance {
+            behaviour.blink_duration * 1.5
+        } else {
+            behaviour.blink_interval.sample(&mut timeline.rng)
+        };
+    }
+
+    timeline.next_saccade -= delta;
+    if timeline.next_saccade <= 0.0 {
+        let angle = timeline.rng.random::<f32>() * std::f32::consts::TAU;
+        let distance = timeline.rng.random::<f32>() * behaviour.saccade_amplitude;
+        // Saccades are jumps, not eased
+        timeline.saccade = Vec2::from_angle(angle) * distance;
+        timeline.next_saccade = behaviour.saccade_interval.sample(&mut timeline.rng);
+    }
+
+    let target = look_target.0.unwrap_or(Vec2::ZERO);
+    let follow = if behaviour.look_smoothing > 0.0 {
+        1.0 - (-delta / behaviour.look_smoothing).exp()
+    } else {
+        1.0
+    };
+    timeline.look += (target - timeline.look) * follow;
+}
+
+fn animate_eyes(
+    behaviour: Res<EyeBehaviour>,
+    timeline: Res<EyeTimeline>,
+    mut eyes: Query<(&EyeRest, &mut Transform, Option<&mut MorphControls>), With<FaceEye>>,
+) {
+    let blink = timeline.blink_weight(&behaviour);
+    let offset = (timeline.look + timeline.saccade) * behaviour.look_range;
+
+    for (rest, mut transform, morph_controls) in eyes.iter_mut() {
+        transform.translation = rest.0.translation + offset.extend(0.0);
+
+        // Eyes with a `blink` shape key use it, others are squashed instead
+        let has_blink_target = morph_controls.is_some_and(|mut controls| {
+            controls.set_weight("blink", blink, 0.0, EaseFunction::Linear)
+        });
+        transform.scale = if has_blink_target {
+            rest.0.scale
+        } else {
+            rest.0.scale * Vec3::new(1.0, 1.0 - 0.9 * blink, 1.0)
+        };
+    }
+}
+
+fn record_eye_state(
+    behaviour: Res<EyeBehaviour>,
+    timeline: Res<EyeTimeline>,
+    mut frame_metadata: ResMut<FrameMetadata>,
+) {
+    frame_metadata.blink = Some(timeline.blink_weight(&behaviour));
+    frame_metadata.look = Some((timeline.look + timeline.saccade).to_array());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::time::Duration;
+
+    #[test]
+    fn interval_distributions() {
+        assert!(matches!("4".parse(), Ok(IntervalDistribution::Fixed(4.0))));
+        assert!(matches!(
+            "uniform:2:6".parse(),
+            Ok(IntervalDistribution::Uniform { min: 2.0, max: 6.0 })
+        ));
+        assert!("normal:4:1.5:1".parse::<IntervalDistribution>().is_ok());
+        assert!("exp:0.8:0.2".parse::<IntervalDistribution>().is_ok());
+    }
+
+    #[test]
+    fn intervals_must_be_finite_and_positive() {
+        for interval in [
+            "0",
+            "-1",
+            "inf",
+            "NaN",
+            "uniform:0:2",
+            "uniform:1:inf",
+            "uniform:NaN:2",
+            "uniform:3:2",
+            "normal:4:-1:1",
+            "normal:4:1:0",
+            "normal:inf:1:1",
+            "exp:0.8:0",
+            "exp:0.1:0.2",
+            "blink:1",
+        ] {
+            assert!(
+                interval.parse::<IntervalDistribution>().is_err(),
+                "{interval} was accepted"
+            );
+        }
+    }
+
+    /// Blink start times and saccade offsets over ten simulated seconds
+    fn eye_schedule(seed: u64) -> (Vec<u32>, Vec<Vec2>) {
+        let mut world = World::new();
+        world.insert_resource(Time::<()>::default());
+        world.insert_resource(EyeBehaviour::default());
+        world.insert_resource(LookTarget::default());
+        world.insert_resource(RandomSeed(seed));
+        world.run_system_once(init_eye_timeline).unwrap();
+
+        let (mut blinks, mut saccades) = (Vec::new(), Vec::new());
+        for frame in 0..600 {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(1.0 / 60.0));
+            world.run_system_once(advance_eye_timeline).unwrap();
+            let timeline = world.resource::<EyeTimeline>();
+            if timeline.blink == Some(0.0) {
+                blinks.push(frame);
+            }
+            if saccades.last() != Some(&timeline.saccade) {
+                saccades.push(timeline.saccade);
+            }
+        }
+        (blinks, saccades)
+    }
+
+    #[test]
+    fn same_seed_same_schedule() {
+        let schedule = eye_schedule(7);
+        assert!(!schedule.0.is_empty() && schedule.1.len() > 1);
+        assert_eq!(schedule, eye_schedule(7));
+        assert_ne!(schedule, eye_schedule(8));
+    }
+}
@@ -0,0 +1,124 @@
+use bevy::{camera::Viewport, prelude::*};
+
+use crate::{face::FaceCamera, output::PanelLayout, scene::SceneController};
+
+/// Face camera the atlas gave a viewport, with what it had before so it can be restored
+#[derive(Debug, Clone, Copy, Component)]
+struct AtlasCamera {
+    was_active: bool,
+    order: isize,
+}
+
+// (camera, its node name, camera, what it had before the atlas)
+type CameraState = (
+    Entity,
+    Option<&'static Name>,
+    &'static mut Camera,
+    Option<&'static AtlasCamera>,
+);
+
+/// Face cameras named by a region of the layout render into that region of the capture
+/// target, the others are switched off. Without any region naming a camera every face
+/// camera covers the whole target.
+fn assign_atlas_viewports(
+    mut commands: Commands,
+    layout: Res<PanelLayout>,
+    scene_controller: Res<SceneController>,
+    mut cameras: Query<CameraState, With<FaceCamera>>,
+    mut missing: Local<Vec<String>>,
+) {
+    let atlas = layout.regions.iter().any(|region| region.camera.is_some());
+    let scale = Vec2::new(
+        scene_controller.width as f32 / layout.size.width as f32,
+        scene_controller.height as f32 / layout.size.height as f32,
+    );
+
+    for (entity, name, mut camera, atlas_camera) in cameras.iter_mut() {
+        if !atlas {
+            if let Some(atlas_camera) = atlas_camera {
+                camera.viewport = None;
+                camera.is_active = atlas_camera.was_active;
+                camera.order = atlas_camera.order;
+                commands.entity(entity).remove::<AtlasCamera>();
+            }
+            continue;
+        }
+        if atlas_camera.is_none() {
+            commands.entity(entity).insert(AtlasCamera {
+                was_active: camera.is_active,
+                order: camera.order,
+            });
+        }
+
+        let region = layout.regions.iter().enumerate().find(|(_, region)| {
+            region.camera.is_some() && region.camera.as_deref() == name.map(Name::as_str)
+        });
+        let Some((index, region)) = region else {
+            if camera.is_active {
+                camera.is_active = false;
+            }
+            continue;
+        };
+
+        // Panel pixels to target pixels, rounded so neighbouring regions share their edge
+        let [x, y, width, height] = region.rect.map(|value| value as f32);
+        let min = (Vec2::new(x, y) * scale).round().as_uvec2();
+        let max = (Vec2::new(x + width, y + height) * scale)
+            .round()
+            .as_uvec2();
+        let viewport = Viewport {
+            physical_position: min,
+            physical_size: (max - min).max(UVec2::ONE),
+            ..default()
+        };
+        if camera
+            .viewport
+            .as_ref()
+            .map(|current| (current.physical_position, current.physical_size))
+            != Some((viewport.physical_position, viewport.physical_size))
+        {
+            camera.viewport = Some(viewport);
+        }
+        // Before the layer camera, one order per region so they do not clash
+        let order = index as isize - layout.regions.len() as isize;
+        if !camera.is_active || camera.order != order {
+            camera.is_active = true;
+            camera.order = order;
+        }
+    }
+
+    // Reported once per layout, a region naming a camera the face does not have stays empty
+    if layout.is_changed() {
+        missing.clear();
+    }
+    // Not spawned yet
+    if cameras.is_empty() {
+        return;
+    }
+    for region in layout.regions.iter() {
+        let Some(camera) = &region.camera else {
+            continue;
+        };
+        if !cameras
+            .iter()
+            .any(|(_, name, ..)| name.is_some_and(|name| name.as_str() == camera))
+            && !missing.contains(camera)
+        {
+            warn!(
+                "Region `{}` wants camera `{camera}`, the face has none",
+                region.name
+            );
+            missing.push(camera.clone());
+        }
+    }
+}
+
+/// Renders the face through a camera per panel region, all into one capture target so a
+/// single readback holds every region
+pub struct FaceAtlasPlugin;
+
+impl Plugin for FaceAtlasPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, assign_atlas_viewports);
+    }
+}
@@ -0,0 +1,205 @@
+use bevy::{
+    camera::{RenderTarget, visibility::RenderLayers},
+    core_pipeline::tonemapping::Tonemapping,
+    gltf::GltfAssetLabel,
+    light::{DirectionalLight, PointLight, SpotLight},
+    prelude::*,
+    scene::SceneInstanceReady,
+};
+
+use crate::scene::{ReadinessGate, SceneController};
+
+/// glTF/GLB asset the face is loaded from, relative to the `assets` folder
+#[derive(Debug, Clone, Resource)]
+pub struct FaceScene {
+    pub path: String,
+    // Index of the glTF scene to spawn, most exporters only write scene 0
+    pub scene_index: usize,
+}
+
+impl FaceScene {
+    pub fn new(path: impl Into<String>) -> FaceScene {
+        FaceScene {
+            path: path.into(),
+            scene_index: 0,
+        }
+    }
+
+    /// Loads the face and spawns its root, [`FaceSceneReady`] is triggered once it is spawned
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        layer: FaceLayer,
+    ) -> (Entity, Handle<Scene>) {
+        info!("Loading face scene: {}", self.path);
+        let scene = asset_server
+            .load(GltfAssetLabel::Scene(self.scene_index).from_asset(self.path.clone()));
+        let root = commands
+            .spawn((FaceRoot, layer, SceneRoot(scene.clone())))
+            .observe(discover_face_nodes)
+            .id();
+        (root, scene)
+    }
+}
+
+/// Side of the face an eye node belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EyeSide {
+    Left,
+    Right,
+}
+
+/// Eye node discovered in the face scene (`Eye.L`, `eye_right`, ...)
+#[derive(Debug, Clone, Copy, Component)]
+pub struct FaceEye {
+    pub side: EyeSide,
+}
+
+/// Mouth node discovered in the face scene
+#[derive(Debug, Clone, Copy, Component)]
+pub struct FaceMouth;
+
+/// Camera node discovered in the face scene, wired to the capture render target
+#[derive(Debug, Clone, Copy, Component)]
+pub struct FaceCamera;
+
+/// Light node discovered in the face scene
+#[derive(Debug, Clone, Copy, Component)]
+pub struct FaceLight;
+
+/// Root entity of the spawned face scene
+#[derive(Debug, Clone, Copy, Component)]
+pub struct FaceRoot;
+
+/// Render layer of every entity of a face scene, so the cameras of two faces spawned at
+/// once, e.g. during a transition, only see their own face
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct FaceLayer(pub usize);
+
+/// Target the cameras of this face render into instead of the capture target
+#[derive(Debug, Clone, Component)]
+pub struct FaceSceneTarget(pub RenderTarget);
+
+/// Triggered once the face scene is spawned and its named nodes are tagged.
+/// Capture still waits for the `ReadinessGate` after this.
+#[derive(Debug, Clone, Copy, Event)]
+pub struct FaceSceneReady {
+    pub root: Entity,
+}
+
+/// Loads the configured face asset and tags its named nodes
+pub struct FaceScenePlugin;
+
+impl Plugin for FaceScenePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_face_scene)
+            .add_observer(mark_scene_spawned);
+    }
+}
+
+fn spawn_face_scene(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    face_scene: Res<FaceScene>,
+    mut readiness_gate: ResMut<ReadinessGate>,
+) {
+    let root = face_scene.spawn(&mut commands, &asset_server, FaceLayer(1));
+    // The scene handle depends on every mesh, material and texture of the glTF
+    readiness_gate.track(face_scene.path.clone(), root.1);
+}
+
+/// Classifies node names following the usual Blender conventions,
+/// e.g. `Eye.L`, `eye_left`, `EyeR`, `Mouth`, `mouth.001`
+fn classify_eye(name: &str) -> Option<EyeSide> {
+    let name = name.to_ascii_lowercase();
+    let rest = name.strip_prefix("eye")?;
+    let is_separator = |c: char| matches!(c, '.' | '_' | '-' | ' ') || c.is_ascii_digit();
+    let rest = rest.trim_start_matches(is_separator);
+    // `eyelid_l` must not be mistaken for a left eye, so only a whole token counts
+    match rest.split(is_separator).next()? {
+        "l" | "left" => Some(EyeSide::Left),
+        "r" | "right" => Some(EyeSide::Right),
+        _ => None,
+    }
+}
+
+fn is_mouth(name: &str) -> bool {
+    name.to_ascii_lowercase().starts_with("mouth")
+}
+
+type LightFilter = Or<(With<PointLight>, With<DirectionalLight>, With<SpotLight>)>;
+
+fn discover_face_nodes(
+    ready: On<SceneInstanceReady>,
+    mut commands: Commands,
+    scene_controller: Res<SceneController>,
+    roots: Query<(&FaceLayer, Option<&FaceSceneTarget>)>,
+    children: Query<&Children>,
+    // (name, is camera, is mesh primitive)
+    nodes: Query<(Option<&Name>, Has<Camera3d>, Has<Mesh3d>)>,
+    lights: Query<(), LightFilter>,
+) {
+    let root = ready.entity;
+    let Ok((layer, target)) = roots.get(root) else {
+        return;
+    };
+    let Some(render_target) = target
+        .map(|target| target.0.clone())
+        .or_else(|| scene_controller.render_target.clone())
+    else {
+        error!("Face scene is ready but no render target was set up");
+        return;
+    };
+
+    let render_layers = RenderLayers::layer(layer.0);
+    let mut camera_found = false;
+    for entity in children.iter_descendants(root) {
+        commands.entity(entity).insert(render_layers.clone());
+        let (name, is_camera, is_primitive) = nodes.get(entity).unwrap_or_default();
+        let name = name.map(Name::as_str).unwrap_or_default();
+
+        if is_camera {
+            // Every glTF camera renders to the capture target, the loader only
+            // activates the first one so extra cameras stay inactive
+            commands
+                .entity(entity)
+                .insert((FaceCamera, render_target.clone(), Tonemapping::None));
+            camera_found = true;
+            debug!("Face camera: {name}");
+        } else if lights.contains(entity) {
+            commands.entity(entity).insert(FaceLight);
+            debug!("Face light: {name}");
+        } else if is_primitive {
+            // Mesh primitives are named after their mesh, only tag the nodes owning them
+            continue;
+        } else if let Some(side) = classify_eye(name) {
+            let eye = FaceEye { side };
+            debug!("Face eye ({:?}): {name}", eye.side);
+            commands.entity(entity).insert(eye);
+        } else if is_mouth(name) {
+            commands.entity(entity).insert(FaceMouth);
+            debug!("Face mouth: {name}");
+        }
+    }
+
+    if !camera_found {
+        warn!("Face scene has no camera, using a default one");
+        // A child of the root, so it goes away with the face
+        commands.entity(root).with_child((
+            FaceCamera,
+            Camera3d::default(),
+            render_target,
+            render_layers,
+            Tonemapping::None,
+            Transform::from_xyz(0.0, 0.0, 6.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ));
+    }
+
+    commands.trigger(FaceSceneReady { root });
+}
+
+fn mark_scene_spawned(ready: On<FaceSceneReady>, mut readiness_gate: ResMut<ReadinessGate>) {
+    info!("Face scene {} spawned", ready.root);
+    readiness_gate.mark_scene_spawned();
+}
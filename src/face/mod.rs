@@ -0,0 +1,11 @@
+mod eyes;
+pub use eyes::{EyeBehaviour, EyeBehaviourPlugin};
+mod face_atlas;
+pub use face_atlas::FaceAtlasPlugin;
+mod face_scene;
+pub use face_scene::{
+    FaceCamera, FaceEye, FaceLayer, FaceMouth, FaceRoot, FaceScene, FaceScenePlugin,
+    FaceSceneReady, FaceSceneTarget,
+};
+mod morph;
+pub use morph::{EaseMorphWeights, MorphControls, MorphPlugin};
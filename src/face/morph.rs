@@ -0,0 +1,405 @@
+use bevy::{
+    math::curve::{Curve, easing::EaseFunction},
+    mesh::morph::MorphWeights,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+
+use crate::{control::ControlCommand, image_grab::FrameMetadata};
+
+/// Eased transition of one morph target weight
+#[derive(Debug, Clone)]
+struct MorphChannel {
+    from: f32,
+    to: f32,
+    elapsed: f32,
+    duration: f32,
+    easing: EaseFunction,
+}
+
+impl MorphChannel {
+    fn new(weight: f32) -> MorphChannel {
+        MorphChannel {
+            from: weight,
+            to: weight,
+            elapsed: 0.0,
+            duration: 0.0,
+            easing: EaseFunction::Linear,
+        }
+    }
+
+    fn weight(&self) -> f32 {
+        if self.elapsed >= self.duration {
+            return self.to;
+        }
+        let t = self.easing.sample_clamped(self.elapsed / self.duration);
+        self.from + (self.to - self.from) * t
+    }
+
+    fn retarget(&mut self, to: f32, duration: f32, easing: EaseFunction) {
+        // Start from wherever the previous transition currently is, so it never jumps
+        self.from = self.weight();
+        self.to = to;
+        self.elapsed = 0.0;
+        self.duration = duration.max(0.0);
+        self.easing = easing;
+    }
+}
+
+/// Named access to the morph target (shape key) weights of a face node.
+///
+/// Added to every node with [`MorphWeights`] once its mesh is loaded, target names come
+/// from the glTF `targetNames` extra. Setting a weight eases towards it, the eased weights
+/// are written back to [`MorphWeights`] every frame.
+#[derive(Debug, Clone, Component)]
+pub struct MorphControls {
+    names: Vec<String>,
+    channels: Vec<MorphChannel>,
+    // Added on top of the eased weights by continuous drivers like the audio mouth
+    offsets: Vec<f32>,
+}
+
+impl MorphControls {
+    fn new(names: Vec<String>, weights: &[f32]) -> MorphControls {
+        MorphControls {
+            channels: weights.iter().copied().map(MorphChannel::new).collect(),
+            offsets: vec![0.0; weights.len()],
+            names,
+        }
+    }
+
+    /// Current eased weight plus offset of every target, by name
+    pub fn weights(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.names.iter().map(String::as_str).zip(
+            self.channels
+                .iter()
+                .zip(self.offsets.iter())
+                .map(|(channel, offset)| (channel.weight() + offset).clamp(0.0, 1.0)),
+        )
+    }
+
+    /// Eases the target towards `weight` over `duration` seconds, false if it does not exist
+    pub fn set_weight(
+        &mut self,
+        name: &str,
+        weight: f32,
+        duration: f32,
+        easing: EaseFunction,
+    ) -> bool {
+        match self.names.iter().position(|n| n == name) {
+            Some(index) => {
+                self.channels[index].retarget(weight, duration, easing);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds `offset` on top of the eased weight until it is set again, false if the target
+    /// does not exist. Leaves the weight set by expressions and commands untouched.
+    pub fn set_offset(&mut self, name: &str, offset: f32) -> bool {
+        match self.names.iter().position(|n| n == name) {
+            Some(index) => {
+                self.offsets[index] = offset;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn advance(&mut self, delta: f32) {
+        for channel in self.channels.iter_mut() {
+            channel.elapsed += delta;
+        }
+    }
+
+    /// Weight of a target named `target` on any node or `node/target`, 0 when no node has it
+    pub fn find_weight<'a>(
+        nodes: impl IntoIterator<Item = (&'a MorphControls, Option<&'a Name>)>,
+        name: &str,
+    ) -> f32 {
+        let (node, target) = match name.split_once('/') {
+            Some((node, target)) => (Some(node), target),
+            None => (None, name),
+        };
+        nodes
+            .into_iter()
+            .filter(|(_, name)| node.is_none() || name.map(Name::as_str) == node)
+            .flat_map(|(controls, _)| controls.weights())
+            .find(|(name, _)| *name == target)
+            .map_or(0.0, |(_, weight)| weight)
+    }
+}
+
+/// A named pose made of morph target weights
+#[derive(Debug, Clone)]
+pub struct Expression {
+    // (node, target, weight), a `None` node matches every node with the target
+    pub weights: Vec<(Option<String>, String, f32)>,
+    pub duration: f32,
+    pub easing: EaseFunction,
+}
+
+impl Expression {
+    pub fn new(duration: f32) -> Expression {
+        Expression {
+            weights: Vec::new(),
+            duration,
+            easing: EaseFunction::SmoothStep,
+        }
+    }
+
+    pub fn with(mut self, target: &str, weight: f32) -> Expression {
+        self.weights.push((None, target.to_string(), weight));
+        self
+    }
+}
+
+/// Expressions that can be selected by name
+#[derive(Debug, Clone, Resource, Deref, DerefMut)]
+pub struct Expressions(pub HashMap<String, Expression>);
+
+impl Default for Expressions {
+    fn default() -> Self {
+        Expressions(HashMap::from_iter([
+            (String::from("neutral"), Expression::new(0.25)),
+            (
+                String::from("happy"),
+                Expression::new(0.25).with("smile", 1.0),
+            ),
+            (
+                String::from("angry"),
+                Expression::new(0.15).with("angry", 1.0),
+            ),
+            (
+                String::from("surprised"),
+                Expression::new(0.1).with("open", 0.7),
+            ),
+        ]))
+    }
+}
+
+/// Expression currently applied, its targets are released when switching away
+#[derive(Debug, Default, Resource)]
+pub struct ActiveExpression {
+    pub name: Option<String>,
+    targets: HashSet<(Option<String>, String)>,
+}
+
+/// Writes the eased weights to [`MorphWeights`], systems setting weights run before it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemSet)]
+pub struct EaseMorphWeights;
+
+/// Exposes glTF morph targets by name and drives them from expressions and control input
+pub struct MorphPlugin;
+
+impl Plugin for MorphPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Expressions>()
+            .init_resource::<ActiveExpression>()
+            .add_systems(
+                Update,
+                (
+                    attach_morph_controls,
+                    apply_morph_commands,
+                    ease_morph_weights.in_set(EaseMorphWeights),
+                )
+                    .chain(),
+            )
+            .add_systems(PostUpdate, record_morph_weights);
+    }
+}
+
+/// Nodes get their controls once the mesh holding the target names is loaded
+fn attach_morph_controls(
+    mut commands: Commands,
+    nodes: Query<(Entity, &MorphWeights, Option<&Name>), Without<MorphControls>>,
+    meshes: Res<Assets<Mesh>>,
+) {
+    for (entity, weights, name) in nodes.iter() {
+        let Some(mesh) = weights.first_mesh().and_then(|handle| meshes.get(handle)) else {
+            continue;
+        };
+        let mut names = mesh
+            .try_morph_target_names()
+            .ok()
+            .flatten()
+            .map(<[String]>::to_vec)
+            .unwrap_or_default();
+        // Unnamed targets can still be driven by index
+        names.extend((names.len()..weights.weights().len()).map(|i| format!("target{i}")));
+        names.truncate(weights.weights().len());
+
+        debug!(
+            "Morph targets of {}: {names:?}",
+            name.map(Name::as_str).unwrap_or("unnamed node")
+        );
+        commands
+            .entity(entity)
+            .insert(MorphControls::new(names, weights.weights()));
+    }
+}
+
+fn set_node_weight(
+    controls: &mut Query<(&mut MorphControls, Option<&Name>)>,
+    node: Option<&str>,
+    target: &str,
+    weight: f32,
+    duration: f32,
+    easing: EaseFunction,
+) -> bool {
+    let mut found = false;
+    for (mut morph_controls, name) in controls.iter_mut() {
+        if node.is_some_and(|node| name.map(Name::as_str) != Some(node)) {
+            continue;
+        }
+        found |= morph_controls.set_weight(target, weight, duration, easing);
+    }
+    found
+}
+
+fn apply_morph_commands(
+    mut control_commands: MessageReader<ControlCommand>,
+    expressions: Res<Expressions>,
+    mut active_expression: ResMut<ActiveExpression>,
+    mut controls: Query<(&mut MorphControls, Option<&Name>)>,
+) {
+    for command in control_commands.read() {
+        match command {
+            ControlCommand::Morph {
+                node,
+                target,
+                weight,
+                duration,
+                easing,
+            } => {
+                let found = set_node_weight(
+                    &mut controls,
+                    node.as_deref(),
+                    target,
+                    *weight,
+                    *duration,
+                    *easing,
+                );
+                if !found {
+                    warn!("No morph target `{target}` on {node:?}");
+                }
+            }
+            ControlCommand::Expression { name, duration } => {
+                let Some(expression) = expressions.get(name) else {
+                    warn!("Unknown expression `{name}`");
+                    continue;
+                };
+                let duration = duration.unwrap_or(expression.duration);
+
+                let targets = expression
+                    .weights
+                    .iter()
+                    .map(|(node, target, _)| (node.clone(), target.clone()))
+                    .collect::<HashSet<_>>();
+                // Targets of the previous expression not used by this one go back to rest
+                for (node, target) in active_expression.targets.difference(&targets) {
+                    set_node_weight(
+                        &mut controls,
+                        node.as_deref(),
+                        target,
+                        0.0,
+                        duration,
+                        expression.easing,
+                    );
+                }
+                for (node, target, weight) in expression.weights.iter() {
+                    set_node_weight(
+                        &mut controls,
+                        node.as_deref(),
+                        target,
+                        *weight,
+                        duration,
+                        expression.easing,
+                    );
+                }
+
+                active_expression.name = Some(name.clone());
+                active_expression.targets = targets;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn ease_morph_weights(time: Res<Time>, mut nodes: Query<(&mut MorphControls, &mut MorphWeights)>) {
+    for (mut controls, mut weights) in nodes.iter_mut() {
+        controls.advance(time.delta_secs());
+        for (weight, (_, eased)) in weights.weights_mut().iter_mut().zip(controls.weights()) {
+            *weight = eased;
+        }
+    }
+}
+
+/// Stores the weights in the metadata delivered with the frame rendered from this state
+fn record_morph_weights(
+    nodes: Query<(&MorphControls, Option<&Name>)>,
+    active_expression: Res<ActiveExpression>,
+    mut frame_metadata: ResMut<FrameMetadata>,
+) {
+    frame_metadata.expression = active_expression.name.clone();
+    frame_metadata.morph_weights.clear();
+    for (controls, name) in nodes.iter() {
+        let node = name.map(Name::as_str).unwrap_or("unnamed");
+        for (target, weight) in controls.weights() {
+            frame_metadata
+                .morph_weights
+                .insert(format!("{node}/{target}"), weight);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easing_reaches_the_target_at_the_duration() {
+        let mut controls = MorphControls::new(vec![String::from("open")], &[0.0]);
+        assert!(controls.set_weight("open", 1.0, 0.5, EaseFunction::SmoothStep));
+        assert!(!controls.set_weight("close", 1.0, 0.5, EaseFunction::SmoothStep));
+        let weight = |controls: &MorphControls| controls.weights().next().unwrap().1;
+
+        assert_eq!(weight(&controls), 0.0);
+        controls.advance(0.25);
+        assert!((weight(&controls) - 0.5).abs() < 1e-6);
+        controls.advance(0.25);
+        assert_eq!(weight(&controls), 1.0);
+        controls.advance(1.0);
+        assert_eq!(weight(&controls), 1.0);
+    }
+
+    #[test]
+    fn retargeting_starts_from_the_current_weight() {
+        let mut channel = MorphChannel::new(0.0);
+        channel.retarget(1.0, 1.0, EaseFunction::Linear);
+        channel.elapsed = 0.5;
+        channel.retarget(0.0, 1.0, EaseFunction::Linear);
+        assert_eq!(channel.weight(), 0.5);
+        channel.elapsed = 1.0;
+        assert_eq!(channel.weight(), 0.0);
+        // A zero duration jumps straight to the target
+        channel.retarget(0.8, 0.0, EaseFunction::Linear);
+        assert_eq!(channel.weight(), 0.8);
+    }
+
+    #[test]
+    fn offsets_add_to_the_eased_weight() {
+        let mut controls = MorphControls::new(vec![String::from("smile")], &[0.0]);
+        controls.set_weight("smile", 0.6, 0.0, EaseFunction::Linear);
+        assert!(controls.set_offset("smile", 0.3));
+        assert!(!controls.set_offset("frown", 0.3));
+        assert!((controls.weights().next().unwrap().1 - 0.9).abs() < 1e-6);
+
+        controls.set_offset("smile", 0.8);
+        assert_eq!(controls.weights().next(), Some(("smile", 1.0)));
+        // Clearing the offset goes back to the weight set before
+        controls.set_offset("smile", 0.0);
+        assert!((controls.weights().next().unwrap().1 - 0.6).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,257 @@
+use bevy::{image::BevyDefault, render::render_resource::TextureFormat};
+
+/// Pixel format of a capture target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptureFormat {
+    // 8 bit sRGB, what PNGs and the panels take as is
+    #[default]
+    Rgba8,
+    // 10 bits per color channel, linear
+    Rgb10a2,
+    // Half floats, linear with headroom above 1 for tone mapping to the LEDs
+    Rgba16Float,
+}
+
+impl std::str::FromStr for CaptureFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgba8" => Ok(CaptureFormat::Rgba8),
+            "rgb10a2" => Ok(CaptureFormat::Rgb10a2),
+            "rgba16f" | "rgba16float" => Ok(CaptureFormat::Rgba16Float),
+            other => Err(format!(
+                "unknown capture format `{other}`, expected rgba8, rgb10a2 or rgba16f"
+            )),
+        }
+    }
+}
+
+impl CaptureFormat {
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            CaptureFormat::Rgba8 => TextureFormat::bevy_default(),
+            CaptureFormat::Rgb10a2 => TextureFormat::Rgb10a2Unorm,
+            CaptureFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        }
+    }
+
+    /// Bytes per pixel of the target and of the buffer it is read back into
+    pub fn pixel_size(self) -> usize {
+        match self {
+            CaptureFormat::Rgba8 | CaptureFormat::Rgb10a2 => 4,
+            CaptureFormat::Rgba16Float => 8,
+        }
+    }
+
+    /// Cameras drawing into the target need an HDR view, an 8 bit one loses the precision
+    /// and headroom before the target gets to keep them
+    pub fn needs_hdr_view(self) -> bool {
+        self != CaptureFormat::Rgba8
+    }
+
+    /// Linear RGBA of tightly packed read back pixels
+    pub fn to_linear(self, data: &[u8]) -> Vec<[f32; 4]> {
+        match self {
+            CaptureFormat::Rgba8 => data
+                .chunks_exact(4)
+                .map(|pixel| {
+                    let channel = |value: u8| srgb_to_linear(value as f32 / 255.0);
+                    [
+                        channel(pixel[0]),
+                        channel(pixel[1]),
+                        channel(pixel[2]),
+                        pixel[3] as f32 / 255.0,
+                    ]
+                })
+                .collect(),
+            CaptureFormat::Rgb10a2 => data
+                .chunks_exact(4)
+                .map(|pixel| {
+                    let bits = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                    let channel = |shift: u32| ((bits >> shift) & 0x3ff) as f32 / 1023.0;
+                    [
+                        channel(0),
+                        channel(10),
+                        channel(20),
+                        (bits >> 30) as f32 / 3.0,
+                    ]
+                })
+                .collect(),
+            CaptureFormat::Rgba16Float => data
+                .chunks_exact(8)
+                .map(|pixel| {
+                    let channel =
+                        |i: usize| f16_to_f32(u16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]));
+                    [channel(0), channel(1), channel(2), channel(3)]
+                })
+                .collect(),
+        }
+    }
+
+    /// 8 bit sRGB RGBA of tightly packed read back pixels, wider formats go through
+    /// `conversion`
+    pub fn to_rgba8(self, data: &[u8], conversion: CaptureConversion) -> Vec<u8> {
+        if self == CaptureFormat::Rgba8 {
+            return data.to_vec();
+        }
+        self.to_linear(data)
+            .into_iter()
+            .flat_map(|[r, g, b, a]| {
+                let [r, g, b] = conversion.apply([r, g, b]);
+                let encode = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                [
+                    encode(linear_to_srgb(r)),
+                    encode(linear_to_srgb(g)),
+                    encode(linear_to_srgb(b)),
+                    encode(a),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// How linear captures are brought into the 0..1 range of 8 bit images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureConversion {
+    // Values above 1 are clipped
+    Clamp,
+    // Highlights roll off smoothly instead of clipping, after scaling by `exposure`
+    ToneMapped { exposure: f32 },
+}
+
+impl CaptureConversion {
+    fn apply(self, rgb: [f32; 3]) -> [f32; 3] {
+        match self {
+            CaptureConversion::Clamp => rgb,
+            CaptureConversion::ToneMapped { exposure } => {
+                // Reinhard on the luminance, so hues stay the same while they get brighter
+                let rgb = rgb.map(|value| value.max(0.0) * exposure);
+                let luminance = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+                let scale = match luminance > 0.0 {
+                    true => 1.0 / (1.0 + luminance),
+                    false => 1.0,
+                };
+                rgb.map(|value| value * scale)
+            }
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    match value <= 0.04045 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    match value <= 0.0031308 {
+        true => value * 12.92,
+        false => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        // Subnormal
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_values() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        // Smallest subnormal and the largest one, just under the smallest normal
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x03ff), 1023.0 * 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x0400), 2f32.powi(-14));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn srgb_round_trips() {
+        for value in 0..=255u8 {
+            let encoded = value as f32 / 255.0;
+            let decoded = linear_to_srgb(srgb_to_linear(encoded));
+            assert!((decoded - encoded).abs() < 1e-5, "{value}");
+        }
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rgb10a2_bits() {
+        // Red full, green half, blue zero, alpha 2 of 3, red in the lowest bits
+        let bits: u32 = 1023 | (512 << 10) | (2 << 30);
+        let [pixel] = CaptureFormat::Rgb10a2.to_linear(&bits.to_le_bytes())[..] else {
+            panic!("expected one pixel");
+        };
+        assert_eq!(pixel[0], 1.0);
+        assert_eq!(pixel[1], 512.0 / 1023.0);
+        assert_eq!(pixel[2], 0.0);
+        assert_eq!(pixel[3], 2.0 / 3.0);
+    }
+
+    #[test]
+    fn to_linear_decodes_every_format() {
+        let rgba8 = CaptureFormat::Rgba8.to_linear(&[255, 0, 128, 51]);
+        assert_eq!(rgba8.len(), 1);
+        assert_eq!(rgba8[0][0], 1.0);
+        assert_eq!(rgba8[0][1], 0.0);
+        assert!((rgba8[0][2] - srgb_to_linear(128.0 / 255.0)).abs() < 1e-6);
+        // Alpha stays linear
+        assert_eq!(rgba8[0][3], 0.2);
+
+        let half = [0x3c00u16, 0x4000, 0x0000, 0x3800]
+            .iter()
+            .flat_map(|bits| bits.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            CaptureFormat::Rgba16Float.to_linear(&half),
+            [[1.0, 2.0, 0.0, 0.5]]
+        );
+        // Trailing bytes of an incomplete pixel are ignored
+        assert_eq!(CaptureFormat::Rgba16Float.to_linear(&half[..7]).len(), 0);
+    }
+
+    #[test]
+    fn to_rgba8_encodes_to_srgb() {
+        let data = [200, 100, 50, 255];
+        assert_eq!(
+            CaptureFormat::Rgba8.to_rgba8(&data, CaptureConversion::Clamp),
+            data
+        );
+
+        // 2.0, 0.5, -1.0, 1.0 as half floats
+        let half = [0x4000u16, 0x3800, 0xbc00, 0x3c00]
+            .iter()
+            .flat_map(|bits| bits.to_le_bytes())
+            .collect::<Vec<_>>();
+        let clamped = CaptureFormat::Rgba16Float.to_rgba8(&half, CaptureConversion::Clamp);
+        assert_eq!(clamped, [255, 188, 0, 255]);
+
+        // Tone mapping brings the highlight below white and keeps the hue ordering
+        let tone_mapped = CaptureFormat::Rgba16Float
+            .to_rgba8(&half, CaptureConversion::ToneMapped { exposure: 0.5 });
+        assert!(tone_mapped[0] < 255 && tone_mapped[0] > tone_mapped[1]);
+        assert_eq!(tone_mapped[2], 0);
+        assert_eq!(tone_mapped[3], 255);
+    }
+}
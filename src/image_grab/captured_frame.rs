@@ -0,0 +1,37 @@
+use bevy::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{audio::Viseme, image_grab::CaptureFormat};
+
+/// State of the main world a frame was rendered from.
+/// Systems fill it during `PostUpdate`, it is extracted together with the frame
+/// and delivered alongside the image data.
+#[derive(Debug, Clone, Default, Resource, Serialize)]
+pub struct FrameMetadata {
+    // Index since capture started, `None` while the scene is still being built
+    pub frame: Option<u32>,
+    // Seconds of virtual time since startup
+    pub time: f32,
+    pub expression: Option<String>,
+    // Eased morph target weights keyed `node/target`
+    pub morph_weights: BTreeMap<String, f32>,
+    // Smoothed audio level and mouth shape, when audio input is enabled
+    pub audio_level: Option<f32>,
+    pub viseme: Option<Viseme>,
+    // Eyelid closure in 0..1 and gaze offset, when eye behaviour is enabled
+    pub blink: Option<f32>,
+    pub look: Option<[f32; 2]>,
+}
+
+/// Image data read back from the gpu and the metadata it was rendered with
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    pub data: Vec<u8>,
+    pub metadata: FrameMetadata,
+    // `CaptureTarget` the frame was read back from, at the size and format it had then
+    pub target: Entity,
+    pub width: u32,
+    pub height: u32,
+    pub format: CaptureFormat,
+}
@@ -8,9 +8,11 @@ use bevy::{
         render_graph::{self, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel},
         render_resource::{
             Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, MapMode,
-            PollType, TexelCopyBufferInfo, TexelCopyBufferLayout, TextureFormat, TextureUsages,
+            PollType, TexelCopyBufferInfo, TexelCopyBufferLayout, TextureUsages,
         },
         renderer::{RenderContext, RenderDevice, RenderQueue},
+        texture::GpuImage,
+        view::Hdr,
     },
 };
 use std::sync::{
@@ -18,7 +20,10 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
 };
 
-use crate::scene::{SceneController, SceneState};
+use crate::{
+    image_grab::{CaptureFormat, CapturedFrame, FrameMetadata},
+    scene::{SceneController, SceneState},
+};
 use crossbeam_channel::{Receiver, Sender};
 
 // To communicate between the main world and the render world we need a channel.
@@ -32,55 +37,223 @@ use crossbeam_channel::{Receiver, Sender};
 // That's why there is single images role, if you want to differentiate images
 // from different cameras, you should keep Receiver in ImageCopier and Sender in ImageToSave
 // or send some id with data
+//
+// Every frame travels with the `FrameMetadata` extracted in the same frame, so the main world
+// knows which state produced the image despite the latency
 
 /// This will receive asynchronously any data sent from the render world
 #[derive(Resource, Deref)]
-pub struct MainWorldReceiver(Receiver<Vec<u8>>);
+pub struct MainWorldReceiver(Receiver<CapturedFrame>);
 
 /// This will send asynchronously any data to the main world
 #[derive(Resource, Deref)]
-struct RenderWorldSender(Sender<Vec<u8>>);
+struct RenderWorldSender(Sender<CapturedFrame>);
+
+/// Image the cameras render into that is read back every frame.
+///
+/// Spawn one to add a capture target, change its size to resize it and despawn it to
+/// remove it. `ImageCopyPlugin` allocates the render target, the buffer it is copied to and
+/// the cpu image frames are decoded into, frames still in flight for an old size or a
+/// removed target are dropped when they arrive.
+#[derive(Debug, Clone, Component)]
+pub struct CaptureTarget {
+    // Frames of extra targets are saved to a folder of this name in the output folder
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: CaptureFormat,
+}
+
+/// The capture target `SceneController` describes, its frames go to the output sinks
+#[derive(Debug, Clone, Copy, Component)]
+pub struct PrimaryCaptureTarget;
+
+/// Render target image of a [`CaptureTarget`], set once it is allocated
+#[derive(Debug, Clone, Component, Deref)]
+pub struct CaptureImage(Handle<Image>);
+
+impl CaptureImage {
+    pub fn render_target(&self) -> RenderTarget {
+        RenderTarget::Image(self.0.clone().into())
+    }
+}
 
 /// Plugin for Render world part of work
 pub struct ImageCopyPlugin;
 impl ImageCopyPlugin {
-    /// Setups render target and cpu image for saving, changes scene state into render mode
+    /// Setups the primary capture target, its render target and cpu image for saving.
+    /// The scene state stays in `BuildScene` until the `ReadinessGate` opens.
     pub fn setup_render_target(
         commands: &mut Commands,
         images: &mut ResMut<Assets<Image>>,
         render_device: &Res<RenderDevice>,
         scene_controller: &mut ResMut<SceneController>,
-        pre_roll_frames: u32,
         scene_name: String,
     ) -> RenderTarget {
-        let size = Extent3d {
+        let target = CaptureTarget {
+            name: scene_name.clone(),
             width: scene_controller.width,
             height: scene_controller.height,
-            ..Default::default()
+            format: scene_controller.capture_format,
         };
+        let mut entity = commands.spawn((target.clone(), PrimaryCaptureTarget));
+        let render_target = allocate_capture_target(&mut entity, images, render_device, &target);
 
-        // This is the texture that will be rendered to.
-        let mut render_target_image =
-            Image::new_target_texture(size.width, size.height, TextureFormat::bevy_default(), None);
-        render_target_image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
-        let render_target_image_handle = images.add(render_target_image);
+        scene_controller.state = SceneState::BuildScene;
+        scene_controller.name = scene_name;
+        scene_controller.render_target = Some(render_target.clone());
+        render_target
+    }
+}
 
-        // This is the texture that will be copied to.
-        let cpu_image =
-            Image::new_target_texture(size.width, size.height, TextureFormat::bevy_default(), None);
-        let cpu_image_handle = images.add(cpu_image);
+/// Render target, the buffer it is copied to and the cpu image frames are decoded into,
+/// replacing the ones the capture target had
+fn allocate_capture_target(
+    entity: &mut EntityCommands,
+    images: &mut Assets<Image>,
+    render_device: &RenderDevice,
+    target: &CaptureTarget,
+) -> RenderTarget {
+    let size = Extent3d {
+        width: target.width,
+        height: target.height,
+        ..Default::default()
+    };
+
+    // This is the texture that will be rendered to.
+    let mut render_target_image = Image::new_target_texture(
+        size.width,
+        size.height,
+        target.format.texture_format(),
+        None,
+    );
+    render_target_image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
+    let render_target_image_handle = images.add(render_target_image);
+
+    // This is the texture that will be copied to.
+    let cpu_image = Image::new_target_texture(
+        size.width,
+        size.height,
+        target.format.texture_format(),
+        None,
+    );
+    let cpu_image_handle = images.add(cpu_image);
+
+    let copier = ImageCopier::new(
+        entity.id(),
+        render_target_image_handle.clone(),
+        size,
+        target.format,
+        render_device,
+    );
+    let image = CaptureImage(render_target_image_handle);
+    let render_target = image.render_target();
+    entity.insert((copier, ImageToSave(cpu_image_handle), image));
+    render_target
+}
 
-        commands.spawn(ImageCopier::new(
-            render_target_image_handle.clone(),
-            size,
-            render_device,
-        ));
+/// `SceneController` width, height and capture format are those of the primary capture target
+fn resize_primary_target(
+    scene_controller: Res<SceneController>,
+    mut targets: Query<&mut CaptureTarget, With<PrimaryCaptureTarget>>,
+) {
+    for mut target in targets.iter_mut() {
+        if (target.width, target.height, target.format)
+            != (
+                scene_controller.width,
+                scene_controller.height,
+                scene_controller.capture_format,
+            )
+        {
+            target.width = scene_controller.width;
+            target.height = scene_controller.height;
+            target.format = scene_controller.capture_format;
+        }
+    }
+}
 
-        commands.spawn(ImageToSave(cpu_image_handle));
+// (entity, target, its current allocation, is primary)
+type CaptureTargetState = (
+    Entity,
+    &'static CaptureTarget,
+    Option<&'static ImageCopier>,
+    Has<PrimaryCaptureTarget>,
+);
+
+/// Allocates added capture targets and reallocates resized or reformatted ones. Cameras
+/// drawing into the old image move to the new one, so capture goes on.
+fn allocate_capture_targets(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    render_device: Res<RenderDevice>,
+    mut scene_controller: ResMut<SceneController>,
+    targets: Query<CaptureTargetState, Changed<CaptureTarget>>,
+    mut cameras: Query<&mut RenderTarget, With<Camera>>,
+) {
+    for (entity, target, image_copier, primary) in targets.iter() {
+        if image_copier.is_some_and(|copier| {
+            copier.size.width == target.width
+                && copier.size.height == target.height
+                && copier.format == target.format
+        }) {
+            continue;
+        }
+        if target.width == 0 || target.height == 0 {
+            warn!(
+                "Capture target {} can not be {}x{}",
+                target.name, target.width, target.height
+            );
+            continue;
+        }
 
-        scene_controller.state = SceneState::Render(pre_roll_frames);
-        scene_controller.name = scene_name;
-        RenderTarget::Image(render_target_image_handle.into())
+        info!(
+            "Allocating capture target {} at {}x{} {:?}",
+            target.name, target.width, target.height, target.format
+        );
+        let render_target = allocate_capture_target(
+            &mut commands.entity(entity),
+            &mut images,
+            &render_device,
+            target,
+        );
+        if let Some(image_copier) = image_copier {
+            for mut camera_target in cameras.iter_mut() {
+                if camera_target.as_image() == Some(&image_copier.src_image) {
+                    *camera_target = render_target.clone();
+                }
+            }
+        }
+        if primary {
+            scene_controller.render_target = Some(render_target);
+        }
+    }
+}
+
+/// Cameras drawing into a wide capture target render with an HDR view, so the precision and
+/// headroom of the target are not lost in an 8 bit view first
+fn sync_hdr_views(
+    mut commands: Commands,
+    images: Query<(&CaptureImage, &CaptureTarget)>,
+    cameras: Query<(Entity, &RenderTarget, Has<Hdr>), Changed<RenderTarget>>,
+) {
+    for (entity, render_target, hdr) in cameras.iter() {
+        let Some(format) = render_target.as_image().and_then(|image| {
+            images
+                .iter()
+                .find(|(capture_image, _)| capture_image.0 == *image)
+                .map(|(_, target)| target.format)
+        }) else {
+            continue;
+        };
+        match (format.needs_hdr_view(), hdr) {
+            (true, false) => {
+                commands.entity(entity).insert(Hdr);
+            }
+            (false, true) => {
+                commands.entity(entity).remove::<Hdr>();
+            }
+            _ => {}
+        }
     }
 }
 
@@ -90,6 +263,17 @@ impl Plugin for ImageCopyPlugin {
 
         let render_app = app
             .insert_resource(MainWorldReceiver(r))
+            .init_resource::<FrameMetadata>()
+            .add_systems(
+                Update,
+                (
+                    resize_primary_target.run_if(resource_changed::<SceneController>),
+                    allocate_capture_targets,
+                    sync_hdr_views,
+                )
+                    .chain(),
+            )
+            .add_systems(PostUpdate, stamp_frame_metadata)
             .sub_app_mut(RenderApp);
 
         let mut graph = render_app.world_mut().resource_mut::<RenderGraph>();
@@ -98,8 +282,12 @@ impl Plugin for ImageCopyPlugin {
 
         render_app
             .insert_resource(RenderWorldSender(s))
+            .init_resource::<FrameMetadata>()
             // Make ImageCopiers accessible in RenderWorld system and plugin
-            .add_systems(ExtractSchedule, image_copy_extract)
+            .add_systems(
+                ExtractSchedule,
+                (image_copy_extract, frame_metadata_extract),
+            )
             // Receives image data from buffer to channel
             // so we need to run it after the render graph is done
             .add_systems(
@@ -116,19 +304,26 @@ struct ImageCopiers(pub Vec<ImageCopier>);
 /// Used by `ImageCopyDriver` for copying from render target to buffer
 #[derive(Clone, Component)]
 struct ImageCopier {
+    // Main world entity of the `CaptureTarget`, frames are tagged with it
+    target: Entity,
     buffer: Buffer,
     enabled: Arc<AtomicBool>,
     src_image: Handle<Image>,
+    size: Extent3d,
+    format: CaptureFormat,
 }
 
 impl ImageCopier {
     pub fn new(
+        target: Entity,
         src_image: Handle<Image>,
         size: Extent3d,
+        format: CaptureFormat,
         render_device: &RenderDevice,
     ) -> ImageCopier {
+        // Rows are padded the same way `ImageCopyDriver` copies them
         let padded_bytes_per_row =
-            RenderDevice::align_copy_bytes_per_row((size.width) as usize) * 4;
+            RenderDevice::align_copy_bytes_per_row(size.width as usize * format.pixel_size());
 
         let cpu_buffer = render_device.create_buffer(&BufferDescriptor {
             label: None,
@@ -138,9 +333,12 @@ impl ImageCopier {
         });
 
         ImageCopier {
+            target,
             buffer: cpu_buffer,
             src_image,
             enabled: Arc::new(AtomicBool::new(true)),
+            size,
+            format,
         }
     }
 
@@ -156,16 +354,44 @@ fn image_copy_extract(mut commands: Commands, image_copiers: Extract<Query<&Imag
     ));
 }
 
+/// Numbers the frames rendered since the scene became ready
+fn stamp_frame_metadata(
+    time: Res<Time>,
+    scene_controller: Res<SceneController>,
+    mut frame_metadata: ResMut<FrameMetadata>,
+    mut captured_frames: Local<u32>,
+) {
+    frame_metadata.time = time.elapsed_secs();
+    frame_metadata.frame = match scene_controller.state {
+        SceneState::Render => {
+            *captured_frames += 1;
+            Some(*captured_frames - 1)
+        }
+        SceneState::BuildScene => None,
+    };
+}
+
+/// Extracting the metadata recorded while the frame was built
+fn frame_metadata_extract(mut commands: Commands, frame_metadata: Extract<Res<FrameMetadata>>) {
+    commands.insert_resource(frame_metadata.clone());
+}
+
 /// runs in render world after Render stage to send image from buffer via channel (receiver is in main world)
 fn receive_image_from_buffer(
     image_copiers: Res<ImageCopiers>,
     render_device: Res<RenderDevice>,
     sender: Res<RenderWorldSender>,
+    frame_metadata: Res<FrameMetadata>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
 ) {
     for image_copier in image_copiers.0.iter() {
         if !image_copier.enabled() {
             continue;
         }
+        // `ImageCopyDriver` skipped it, the buffer holds nothing of this frame
+        if gpu_images.get(&image_copier.src_image).is_none() {
+            continue;
+        }
 
         // Finally time to get our data back from the gpu.
         // First we get a buffer slice which represents a chunk of the buffer (which we
@@ -218,7 +444,14 @@ fn receive_image_from_buffer(
         r.recv().expect("Failed to receive the map_async message");
 
         // This could fail on app exit, if Main world clears resources (including receiver) while Render world still renders
-        let _ = sender.send(buffer_slice.get_mapped_range().to_vec());
+        let _ = sender.send(CapturedFrame {
+            data: buffer_slice.get_mapped_range().to_vec(),
+            metadata: frame_metadata.clone(),
+            target: image_copier.target,
+            width: image_copier.size.width,
+            height: image_copier.size.height,
+            format: image_copier.format,
+        });
 
         // We need to make sure all `BufferView`'s are dropped before we do what we're about
         // to do.
@@ -248,16 +481,17 @@ impl render_graph::Node for ImageCopyDriver {
         world: &World,
     ) -> Result<(), NodeRunError> {
         let image_copiers = world.get_resource::<ImageCopiers>().unwrap();
-        let gpu_images = world
-            .get_resource::<RenderAssets<bevy::render::texture::GpuImage>>()
-            .unwrap();
+        let gpu_images = world.get_resource::<RenderAssets<GpuImage>>().unwrap();
 
         for image_copier in image_copiers.iter() {
             if !image_copier.enabled() {
                 continue;
             }
 
-            let src_image = gpu_images.get(&image_copier.src_image).unwrap();
+            // Not uploaded yet in the frame a resized target was created
+            let Some(src_image) = gpu_images.get(&image_copier.src_image) else {
+                continue;
+            };
 
             let mut encoder = render_context
                 .render_device()
@@ -1,2 +1,8 @@
+mod capture_format;
+pub use capture_format::{CaptureConversion, CaptureFormat};
+mod captured_frame;
+pub use captured_frame::{CapturedFrame, FrameMetadata};
 mod image_copy;
-pub use image_copy::{ImageCopyPlugin, ImageToSave, MainWorldReceiver};
+pub use image_copy::{
+    CaptureTarget, ImageCopyPlugin, ImageToSave, MainWorldReceiver, PrimaryCaptureTarget,
+};
@@ -0,0 +1,550 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    platform::collections::HashMap,
+    prelude::*,
+};
+
+/// One glyph of a bitmap font
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub width: u32,
+    pub height: u32,
+    // Left edge of the bitmap from the pen position
+    pub x_offset: i32,
+    // Bottom edge of the bitmap above the baseline
+    pub y_offset: i32,
+    // Pixels the pen moves right after this glyph
+    pub advance: i32,
+    // Set pixels, row by row from the top
+    pub pixels: Vec<bool>,
+}
+
+impl Glyph {
+    pub fn pixel(&self, x: u32, y: u32) -> bool {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+/// Pixel font read from an X11 BDF or PCF file, drawn without any scaling or smoothing
+#[derive(Debug, Clone, Asset, TypePath)]
+pub struct BitmapFont {
+    pub glyphs: HashMap<char, Glyph>,
+    // Pixels above and below the baseline of a line of text
+    pub ascent: i32,
+    pub descent: i32,
+    // Drawn for characters the font has no glyph for
+    pub default_glyph: Option<char>,
+}
+
+impl BitmapFont {
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&self.default_glyph?))
+    }
+
+    /// Width of `text` in pixels, the sum of its advances
+    pub fn text_width(&self, text: &str) -> i32 {
+        text.chars()
+            .filter_map(|c| self.glyph(c))
+            .map(|glyph| glyph.advance)
+            .sum()
+    }
+
+    pub fn line_height(&self) -> i32 {
+        self.ascent + self.descent
+    }
+
+    /// Reads the text based Glyph Bitmap Distribution Format
+    pub fn from_bdf(source: &str) -> Result<BitmapFont, String> {
+        let mut font = BitmapFont {
+            glyphs: HashMap::default(),
+            ascent: 0,
+            descent: 0,
+            default_glyph: None,
+        };
+        // Fallbacks for fonts without ascent and descent properties
+        let mut bounding_box = [0i32; 4];
+        let mut default_char = None;
+
+        let mut lines = source.lines().map(str::trim);
+        while let Some(line) = lines.next() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => bounding_box = parse_numbers(words, line)?,
+                Some("FONT_ASCENT") => font.ascent = parse_numbers::<1>(words, line)?[0],
+                Some("FONT_DESCENT") => font.descent = parse_numbers::<1>(words, line)?[0],
+                Some("DEFAULT_CHAR") => default_char = Some(parse_numbers::<1>(words, line)?[0]),
+                Some("STARTCHAR") => {
+                    if let Some((code, glyph)) = read_bdf_glyph(&mut lines)?
+                        && let Some(c) = char::from_u32(code)
+                    {
+                        font.glyphs.insert(c, glyph);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if font.ascent == 0 && font.descent == 0 {
+            let [_, height, _, y_offset] = bounding_box;
+            font.ascent = height + y_offset;
+            font.descent = -y_offset;
+        }
+        font.default_glyph = default_char
+            .and_then(|code| char::from_u32(code as u32))
+            .filter(|c| font.glyphs.contains_key(c));
+        if font.glyphs.is_empty() {
+            return Err(String::from("font has no glyphs"));
+        }
+        Ok(font)
+    }
+
+    /// Reads the binary Portable Compiled Format X11 fonts are usually installed in
+    pub fn from_pcf(bytes: &[u8]) -> Result<BitmapFont, String> {
+        if bytes.get(..4) != Some(b"\x01fcp") {
+            return Err(String::from("not a PCF font"));
+        }
+        let mut header = PcfReader::new(bytes, 4, 0)?;
+        let table_count = header.u32()?;
+        let mut tables: HashMap<u32, usize> = HashMap::default();
+        for _ in 0..table_count {
+            let kind = header.u32()?;
+            let _format = header.u32()?;
+            let _size = header.u32()?;
+            let offset = header.u32()? as usize;
+            tables.insert(kind, offset);
+        }
+        let table = |kind| {
+            let offset = *tables
+                .get(&kind)
+                .ok_or_else(|| format!("font has no table {kind:#x}"))?;
+            let format = PcfReader::new(bytes, offset, 0)?.u32()?;
+            PcfReader::new(bytes, offset + 4, format).map(|reader| (reader, format))
+        };
+
+        // Glyph metrics, in glyph index order
+        let (mut metrics, format) = table(PCF_METRICS)?;
+        let metrics = if format & PCF_COMPRESSED_METRICS != 0 {
+            let count = metrics.u16()?;
+            (0..count)
+                .map(|_| {
+                    let mut value = || metrics.u8().map(|value| value as i32 - 0x80);
+                    Ok([value()?, value()?, value()?, value()?, value()?])
+                })
+                .collect::<Result<Vec<_>, String>>()?
+        } else {
+            let count = metrics.u32()?;
+            (0..count)
+                .map(|_| {
+                    let mut value = || metrics.i16().map(i32::from);
+                    let values = [value()?, value()?, value()?, value()?, value()?];
+                    // Attributes
+                    metrics.u16()?;
+                    Ok(values)
+                })
+                .collect::<Result<Vec<_>, String>>()?
+        };
+
+        let (mut bitmaps, format) = table(PCF_BITMAPS)?;
+        let glyph_count = bitmaps.u32()? as usize;
+        let offsets = (0..glyph_count)
+            .map(|_| bitmaps.u32().map(|offset| offset as usize))
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut sizes = [0; 4];
+        for size in sizes.iter_mut() {
+            *size = bitmaps.u32()? as usize;
+        }
+        let data = bitmaps.take(sizes[(format & 3) as usize])?;
+        let row_padding = 1 << (format & 3);
+        let scan_unit = 1 << ((format >> 4) & 3);
+        let msb_byte = format & PCF_BYTE_MASK != 0;
+        let msb_bit = format & PCF_BIT_MASK != 0;
+
+        let glyphs = metrics
+            .iter()
+            .zip(offsets)
+            .map(|(&[left, right, advance, ascent, descent], offset)| {
+                let width = (right - left).max(0) as u32;
+                let height = (ascent + descent).max(0) as u32;
+                let row_bytes = (width as usize).div_ceil(8).div_ceil(row_padding) * row_padding;
+                if offset + height as usize * row_bytes > data.len() {
+                    return Err(format!(
+                        "glyph bitmap {width}x{height} goes past the end of the bitmaps"
+                    ));
+                }
+                let mut pixels = Vec::with_capacity(width as usize * height as usize);
+                for y in 0..height as usize {
+                    for x in 0..width as usize {
+                        let mut byte = x / 8;
+                        // Bytes are stored swapped within a scan unit when the byte and
+                        // bit order differ
+                        if msb_byte != msb_bit && scan_unit > 1 {
+                            byte = byte - byte % scan_unit + scan_unit - 1 - byte % scan_unit;
+                        }
+                        let value = data
+                            .get(offset + y * row_bytes + byte)
+                            .copied()
+                            .unwrap_or(0);
+                        let mask = match msb_bit {
+                            true => 0x80 >> (x % 8),
+                            false => 1 << (x % 8),
+                        };
+                        pixels.push(value & mask != 0);
+                    }
+                }
+                Ok(Glyph {
+                    width,
+                    height,
+                    x_offset: left,
+                    y_offset: -descent,
+                    advance,
+                    pixels,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // Maps two byte character codes to glyph indices
+        let (mut encodings, _) = table(PCF_BDF_ENCODINGS)?;
+        let min_byte2 = encodings.i16()? as i32;
+        let max_byte2 = encodings.i16()? as i32;
+        let min_byte1 = encodings.i16()? as i32;
+        let max_byte1 = encodings.i16()? as i32;
+        let default_char = encodings.u16()? as u32;
+        let mut font = BitmapFont {
+            glyphs: HashMap::default(),
+            ascent: 0,
+            descent: 0,
+            default_glyph: None,
+        };
+        for byte1 in min_byte1..=max_byte1 {
+            for byte2 in min_byte2..=max_byte2 {
+                let index = encodings.u16()?;
+                let code = (byte1 << 8 | byte2) as u32;
+                if let (Some(glyph), Some(c)) = (glyphs.get(index as usize), char::from_u32(code)) {
+                    font.glyphs.insert(c, glyph.clone());
+                }
+            }
+        }
+        font.default_glyph = char::from_u32(default_char).filter(|c| font.glyphs.contains_key(c));
+
+        let (mut accelerators, _) =
+            table(PCF_BDF_ACCELERATORS).or_else(|_| table(PCF_ACCELERATORS))?;
+        // Flags
+        accelerators.take(8)?;
+        font.ascent = accelerators.i32()?;
+        font.descent = accelerators.i32()?;
+        if font.glyphs.is_empty() {
+            return Err(String::from("font has no glyphs"));
+        }
+        Ok(font)
+    }
+}
+
+fn parse_numbers<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+    line: &str,
+) -> Result<[i32; N], String> {
+    let mut numbers = [0; N];
+    for number in numbers.iter_mut() {
+        *number = words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| format!("invalid line `{line}`"))?;
+    }
+    Ok(numbers)
+}
+
+/// Reads the glyph following `STARTCHAR`, `None` for glyphs without an encoding
+fn read_bdf_glyph<'a>(
+    lines: &mut impl Iterator<Item = &'a str>,
+) -> Result<Option<(u32, Glyph)>, String> {
+    let mut code = -1;
+    let mut advance = 0;
+    let mut bounding_box = [0i32; 4];
+    for line in lines.by_ref() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("ENCODING") => code = parse_numbers::<1>(words, line)?[0],
+            Some("DWIDTH") => advance = parse_numbers::<1>(words, line)?[0],
+            Some("BBX") => bounding_box = parse_numbers(words, line)?,
+            Some("BITMAP") => break,
+            Some("ENDCHAR") => return Ok(None),
+            _ => {}
+        }
+    }
+
+    let [width, height, x_offset, y_offset] = bounding_box;
+    let (width, height) = (width.max(0) as u32, height.max(0) as u32);
+    let pixel_count = width
+        .checked_mul(height)
+        .ok_or_else(|| format!("glyph bounding box {width}x{height} is too large"))?;
+    // Rows are hex, padded to whole bytes with the leftmost pixel in the top bit
+    let row_length = (width as usize).div_ceil(8) * 2;
+    let mut pixels = Vec::new();
+    for line in lines.by_ref().take_while(|line| *line != "ENDCHAR") {
+        if !line.is_ascii() || line.len() < row_length {
+            return Err(format!("invalid bitmap row `{line}`"));
+        }
+        let row = (0..row_length / 2)
+            .map(|byte| u8::from_str_radix(&line[byte * 2..byte * 2 + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid bitmap row `{line}`"))?;
+        pixels.extend((0..width as usize).map(|x| row[x / 8] & (0x80 >> (x % 8)) != 0));
+    }
+    if pixels.len() != pixel_count as usize {
+        return Err(format!(
+            "glyph has {} bitmap rows, its bounding box {height}",
+            pixels.len() / width.max(1) as usize
+        ));
+    }
+
+    if code < 0 {
+        return Ok(None);
+    }
+    Ok(Some((
+        code as u32,
+        Glyph {
+            width,
+            height,
+            x_offset,
+            y_offset,
+            advance,
+            pixels,
+        },
+    )))
+}
+
+const PCF_ACCELERATORS: u32 = 1 << 1;
+const PCF_METRICS: u32 = 1 << 2;
+const PCF_BITMAPS: u32 = 1 << 3;
+const PCF_BDF_ENCODINGS: u32 = 1 << 5;
+const PCF_BDF_ACCELERATORS: u32 = 1 << 8;
+const PCF_COMPRESSED_METRICS: u32 = 0x100;
+const PCF_BYTE_MASK: u32 = 1 << 2;
+const PCF_BIT_MASK: u32 = 1 << 3;
+
+/// Reads the values of a PCF table, in the byte order its format sets
+struct PcfReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl<'a> PcfReader<'a> {
+    fn new(bytes: &'a [u8], position: usize, format: u32) -> Result<PcfReader<'a>, String> {
+        if position > bytes.len() {
+            return Err(String::from("table outside of the file"));
+        }
+        Ok(PcfReader {
+            bytes,
+            position,
+            big_endian: format & PCF_BYTE_MASK != 0,
+        })
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or("unexpected end of file")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        self.u16().map(|value| value as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        self.u32().map(|value| value as i32)
+    }
+}
+
+/// Loads `.bdf` and `.pcf` files as [`BitmapFont`] assets
+#[derive(Debug, Default, TypePath)]
+pub struct BitmapFontLoader;
+
+impl AssetLoader for BitmapFontLoader {
+    type Asset = BitmapFont;
+    type Settings = ();
+    type Error = String;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<BitmapFont, String> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|e| e.to_string())?;
+        let path = load_context.path().path().to_owned();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("pcf") => BitmapFont::from_pcf(&bytes),
+            _ => BitmapFont::from_bdf(&String::from_utf8_lossy(&bytes)),
+        }
+        .map_err(|e| format!("{path:?}: {e}"))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bdf", "pcf"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BDF: &str = "STARTFONT 2.1
+FONT -test-small
+FONTBOUNDINGBOX 4 3 0 -1
+STARTPROPERTIES 3
+FONT_ASCENT 2
+FONT_DESCENT 1
+DEFAULT_CHAR 63
+ENDPROPERTIES
+CHARS 3
+STARTCHAR A
+ENCODING 65
+DWIDTH 4 0
+BBX 3 2 0 0
+BITMAP
+A0
+40
+ENDCHAR
+STARTCHAR question
+ENCODING 63
+DWIDTH 2 0
+BBX 1 1 0 -1
+BITMAP
+80
+ENDCHAR
+STARTCHAR unencoded
+ENCODING -1
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+ENDFONT
+";
+
+    fn rows(glyph: &Glyph) -> Vec<Vec<bool>> {
+        glyph
+            .pixels
+            .chunks(glyph.width as usize)
+            .map(<[bool]>::to_vec)
+            .collect()
+    }
+
+    #[test]
+    fn reads_bdf() {
+        let font = BitmapFont::from_bdf(BDF).unwrap();
+        assert_eq!((font.ascent, font.descent), (2, 1));
+        assert_eq!(font.glyphs.len(), 2);
+        let glyph = font.glyph('A').unwrap();
+        assert_eq!((glyph.width, glyph.height, glyph.advance), (3, 2, 4));
+        assert_eq!(
+            rows(glyph),
+            [vec![true, false, true], vec![false, true, false]]
+        );
+        // Missing characters fall back to DEFAULT_CHAR
+        assert_eq!(font.glyph('z').unwrap().y_offset, -1);
+        assert_eq!(font.text_width("AAz"), 10);
+    }
+
+    #[test]
+    fn rejects_broken_bdf_glyphs() {
+        for (from, to) in [
+            ("A0\n40", "A0\né0"),
+            ("BBX 3 2 0 0", "BBX 12 2 0 0"),
+            ("BBX 3 2 0 0", "BBX 3 3 0 0"),
+            ("BBX 3 2 0 0", "BBX 70000 70000 0 0"),
+        ] {
+            let font = BDF.replacen(from, to, 1);
+            assert!(BitmapFont::from_bdf(&font).is_err(), "{to}");
+        }
+    }
+
+    /// Little endian PCF with uncompressed metrics and byte padded, LSB first rows
+    fn pcf() -> Vec<u8> {
+        let mut metrics = vec![0, 0, 0, 0, 1, 0, 0, 0];
+        for value in [0i16, 3, 4, 2, 0] {
+            metrics.extend(value.to_le_bytes());
+        }
+        metrics.extend([0, 0]);
+        let mut bitmaps = vec![0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        for size in [2u32, 2, 4, 4] {
+            bitmaps.extend(size.to_le_bytes());
+        }
+        bitmaps.extend([0b101, 0b010]);
+        let mut encodings = vec![0, 0, 0, 0];
+        for value in [65i16, 65, 0, 0, 65, 0] {
+            encodings.extend(value.to_le_bytes());
+        }
+        let mut accelerators = vec![0; 12];
+        accelerators.extend(2i32.to_le_bytes());
+        accelerators.extend(1i32.to_le_bytes());
+
+        let tables = [
+            (PCF_ACCELERATORS, accelerators),
+            (PCF_METRICS, metrics),
+            (PCF_BITMAPS, bitmaps),
+            (PCF_BDF_ENCODINGS, encodings),
+        ];
+        let mut bytes = b"\x01fcp".to_vec();
+        bytes.extend((tables.len() as u32).to_le_bytes());
+        let mut offset = 8 + tables.len() * 16;
+        for (kind, table) in tables.iter() {
+            for value in [*kind, 0, table.len() as u32, offset as u32] {
+                bytes.extend(value.to_le_bytes());
+            }
+            offset += table.len();
+        }
+        for (_, table) in tables.iter() {
+            bytes.extend(table);
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_pcf() {
+        let font = BitmapFont::from_pcf(&pcf()).unwrap();
+        assert_eq!((font.ascent, font.descent), (2, 1));
+        assert_eq!(font.default_glyph, Some('A'));
+        let glyph = font.glyph('A').unwrap();
+        assert_eq!((glyph.width, glyph.height, glyph.advance), (3, 2, 4));
+        assert_eq!(
+            rows(glyph),
+            [vec![true, false, true], vec![false, true, false]]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_pcf() {
+        let bytes = pcf();
+        assert!(BitmapFont::from_pcf(&bytes[..bytes.len() - 3]).is_err());
+    }
+}
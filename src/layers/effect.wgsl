@@ -0,0 +1,45 @@
+// Shared by every effect layer, the effect's own code is appended and has to define
+//
+//     fn main_image(uv: vec2<f32>, pixel: vec2<f32>) -> vec4<f32>
+//
+// `uv` is 0..1 across the region from the top left, `pixel` the same in panel pixels.
+
+struct EffectUniforms {
+    // Seconds of animation
+    time: f32,
+    // Smoothed audio level in 0..1
+    level: f32,
+    // Size of the region in panel pixels
+    resolution: vec2<f32>,
+    // Smoothed audio band magnitudes, lowest band first
+    bands: array<vec4<f32>, 2>,
+    // Morph weights of the shape keys the layer lists as params
+    params: array<vec4<f32>, 2>,
+    // Bottom left in xy, top right in zw, in layer space
+    clip: vec4<f32>,
+    opacity: f32,
+}
+
+@group(2) @binding(0) var<uniform> effect: EffectUniforms;
+
+fn audio_band(index: u32) -> f32 {
+    return effect.bands[index / 4u][index % 4u];
+}
+
+fn param(index: u32) -> f32 {
+    return effect.params[index / 4u][index % 4u];
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Layers never draw outside their panel region
+    let position = in.world_position.xy;
+    if any(position < effect.clip.xy) || any(position > effect.clip.zw) {
+        discard;
+    }
+
+    let color = main_image(in.uv, in.uv * effect.resolution);
+    let alpha = clamp(color.a, 0.0, 1.0) * effect.opacity;
+    // Premultiplied, like the other layers
+    return vec4<f32>(color.rgb * alpha, alpha);
+}
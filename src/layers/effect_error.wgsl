@@ -0,0 +1,5 @@
+// Shown instead of an effect whose shader does not compile, scrolling red and black stripes
+fn main_image(uv: vec2<f32>, pixel: vec2<f32>) -> vec4<f32> {
+    let stripe = fract((pixel.x + pixel.y - effect.time * 8.0) / 8.0) < 0.5;
+    return select(vec4<f32>(0.0, 0.0, 0.0, 1.0), vec4<f32>(1.0, 0.0, 0.0, 1.0), stripe);
+}
@@ -0,0 +1,245 @@
+use bevy::{asset::io::file::FileAssetReader, prelude::*};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use serde::Deserialize;
+use std::path::{Component, PathBuf};
+
+use crate::{
+    audio::AudioLevels,
+    face::MorphControls,
+    layers::{BlendMode, EffectMaterial, effect_material::EffectUniforms},
+    output::PanelLayout,
+    scene::WatchedFile,
+};
+
+/// Uniforms, helpers and the fragment entry point every effect is appended to
+const EFFECT_TEMPLATE: &str = include_str!("effect.wgsl");
+/// Effect code drawn while an effect does not compile
+const ERROR_PATTERN: &str = include_str!("effect_error.wgsl");
+/// Morph weights an effect can receive as params
+const MAX_PARAMS: usize = 8;
+
+fn default_one() -> f32 {
+    1.0
+}
+
+/// Shadertoy style WGSL fragment shader drawn over a panel region
+#[derive(Debug, Clone, Deserialize)]
+pub struct EffectLayerConfig {
+    // WGSL file defining `main_image` relative to the `assets` folder, reloaded whenever it
+    // changes
+    pub shader: PathBuf,
+    // Shape keys, `target` or `node/target`, whose weights are passed as `param(0)` and up
+    #[serde(default)]
+    pub params: Vec<String>,
+    #[serde(default)]
+    pub blend: BlendMode,
+    #[serde(default = "default_one")]
+    pub opacity: f32,
+}
+
+impl EffectLayerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        // Only files under the asset root can be loaded
+        if !self
+            .shader
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!(
+                "effect shader {:?} must be a path inside the assets folder",
+                self.shader
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Effect layer of a panel region
+#[derive(Debug, Component)]
+pub struct EffectLayer {
+    config: EffectLayerConfig,
+    // Effect file under the asset root
+    file: WatchedFile,
+    // Compiled from the effect file, replaced in place when the file changes
+    shader: Handle<Shader>,
+    error_shader: Handle<Shader>,
+    resolution: Vec2,
+}
+
+impl EffectLayer {
+    /// Reads and validates the effect file, the shader the material should use afterwards
+    fn reload(&mut self, shaders: &mut Assets<Shader>) -> Handle<Shader> {
+        let path = self.file.path();
+        let code = match std::fs::read_to_string(path) {
+            Ok(code) => code,
+            Err(e) => {
+                error!("Failed to read effect {path:?}: {e}");
+                return self.error_shader.clone();
+            }
+        };
+        if let Err(e) = validate_effect(&code) {
+            error!("Effect {path:?} does not compile, showing the error pattern\n{e}");
+            return self.error_shader.clone();
+        }
+
+        info!("Loaded effect {path:?}");
+        if let Some(shader) = shaders.get_mut(&self.shader) {
+            *shader = compose_effect(&code, &path.to_string_lossy());
+        }
+        self.shader.clone()
+    }
+}
+
+/// Shader of an effect, ready for the 2D mesh pipeline
+fn compose_effect(code: &str, path: &str) -> Shader {
+    Shader::from_wgsl(
+        format!(
+            "#import bevy_sprite::mesh2d_vertex_output::VertexOutput\n{EFFECT_TEMPLATE}\n{code}"
+        ),
+        path.to_string(),
+    )
+}
+
+/// Compiles an effect on its own, so errors show up here instead of failing the pipeline
+fn validate_effect(code: &str) -> Result<(), String> {
+    // Stands in for the import, which naga can not resolve itself
+    let source = format!(
+        "struct VertexOutput {{
+            @builtin(position) position: vec4<f32>,
+            @location(0) world_position: vec4<f32>,
+            @location(1) world_normal: vec3<f32>,
+            @location(2) uv: vec2<f32>,
+        }}
+        {EFFECT_TEMPLATE}\n{code}"
+    );
+    let module = naga::front::wgsl::parse_str(&source).map_err(|e| e.emit_to_string(&source))?;
+    Validator::new(ValidationFlags::all(), Capabilities::default())
+        .validate(&module)
+        .map_err(|e| e.emit_to_string(&source))?;
+    Ok(())
+}
+
+pub(super) fn spawn_effect_layers(
+    mut commands: Commands,
+    layout: Res<PanelLayout>,
+    mut shaders: ResMut<Assets<Shader>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<EffectMaterial>>,
+) {
+    let error_shader = shaders.add(compose_effect(ERROR_PATTERN, "effect_error.wgsl"));
+    for (region_index, region) in layout.regions.iter().enumerate() {
+        for (layer_index, config) in region.effects.iter().enumerate() {
+            let [_, _, width, height] = region.rect;
+            let bounds = region.layer_bounds(layout.size.height);
+            let mut layer = EffectLayer {
+                config: config.clone(),
+                file: WatchedFile::new(
+                    FileAssetReader::get_base_path()
+                        .join("assets")
+                        .join(&config.shader),
+                ),
+                // Placeholder until the file is read
+                shader: shaders.add(compose_effect(ERROR_PATTERN, "effect_error.wgsl")),
+                error_shader: error_shader.clone(),
+                resolution: Vec2::new(width as f32, height as f32),
+            };
+            let material = EffectMaterial {
+                uniforms: EffectUniforms {
+                    resolution: layer.resolution,
+                    clip: Vec4::new(bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y),
+                    opacity: config.opacity,
+                    ..default()
+                },
+                shader: layer.reload(&mut shaders),
+                blend: config.blend,
+            };
+            // Under the sprites of the same region
+            let depth = region_index as f32 + (layer_index + 1) as f32 * 0.001;
+            commands.spawn((
+                Name::new(format!("{}/effect{layer_index}", region.name)),
+                Mesh2d(meshes.add(Rectangle::from_size(layer.resolution))),
+                MeshMaterial2d(materials.add(material)),
+                Transform::from_translation(region.layer_center(layout.size.height).extend(depth)),
+                layer,
+            ));
+        }
+    }
+}
+
+/// Reloads effects whose file changed, switching to the error pattern and back as needed
+pub(super) fn reload_effect_layers(
+    mut layers: Query<(&mut EffectLayer, &MeshMaterial2d<EffectMaterial>)>,
+    mut shaders: ResMut<Assets<Shader>>,
+    mut materials: ResMut<Assets<EffectMaterial>>,
+) {
+    for (mut layer, material) in layers.iter_mut() {
+        if !layer.file.changed() {
+            continue;
+        }
+        let shader = layer.reload(&mut shaders);
+        if let Some(material) = materials.get_mut(&material.0) {
+            material.shader = shader;
+        }
+    }
+}
+
+pub(super) fn update_effect_uniforms(
+    time: Res<Time>,
+    audio_levels: Option<Res<AudioLevels>>,
+    morphs: Query<(&MorphControls, Option<&Name>)>,
+    layers: Query<(&EffectLayer, &MeshMaterial2d<EffectMaterial>)>,
+    mut materials: ResMut<Assets<EffectMaterial>>,
+) {
+    let audio_levels = audio_levels.as_deref().cloned().unwrap_or_default();
+    for (layer, material) in layers.iter() {
+        let Some(material) = materials.get_mut(&material.0) else {
+            continue;
+        };
+
+        let mut params = [0.0; MAX_PARAMS];
+        for (param, name) in params.iter_mut().zip(layer.config.params.iter()) {
+            *param = MorphControls::find_weight(morphs.iter(), name);
+        }
+
+        let uniforms = &mut material.uniforms;
+        uniforms.time = time.elapsed_secs();
+        uniforms.level = audio_levels.level;
+        uniforms.bands = [
+            Vec4::from_slice(&audio_levels.bands[..4]),
+            Vec4::from_slice(&audio_levels.bands[4..]),
+        ];
+        uniforms.params = [
+            Vec4::from_slice(&params[..4]),
+            Vec4::from_slice(&params[4..]),
+        ];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(shader: &str) -> EffectLayerConfig {
+        serde_json::from_value(serde_json::json!({ "shader": shader })).unwrap()
+    }
+
+    #[test]
+    fn shaders_stay_inside_the_assets() {
+        assert!(config("effects/plasma.wgsl").validate().is_ok());
+        assert!(config("./effects/plasma.wgsl").validate().is_ok());
+        for shader in ["/etc/passwd", "../secret.wgsl", "effects/../../secret.wgsl"] {
+            assert!(config(shader).validate().is_err(), "{shader} was accepted");
+        }
+    }
+
+    #[test]
+    fn effects_are_validated_before_use() {
+        let code = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/effects/plasma.wgsl"
+        ))
+        .unwrap();
+        assert_eq!(validate_effect(&code), Ok(()));
+        assert!(validate_effect("fn main_image(").is_err());
+    }
+}
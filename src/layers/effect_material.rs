@@ -0,0 +1,75 @@
+use bevy::{
+    mesh::MeshVertexBufferLayoutRef,
+    prelude::*,
+    render::render_resource::{
+        AsBindGroup, RenderPipelineDescriptor, ShaderType, SpecializedMeshPipelineError,
+    },
+    sprite_render::{AlphaMode2d, Material2d, Material2dKey},
+};
+
+use crate::layers::BlendMode;
+
+/// Label of every effect pipeline, [`ReadinessGate`](crate::scene::ReadinessGate) lets
+/// them fail
+pub const EFFECT_PIPELINE_LABEL: &str = "effect_layer_pipeline";
+
+/// Standard inputs of every effect shader, see `effect.wgsl`
+#[derive(Debug, Clone, Default, ShaderType)]
+pub struct EffectUniforms {
+    pub time: f32,
+    pub level: f32,
+    pub resolution: Vec2,
+    pub bands: [Vec4; 2],
+    pub params: [Vec4; 2],
+    pub clip: Vec4,
+    pub opacity: f32,
+}
+
+/// Region sized quad shaded by a user supplied WGSL fragment shader
+#[derive(Debug, Clone, Asset, TypePath, AsBindGroup)]
+#[bind_group_data(EffectMaterialKey)]
+pub struct EffectMaterial {
+    #[uniform(0)]
+    pub uniforms: EffectUniforms,
+    // Composed from `effect.wgsl` and the effect's code, differs per layer
+    pub shader: Handle<Shader>,
+    pub blend: BlendMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EffectMaterialKey {
+    shader: Handle<Shader>,
+    blend: BlendMode,
+}
+
+impl From<&EffectMaterial> for EffectMaterialKey {
+    fn from(material: &EffectMaterial) -> Self {
+        EffectMaterialKey {
+            shader: material.shader.clone(),
+            blend: material.blend,
+        }
+    }
+}
+
+impl Material2d for EffectMaterial {
+    fn alpha_mode(&self) -> AlphaMode2d {
+        // Sorted back to front with the other layers
+        AlphaMode2d::Blend
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.label = Some(EFFECT_PIPELINE_LABEL.into());
+        // The fragment shader is chosen per material instead of per material type
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader = key.bind_group_data.shader.clone();
+            if let Some(target) = fragment.targets.first_mut().and_then(Option::as_mut) {
+                target.blend = key.bind_group_data.blend.blend_state();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+struct LayerParams {
+    tint: vec4<f32>,
+    // Offset in xy, scale in zw of the texture coordinates
+    uv_rect: vec4<f32>,
+    // Bottom left in xy, top right in zw, in layer space
+    clip: vec4<f32>,
+    opacity: f32,
+}
+
+@group(2) @binding(0) var<uniform> params: LayerParams;
+@group(2) @binding(1) var layer_texture: texture_2d<f32>;
+@group(2) @binding(2) var layer_sampler: sampler;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Layers never draw outside their panel region
+    let position = in.world_position.xy;
+    if any(position < params.clip.xy) || any(position > params.clip.zw) {
+        discard;
+    }
+
+    let uv = params.uv_rect.xy + in.uv * params.uv_rect.zw;
+    let color = textureSample(layer_texture, layer_sampler, uv) * params.tint;
+    let alpha = color.a * params.opacity;
+    // Premultiplied, every blend mode is set up for it
+    return vec4<f32>(color.rgb * alpha, alpha);
+}
@@ -0,0 +1,108 @@
+use bevy::{
+    asset::embedded_asset, camera::ScalingMode, core_pipeline::tonemapping::Tonemapping,
+    prelude::*, render::extract_component::ExtractComponent, sprite_render::Material2dPlugin,
+    time::common_conditions::on_real_timer,
+};
+
+use crate::{
+    layers::{
+        EffectMaterial, LayerMaterial,
+        bitmap_font::{BitmapFont, BitmapFontLoader},
+        effect_layer::{
+            EffectLayer, reload_effect_layers, spawn_effect_layers, update_effect_uniforms,
+        },
+        sprite_layer::{
+            SpriteLayer, animate_sprite_layers, prepare_sprite_layers, spawn_sprite_layers,
+        },
+        text_layer::{TextLayer, queue_text_messages, spawn_text_layers, update_text_layers},
+    },
+    output::PanelLayout,
+    scene::{RELOAD_INTERVAL, SceneController},
+};
+
+/// 2D camera drawing the layers over the 3D render.
+///
+/// It renders into the capture target after the face cameras without clearing it, so
+/// `ImageCopyDriver` reads back the composited image. Layer space is panel pixels with
+/// the origin at the bottom left of the panels.
+#[derive(Debug, Clone, Copy, Component, ExtractComponent)]
+pub struct LayerCamera;
+
+/// Composites 2D layers, defined per region of the [`PanelLayout`], over the render
+pub struct LayersPlugin;
+
+impl Plugin for LayersPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "layer.wgsl");
+        app.add_plugins(Material2dPlugin::<LayerMaterial>::default())
+            .add_plugins(Material2dPlugin::<EffectMaterial>::default())
+            .init_asset::<BitmapFont>()
+            .init_asset_loader::<BitmapFontLoader>()
+            .add_systems(
+                Update,
+                (
+                    // Once at startup and again whenever the layout is reloaded
+                    (
+                        despawn_layers,
+                        (spawn_effect_layers, spawn_sprite_layers, spawn_text_layers),
+                    )
+                        .chain()
+                        .run_if(resource_changed::<PanelLayout>),
+                    spawn_layer_camera,
+                    (prepare_sprite_layers, animate_sprite_layers).chain(),
+                    (queue_text_messages, update_text_layers).chain(),
+                    (
+                        reload_effect_layers.run_if(on_real_timer(RELOAD_INTERVAL)),
+                        update_effect_uniforms,
+                    )
+                        .chain(),
+                ),
+            );
+    }
+}
+
+type LayerFilter = Or<(With<EffectLayer>, With<SpriteLayer>, With<TextLayer>)>;
+
+/// Layers of the previous layout make way for the ones of the reloaded layout
+fn despawn_layers(mut commands: Commands, layers: Query<Entity, LayerFilter>) {
+    for entity in layers.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn spawn_layer_camera(
+    mut commands: Commands,
+    cameras: Query<(), With<LayerCamera>>,
+    scene_controller: Res<SceneController>,
+    layout: Res<PanelLayout>,
+) {
+    if !cameras.is_empty() {
+        return;
+    }
+    let Some(render_target) = scene_controller.render_target.clone() else {
+        return;
+    };
+
+    let size = Vec2::new(layout.size.width as f32, layout.size.height as f32);
+    commands.spawn((
+        LayerCamera,
+        Camera2d,
+        Camera {
+            // After the face cameras, on top of what they rendered
+            order: 1,
+            clear_color: ClearColorConfig::None,
+            ..default()
+        },
+        render_target,
+        Tonemapping::None,
+        // The whole target shows the whole panel layout, like `PanelFrame::from_rgba` scales it
+        Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::Fixed {
+                width: size.x,
+                height: size.y,
+            },
+            ..OrthographicProjection::default_2d()
+        }),
+        Transform::from_translation((size / 2.0).extend(0.0)),
+    ));
+}
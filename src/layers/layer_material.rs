@@ -0,0 +1,146 @@
+use bevy::{
+    asset::{AssetPath, embedded_path},
+    mesh::MeshVertexBufferLayoutRef,
+    prelude::*,
+    render::render_resource::{
+        AsBindGroup, BlendComponent, BlendFactor, BlendOperation, BlendState,
+        RenderPipelineDescriptor, ShaderType, SpecializedMeshPipelineError,
+    },
+    shader::ShaderRef,
+    sprite_render::{AlphaMode2d, Material2d, Material2dKey},
+};
+use serde::Deserialize;
+
+/// How a layer is combined with what is below it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    // Alpha blended over
+    #[default]
+    Normal,
+    // Brightens, black is transparent
+    Add,
+    // Darkens, white is transparent
+    Multiply,
+    // Brightens softer than add, black is transparent
+    Screen,
+    // Overwrites, ignoring alpha
+    Replace,
+}
+
+impl BlendMode {
+    /// Blend state for the premultiplied color the layer shader outputs
+    pub(super) fn blend_state(self) -> Option<BlendState> {
+        let color = |src_factor, dst_factor| BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: BlendOperation::Add,
+        };
+        let alpha = color(BlendFactor::One, BlendFactor::OneMinusSrcAlpha);
+        match self {
+            BlendMode::Normal => Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            BlendMode::Add => Some(BlendState {
+                color: color(BlendFactor::One, BlendFactor::One),
+                alpha,
+            }),
+            BlendMode::Multiply => Some(BlendState {
+                color: color(BlendFactor::Dst, BlendFactor::OneMinusSrcAlpha),
+                alpha,
+            }),
+            BlendMode::Screen => Some(BlendState {
+                color: color(BlendFactor::OneMinusDst, BlendFactor::One),
+                alpha,
+            }),
+            BlendMode::Replace => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, ShaderType)]
+pub struct LayerParams {
+    pub tint: LinearRgba,
+    // Offset in xy, scale in zw of the texture coordinates
+    pub uv_rect: Vec4,
+    // Bottom left in xy, top right in zw, in layer space
+    pub clip: Vec4,
+    pub opacity: f32,
+}
+
+/// Textured quad composited over the render with a [`BlendMode`]
+#[derive(Debug, Clone, Asset, TypePath, AsBindGroup)]
+#[bind_group_data(LayerMaterialKey)]
+pub struct LayerMaterial {
+    #[uniform(0)]
+    pub params: LayerParams,
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Handle<Image>,
+    pub blend: BlendMode,
+}
+
+impl LayerMaterial {
+    pub fn new(
+        texture: Handle<Image>,
+        clip: Rect,
+        blend: BlendMode,
+        opacity: f32,
+    ) -> LayerMaterial {
+        LayerMaterial {
+            params: LayerParams {
+                tint: LinearRgba::WHITE,
+                uv_rect: Vec4::new(0.0, 0.0, 1.0, 1.0),
+                clip: Vec4::new(clip.min.x, clip.min.y, clip.max.x, clip.max.y),
+                opacity,
+            },
+            texture,
+            blend,
+        }
+    }
+
+    /// Shows only `rect` of the texture, in normalized texture coordinates
+    pub fn set_uv_rect(&mut self, rect: Rect) {
+        self.params.uv_rect = Vec4::new(rect.min.x, rect.min.y, rect.width(), rect.height());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerMaterialKey {
+    blend: BlendMode,
+}
+
+impl From<&LayerMaterial> for LayerMaterialKey {
+    fn from(material: &LayerMaterial) -> Self {
+        LayerMaterialKey {
+            blend: material.blend,
+        }
+    }
+}
+
+impl Material2d for LayerMaterial {
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Path(
+            AssetPath::from_path_buf(embedded_path!("layer.wgsl")).with_source("embedded"),
+        )
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        // Sorted back to front with the other layers
+        AlphaMode2d::Blend
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(target) = descriptor
+            .fragment
+            .as_mut()
+            .and_then(|fragment| fragment.targets.first_mut())
+            .and_then(Option::as_mut)
+        {
+            target.blend = key.bind_group_data.blend.blend_state();
+        }
+        Ok(())
+    }
+}
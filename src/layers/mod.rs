@@ -0,0 +1,13 @@
+mod bitmap_font;
+mod effect_layer;
+pub use effect_layer::EffectLayerConfig;
+mod effect_material;
+pub use effect_material::{EFFECT_PIPELINE_LABEL, EffectMaterial};
+mod layer_compositor;
+pub use layer_compositor::{LayerCamera, LayersPlugin};
+mod layer_material;
+pub use layer_material::{BlendMode, LayerMaterial};
+mod sprite_layer;
+pub use sprite_layer::SpriteLayerConfig;
+mod text_layer;
+pub use text_layer::TextLayerConfig;
@@ -0,0 +1,320 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    layers::{BlendMode, LayerMaterial},
+    output::PanelLayout,
+    scene::ReadinessGate,
+};
+
+// Largest sprite sheet frame, the texture size every adapter supports
+const MAX_FRAME_SIZE: u32 = 8192;
+
+fn default_frame_duration() -> f32 {
+    0.1
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Animated sprite drawn in a panel region, from a sprite sheet or one PNG per frame
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpriteLayerConfig {
+    // Sprite sheet relative to the `assets` folder, frames left to right then top to bottom
+    pub sheet: Option<String>,
+    // Size of one frame of the sheet in pixels, the whole image when not set
+    pub frame_size: Option<[u32; 2]>,
+    // Frames used from the sheet, every frame when not set
+    pub frame_count: Option<u32>,
+    // One image per frame instead of a sheet
+    #[serde(default)]
+    pub frames: Vec<String>,
+    // Seconds every frame is shown
+    #[serde(default = "default_frame_duration")]
+    pub frame_duration: f32,
+    // Seconds per frame overriding `frame_duration`, the last entry repeats
+    #[serde(default)]
+    pub frame_durations: Vec<f32>,
+    // Stops on the last frame otherwise
+    #[serde(default = "default_true")]
+    pub looping: bool,
+    // Panel pixels from the center of the region, y down
+    #[serde(default)]
+    pub offset: [f32; 2],
+    // Sprite pixels per panel pixel
+    #[serde(default = "default_one")]
+    pub scale: f32,
+    #[serde(default)]
+    pub blend: BlendMode,
+    #[serde(default = "default_one")]
+    pub opacity: f32,
+}
+
+impl SpriteLayerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.frame_count == Some(0) {
+            return Err(String::from("needs a frame_count of at least 1"));
+        }
+        if let Some([width, height]) = self.frame_size
+            && [width, height]
+                .iter()
+                .any(|length| !(1..=MAX_FRAME_SIZE).contains(length))
+        {
+            return Err(format!(
+                "frame_size {width}x{height} must be within 1..{MAX_FRAME_SIZE}"
+            ));
+        }
+        Ok(())
+    }
+
+    fn frame_duration(&self, frame: usize) -> f32 {
+        self.frame_durations
+            .get(frame)
+            .or(self.frame_durations.last())
+            .copied()
+            .unwrap_or(self.frame_duration)
+            // A zero duration would never let the animation advance past it
+            .max(0.001)
+    }
+}
+
+/// One frame, an image and the part of it that is shown
+#[derive(Debug, Clone)]
+struct SpriteFrame {
+    image: usize,
+    uv: Rect,
+}
+
+/// Sprite layer of a panel region, drawn once its images are loaded
+#[derive(Debug, Component)]
+pub struct SpriteLayer {
+    config: SpriteLayerConfig,
+    images: Vec<Handle<Image>>,
+    // Center and bounds of the region in layer space
+    center: Vec2,
+    bounds: Rect,
+    depth: f32,
+    frames: Vec<SpriteFrame>,
+    frame: usize,
+    elapsed: f32,
+}
+
+pub(super) fn spawn_sprite_layers(
+    mut commands: Commands,
+    layout: Res<PanelLayout>,
+    asset_server: Res<AssetServer>,
+    mut readiness_gate: ResMut<ReadinessGate>,
+) {
+    for (region_index, region) in layout.regions.iter().enumerate() {
+        for (layer_index, config) in region.sprites.iter().enumerate() {
+            let paths = match &config.sheet {
+                Some(sheet) => vec![sheet.clone()],
+                None => config.frames.clone(),
+            };
+            if paths.is_empty() {
+                warn!(
+                    "Sprite layer {layer_index} of region `{}` has neither a sheet nor frames",
+                    region.name
+                );
+                continue;
+            }
+
+            let images = paths
+                .iter()
+                .map(|path| {
+                    let image: Handle<Image> = asset_server.load(path);
+                    readiness_gate.track(path.clone(), image.clone());
+                    image
+                })
+                .collect();
+            commands.spawn((
+                Name::new(format!("{}/sprite{layer_index}", region.name)),
+                SpriteLayer {
+                    config: config.clone(),
+                    images,
+                    center: region.layer_center(layout.size.height),
+                    bounds: region.layer_bounds(layout.size.height),
+                    // Later regions and layers on top
+                    depth: region_index as f32 + (layer_index + 1) as f32 * 0.01,
+                    frames: Vec::new(),
+                    frame: 0,
+                    elapsed: 0.0,
+                },
+            ));
+        }
+    }
+}
+
+/// Cuts the images into frames and adds the quad once every image is loaded
+pub(super) fn prepare_sprite_layers(
+    mut commands: Commands,
+    mut layers: Query<(Entity, &mut SpriteLayer), Without<Mesh2d>>,
+    images: Res<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<LayerMaterial>>,
+) {
+    for (entity, mut layer) in layers.iter_mut() {
+        let Some(sizes) = layer
+            .images
+            .iter()
+            .map(|image| images.get(image).map(Image::size))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+
+        let frame_size = match (&layer.config.sheet, layer.config.frame_size) {
+            (Some(_), Some(frame_size)) => UVec2::from(frame_size),
+            _ => sizes[0],
+        };
+        layer.frames = match &layer.config.sheet {
+            Some(sheet) => {
+                let grid = match sheet_grid(sizes[0], frame_size) {
+                    Ok(grid) => grid,
+                    Err(e) => {
+                        warn!("Skipping sprite layer with sheet {sheet:?}: {e}");
+                        commands.entity(entity).despawn();
+                        continue;
+                    }
+                };
+                let count = grid.x * grid.y;
+                let uv_size = frame_size.as_vec2() / sizes[0].as_vec2();
+                (0..layer.config.frame_count.unwrap_or(count).min(count))
+                    .map(|frame| {
+                        let cell = UVec2::new(frame % grid.x, frame / grid.x);
+                        let min = cell.as_vec2() * uv_size;
+                        SpriteFrame {
+                            image: 0,
+                            uv: Rect::from_corners(min, min + uv_size),
+                        }
+                    })
+                    .collect()
+            }
+            None => (0..layer.images.len())
+                .map(|image| SpriteFrame {
+                    image,
+                    uv: Rect::new(0.0, 0.0, 1.0, 1.0),
+                })
+                .collect(),
+        };
+
+        let mut material = LayerMaterial::new(
+            layer.images[0].clone(),
+            layer.bounds,
+            layer.config.blend,
+            layer.config.opacity,
+        );
+        material.set_uv_rect(layer.frames[0].uv);
+        let [offset_x, offset_y] = layer.config.offset;
+        let translation = layer.center + Vec2::new(offset_x, -offset_y);
+        commands.entity(entity).insert((
+            Mesh2d(meshes.add(Rectangle::from_size(
+                frame_size.as_vec2() * layer.config.scale,
+            ))),
+            MeshMaterial2d(materials.add(material)),
+            Transform::from_translation(translation.extend(layer.depth)),
+        ));
+    }
+}
+
+/// Frames across and down a sheet, which must be cut into whole frames
+fn sheet_grid(sheet_size: UVec2, frame_size: UVec2) -> Result<UVec2, String> {
+    if sheet_size.cmplt(frame_size).any() || (sheet_size % frame_size) != UVec2::ZERO {
+        return Err(format!(
+            "the {}x{} sheet does not divide into {}x{} frames",
+            sheet_size.x, sheet_size.y, frame_size.x, frame_size.y
+        ));
+    }
+    Ok(sheet_size / frame_size)
+}
+
+pub(super) fn animate_sprite_layers(
+    time: Res<Time>,
+    mut layers: Query<(&mut SpriteLayer, &MeshMaterial2d<LayerMaterial>)>,
+    mut materials: ResMut<Assets<LayerMaterial>>,
+) {
+    for (mut layer, material) in layers.iter_mut() {
+        let layer = &mut *layer;
+        let previous_frame = layer.frame;
+        layer.elapsed += time.delta_secs();
+        loop {
+            let duration = layer.config.frame_duration(layer.frame);
+            let last_frame = layer.frame + 1 == layer.frames.len();
+            if layer.elapsed < duration || (last_frame && !layer.config.looping) {
+                break;
+            }
+            layer.elapsed -= duration;
+            layer.frame = if last_frame { 0 } else { layer.frame + 1 };
+        }
+
+        // Changing the material uploads it again, so only when the frame changed
+        if layer.frame != previous_frame
+            && let Some(material) = materials.get_mut(&material.0)
+        {
+            let frame = &layer.frames[layer.frame];
+            material.texture = layer.images[frame.image].clone();
+            material.set_uv_rect(frame.uv);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(frame_size: Option<[u32; 2]>, frame_count: Option<u32>) -> SpriteLayerConfig {
+        serde_json::from_value(serde_json::json!({
+            "sheet": "sprites/heart.png",
+            "frame_size": frame_size,
+            "frame_count": frame_count,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn frame_size_and_count() {
+        assert!(config(None, None).validate().is_ok());
+        assert!(config(Some([16, 16]), Some(4)).validate().is_ok());
+        assert!(config(Some([MAX_FRAME_SIZE, 1]), None).validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_zero_frame_count() {
+        assert!(config(None, Some(0)).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_zero_frame_size() {
+        assert!(config(Some([0, 16]), None).validate().is_err());
+        assert!(config(Some([16, 0]), None).validate().is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_frame_size() {
+        assert!(
+            config(Some([MAX_FRAME_SIZE + 1, 16]), None)
+                .validate()
+                .is_err()
+        );
+        assert!(config(Some([16, u32::MAX]), None).validate().is_err());
+    }
+
+    #[test]
+    fn sheets_divide_into_whole_frames() {
+        assert_eq!(
+            sheet_grid(UVec2::new(64, 32), UVec2::new(16, 16)),
+            Ok(UVec2::new(4, 2))
+        );
+        assert_eq!(
+            sheet_grid(UVec2::new(16, 16), UVec2::new(16, 16)),
+            Ok(UVec2::ONE)
+        );
+        assert!(sheet_grid(UVec2::new(64, 30), UVec2::new(16, 16)).is_err());
+        assert!(sheet_grid(UVec2::new(8, 8), UVec2::new(16, 16)).is_err());
+    }
+}
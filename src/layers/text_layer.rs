@@ -0,0 +1,355 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+use crate::{
+    control::ControlCommand,
+    layers::{BlendMode, LayerMaterial, bitmap_font::BitmapFont},
+    output::PanelLayout,
+    scene::ReadinessGate,
+};
+
+fn default_scroll_speed() -> f32 {
+    24.0
+}
+
+fn default_hold() -> f32 {
+    3.0
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerticalAlign {
+    Top,
+    #[default]
+    Middle,
+    Bottom,
+}
+
+/// When a message scrolls through the region like a marquee
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scroll {
+    Never,
+    // Only messages wider than the region
+    #[default]
+    Overflow,
+    Always,
+}
+
+/// Color of the set pixels of the glyphs
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextFill {
+    Color([u8; 3]),
+    // Colors spread evenly across the region, left to right or top to bottom
+    Gradient {
+        colors: Vec<[u8; 3]>,
+        #[serde(default)]
+        vertical: bool,
+    },
+}
+
+impl Default for TextFill {
+    fn default() -> TextFill {
+        TextFill::Color([255, 255, 255])
+    }
+}
+
+impl TextFill {
+    fn color_at(&self, x: u32, y: u32, size: UVec2) -> [u8; 3] {
+        let (colors, position, length) = match self {
+            TextFill::Color(color) => return *color,
+            TextFill::Gradient {
+                colors,
+                vertical: false,
+            } => (colors, x, size.x),
+            TextFill::Gradient {
+                colors,
+                vertical: true,
+            } => (colors, y, size.y),
+        };
+        if colors.len() < 2 {
+            return colors.first().copied().unwrap_or([255; 3]);
+        }
+        let t = position as f32 / (length.max(2) - 1) as f32 * (colors.len() - 1) as f32;
+        let index = (t as usize).min(colors.len() - 2);
+        let (from, to, t) = (colors[index], colors[index + 1], t - index as f32);
+        [0, 1, 2].map(|channel| {
+            (from[channel] as f32 + (to[channel] as f32 - from[channel] as f32) * t).round() as u8
+        })
+    }
+}
+
+/// Text drawn pixel exact in a panel region with a bitmap font
+#[derive(Debug, Clone, Deserialize)]
+pub struct TextLayerConfig {
+    // `.bdf` or `.pcf` font relative to the `assets` folder
+    pub font: String,
+    // Shown until a message is queued with the `text` command
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub align: TextAlign,
+    #[serde(default)]
+    pub vertical_align: VerticalAlign,
+    #[serde(default)]
+    pub scroll: Scroll,
+    // Panel pixels per second a scrolling message moves left
+    #[serde(default = "default_scroll_speed")]
+    pub scroll_speed: f32,
+    // Seconds a message that does not scroll is shown before the next queued one
+    #[serde(default = "default_hold")]
+    pub hold: f32,
+    #[serde(default)]
+    pub fill: TextFill,
+    #[serde(default)]
+    pub blend: BlendMode,
+    #[serde(default = "default_one")]
+    pub opacity: f32,
+}
+
+impl TextLayerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        // A message scrolling at zero or negative speed would never finish
+        if !self.scroll_speed.is_finite() || self.scroll_speed <= 0.0 {
+            return Err(format!(
+                "text scroll_speed {} must be positive",
+                self.scroll_speed
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Text layer of a panel region, drawing into an image of the region's size
+#[derive(Debug, Component)]
+pub struct TextLayer {
+    region: String,
+    config: TextLayerConfig,
+    font: Handle<BitmapFont>,
+    image: Handle<Image>,
+    size: UVec2,
+    // Messages waiting for the current one to finish
+    queue: VecDeque<String>,
+    text: String,
+    // Seconds the current message has been shown
+    elapsed: f32,
+    // Text and left edge the image was last drawn with
+    drawn: Option<(String, i32)>,
+}
+
+pub(super) fn spawn_text_layers(
+    mut commands: Commands,
+    layout: Res<PanelLayout>,
+    asset_server: Res<AssetServer>,
+    mut readiness_gate: ResMut<ReadinessGate>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<LayerMaterial>>,
+) {
+    for (region_index, region) in layout.regions.iter().enumerate() {
+        let Some(config) = &region.text else {
+            continue;
+        };
+
+        let font: Handle<BitmapFont> = asset_server.load(&config.font);
+        readiness_gate.track(config.font.clone(), font.clone());
+        let [_, _, width, height] = region.rect;
+        let image = images.add(Image::new_fill(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0; 4],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        ));
+        let material = LayerMaterial::new(
+            image.clone(),
+            region.layer_bounds(layout.size.height),
+            config.blend,
+            config.opacity,
+        );
+        // Above the sprites of the same region
+        let depth = region_index as f32 + 0.5;
+        commands.spawn((
+            Name::new(format!("{}/text", region.name)),
+            TextLayer {
+                region: region.name.clone(),
+                config: config.clone(),
+                font,
+                image,
+                size: UVec2::new(width, height),
+                queue: VecDeque::new(),
+                text: config.text.clone(),
+                elapsed: 0.0,
+                drawn: None,
+            },
+            Mesh2d(meshes.add(Rectangle::new(width as f32, height as f32))),
+            MeshMaterial2d(materials.add(material)),
+            Transform::from_translation(region.layer_center(layout.size.height).extend(depth)),
+        ));
+    }
+}
+
+pub(super) fn queue_text_messages(
+    mut control_commands: MessageReader<ControlCommand>,
+    mut layers: Query<&mut TextLayer>,
+) {
+    for command in control_commands.read() {
+        let ControlCommand::Text { region, text } = command else {
+            continue;
+        };
+        let mut found = false;
+        for mut layer in layers.iter_mut().filter(|layer| layer.region == *region) {
+            layer.queue.push_back(text.clone());
+            found = true;
+        }
+        if !found {
+            warn!("No text layer in region `{region}`");
+        }
+    }
+}
+
+/// Moves to the next message once the current one is done and redraws the image when it
+/// changed
+pub(super) fn update_text_layers(
+    time: Res<Time>,
+    fonts: Res<Assets<BitmapFont>>,
+    mut layers: Query<(&mut TextLayer, &MeshMaterial2d<LayerMaterial>)>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<LayerMaterial>>,
+) {
+    for (mut layer, material) in layers.iter_mut() {
+        let Some(font) = fonts.get(&layer.font) else {
+            continue;
+        };
+        let layer = &mut *layer;
+        let width = layer.size.x as i32;
+
+        layer.elapsed += time.delta_secs();
+        let mut text_width = font.text_width(&layer.text);
+        let scroll = layer.config.scroll;
+        let scrolling = |text_width| match scroll {
+            Scroll::Never => false,
+            Scroll::Overflow => text_width > width,
+            Scroll::Always => true,
+        };
+        let done = match scrolling(text_width) {
+            // Entered on the right and left completely on the left
+            true => layer.elapsed * layer.config.scroll_speed >= (width + text_width) as f32,
+            false => layer.elapsed >= layer.config.hold,
+        };
+        if done {
+            match layer.queue.pop_front() {
+                Some(text) => {
+                    text_width = font.text_width(&text);
+                    layer.text = text;
+                    layer.elapsed = 0.0;
+                }
+                // A scrolling message with nothing after it starts over
+                None if scrolling(text_width) => layer.elapsed = 0.0,
+                // Otherwise it stays until a message is queued
+                None => {}
+            }
+        }
+
+        let left = match (scrolling(text_width), layer.config.align) {
+            (true, _) => width - (layer.elapsed * layer.config.scroll_speed).floor() as i32,
+            (false, TextAlign::Left) => 0,
+            (false, TextAlign::Center) => (width - text_width) / 2,
+            (false, TextAlign::Right) => width - text_width,
+        };
+        if layer
+            .drawn
+            .as_ref()
+            .is_some_and(|(text, drawn_left)| *text == layer.text && *drawn_left == left)
+        {
+            continue;
+        }
+
+        let Some(image) = images.get_mut(&layer.image) else {
+            continue;
+        };
+        image.data = Some(draw_text(font, &layer.text, left, layer));
+        layer.drawn = Some((layer.text.clone(), left));
+        // Marks the material changed so it binds the updated image
+        materials.get_mut(&material.0);
+    }
+}
+
+/// RGBA pixels of the region, transparent where no glyph pixel is set
+fn draw_text(font: &BitmapFont, text: &str, left: i32, layer: &TextLayer) -> Vec<u8> {
+    let size = layer.size;
+    let mut pixels = vec![0; (size.x * size.y * 4) as usize];
+    let top = match layer.config.vertical_align {
+        VerticalAlign::Top => 0,
+        VerticalAlign::Middle => (size.y as i32 - font.line_height()) / 2,
+        VerticalAlign::Bottom => size.y as i32 - font.line_height(),
+    };
+    let baseline = top + font.ascent;
+
+    let mut pen = left;
+    for glyph in text.chars().filter_map(|c| font.glyph(c)) {
+        let glyph_top = baseline - glyph.y_offset - glyph.height as i32;
+        for y in 0..glyph.height {
+            for x in 0..glyph.width {
+                let (panel_x, panel_y) = (pen + glyph.x_offset + x as i32, glyph_top + y as i32);
+                if !glyph.pixel(x, y)
+                    || !(0..size.x as i32).contains(&panel_x)
+                    || !(0..size.y as i32).contains(&panel_y)
+                {
+                    continue;
+                }
+                let [r, g, b] = layer
+                    .config
+                    .fill
+                    .color_at(panel_x as u32, panel_y as u32, size);
+                let index = ((panel_y as u32 * size.x + panel_x as u32) * 4) as usize;
+                pixels[index..index + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+        pen += glyph.advance;
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(scroll_speed: f32) -> TextLayerConfig {
+        let mut config: TextLayerConfig =
+            serde_json::from_str(r#"{ "font": "fonts/5x7.bdf" }"#).unwrap();
+        config.scroll_speed = scroll_speed;
+        config
+    }
+
+    #[test]
+    fn scroll_speed_must_be_positive() {
+        assert!(config(24.0).validate().is_ok());
+        for speed in [0.0, -10.0, f32::INFINITY, f32::NAN] {
+            assert!(config(speed).validate().is_err(), "{speed} was accepted");
+        }
+    }
+}
@@ -0,0 +1,52 @@
+// Transfer curve of the LED panels, applied to linear color before it is captured
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+const MAX_PANELS: u32 = 8u;
+
+struct LedTransferUniform {
+    // Lowest output of a lit LED, darker ones flicker
+    black_level: f32,
+    // Values at or below it are switched off
+    black_cutoff: f32,
+    // Where highlights start rolling off instead of clipping
+    shoulder: f32,
+    // Brightness outside every panel rect
+    max_brightness: f32,
+    // Texture coordinate rects of the panels, min xy and max xy
+    panel_rects: array<vec4<f32>, 8>,
+    // Brightness of each panel, four per element
+    panel_brightness: array<vec4<f32>, 2>,
+    panel_count: u32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> transfer: LedTransferUniform;
+
+fn max_brightness(uv: vec2<f32>) -> f32 {
+    for (var i = 0u; i < min(transfer.panel_count, MAX_PANELS); i += 1u) {
+        let rect = transfer.panel_rects[i];
+        if all(uv >= rect.xy) && all(uv < rect.zw) {
+            return transfer.panel_brightness[i / 4u][i % 4u];
+        }
+    }
+    return transfer.max_brightness;
+}
+
+// Linear up to the shoulder, then rolling off towards 1
+fn compress(value: vec3<f32>) -> vec3<f32> {
+    let knee = clamp(transfer.shoulder, 0.0, 0.999);
+    let range = 1.0 - knee;
+    let rolled = knee + range * (1.0 - exp(-(value - knee) / range));
+    return select(value, rolled, value > vec3<f32>(knee));
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    let compressed = compress(max(color.rgb, vec3<f32>(0.0)));
+    // Lit LEDs span from the black level to the brightness of their panel
+    let lit = mix(vec3<f32>(transfer.black_level), vec3<f32>(max_brightness(in.uv)), compressed);
+    let output = select(lit, vec3<f32>(0.0), compressed <= vec3<f32>(transfer.black_cutoff));
+    return vec4<f32>(output, color.a);
+}
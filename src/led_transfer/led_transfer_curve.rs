@@ -0,0 +1,359 @@
+use bevy::{
+    asset::embedded_asset,
+    core_pipeline::{
+        core_2d::graph::{Core2d, Node2d},
+        core_3d::graph::{Core3d, Node3d},
+    },
+    ecs::query::QueryItem,
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderStartup, RenderSystems,
+        extract_component::{ExtractComponent, ExtractComponentPlugin, UniformComponentPlugin},
+        render_graph::{RenderGraphExt, RenderLabel, ViewNodeRunner},
+        render_resource::{ShaderType, SpecializedRenderPipelines},
+    },
+};
+use serde::Deserialize;
+
+use crate::{
+    face::FaceCamera,
+    layers::LayerCamera,
+    led_transfer::led_transfer_node::{
+        LedTransferNode, LedTransferPipeline, init_led_transfer_pipeline,
+        prepare_led_transfer_pipelines,
+    },
+    output::PanelLayout,
+    post_process::PostProcessLabel,
+    symmetry::MirrorSymmetryLabel,
+};
+
+/// Panels with their own brightness the shader tells apart, later ones use `max_brightness`
+const MAX_PANELS: usize = 8;
+
+/// Cameras the LED transfer runs on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedTransferCamera {
+    // The layer camera, over the final composite after post-processing
+    #[default]
+    Layers,
+    // The face cameras, before layers and post-processing are drawn over them
+    Face,
+}
+
+/// Response of the LED panels, the `led_transfer` of a [`PanelLayout`].
+///
+/// ```json
+/// { "black_level": 0.03, "black_cutoff": 0.004, "shoulder": 0.75, "max_brightness": 0.8 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LedTransferConfig {
+    // Lowest output of a lit LED, darker values flicker on PWM driven panels
+    pub black_level: f32,
+    // Values at or below it are switched off, so black stays truly black
+    pub black_cutoff: f32,
+    // Highlights above it roll off instead of clipping
+    pub shoulder: f32,
+    // Output of full white, regions can set their own
+    pub max_brightness: f32,
+    pub camera: LedTransferCamera,
+}
+
+impl Default for LedTransferConfig {
+    fn default() -> Self {
+        LedTransferConfig {
+            black_level: 0.0,
+            black_cutoff: 0.0,
+            shoulder: 0.8,
+            max_brightness: 1.0,
+            camera: LedTransferCamera::default(),
+        }
+    }
+}
+
+impl LedTransferConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..1.0).contains(&self.shoulder) {
+            return Err(format!(
+                "led_transfer shoulder {} is outside 0..1",
+                self.shoulder
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.black_level) || !(0.0..=1.0).contains(&self.black_cutoff) {
+            return Err(String::from(
+                "led_transfer black_level and black_cutoff must be within 0..1",
+            ));
+        }
+        if self.max_brightness < 0.0 {
+            return Err(String::from(
+                "led_transfer max_brightness can not be negative",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Runs the LED transfer on the camera it is on. The one `camera` setting of the layout's
+/// `led_transfer` puts it on the layer camera or on every face camera, not per region.
+#[derive(Debug, Clone, PartialEq, Component)]
+pub struct LedTransfer {
+    pub config: LedTransferConfig,
+    // Texture coordinate rect and brightness of the regions setting one
+    pub panels: Vec<(Rect, f32)>,
+}
+
+impl LedTransfer {
+    fn from_layout(config: &LedTransferConfig, layout: &PanelLayout) -> LedTransfer {
+        let size = Vec2::new(layout.size.width as f32, layout.size.height as f32);
+        let panels = layout
+            .regions
+            .iter()
+            .filter_map(|region| {
+                let [x, y, width, height] = region.rect.map(|value| value as f32);
+                let rect = Rect::new(x, y, x + width, y + height);
+                let uv = Rect::from_corners(rect.min / size, rect.max / size);
+                region.max_brightness.map(|brightness| (uv, brightness))
+            })
+            .collect::<Vec<_>>();
+        if panels.len() > MAX_PANELS {
+            warn!(
+                "Only the first {MAX_PANELS} of {} panel brightnesses are used",
+                panels.len()
+            );
+        }
+        LedTransfer {
+            config: config.clone(),
+            panels: panels.into_iter().take(MAX_PANELS).collect(),
+        }
+    }
+}
+
+/// Uniform of `led_transfer.wgsl`
+#[derive(Debug, Clone, Component, ShaderType)]
+pub struct LedTransferUniform {
+    black_level: f32,
+    black_cutoff: f32,
+    shoulder: f32,
+    max_brightness: f32,
+    panel_rects: [Vec4; MAX_PANELS],
+    panel_brightness: [Vec4; MAX_PANELS / 4],
+    panel_count: u32,
+}
+
+impl ExtractComponent for LedTransfer {
+    type QueryData = &'static LedTransfer;
+    type QueryFilter = ();
+    type Out = LedTransferUniform;
+
+    fn extract_component(transfer: QueryItem<'_, '_, Self::QueryData>) -> Option<Self::Out> {
+        let mut panel_rects = [Vec4::ZERO; MAX_PANELS];
+        let mut panel_brightness = [Vec4::ZERO; MAX_PANELS / 4];
+        for (index, (rect, brightness)) in transfer.panels.iter().enumerate() {
+            panel_rects[index] = Vec4::new(rect.min.x, rect.min.y, rect.max.x, rect.max.y);
+            panel_brightness[index / 4][index % 4] = *brightness;
+        }
+        Some(LedTransferUniform {
+            black_level: transfer.config.black_level,
+            black_cutoff: transfer.config.black_cutoff,
+            shoulder: transfer.config.shoulder,
+            max_brightness: transfer.config.max_brightness,
+            panel_rects,
+            panel_brightness,
+            panel_count: transfer.panels.len() as u32,
+        })
+    }
+}
+
+// (camera, is the layer camera, is a face camera, its current transfer)
+type TransferCamera = (
+    Entity,
+    Has<LayerCamera>,
+    Has<FaceCamera>,
+    Option<&'static LedTransfer>,
+);
+
+/// Puts the transfer of the layout on the cameras it selects, cameras spawn with the scene
+/// and its transitions, so they are checked every frame
+fn select_led_transfer_cameras(
+    mut commands: Commands,
+    layout: Res<PanelLayout>,
+    cameras: Query<TransferCamera>,
+) {
+    let transfer = layout
+        .led_transfer
+        .as_ref()
+        .map(|config| (config.camera, LedTransfer::from_layout(config, &layout)));
+    for (entity, layer_camera, face_camera, current) in cameras.iter() {
+        let selected = match &transfer {
+            Some((LedTransferCamera::Layers, transfer)) if layer_camera => Some(transfer),
+            Some((LedTransferCamera::Face, transfer)) if face_camera => Some(transfer),
+            _ => None,
+        };
+        match (selected, current) {
+            (Some(selected), current) if current != Some(selected) => {
+                commands.entity(entity).insert(selected.clone());
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<LedTransfer>();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, RenderLabel)]
+struct LedTransferLabel;
+
+/// Maps the render onto the response of the LED panels: a black level lit LEDs do not go
+/// below, a shoulder compressing highlights and a maximum brightness per panel. Runs as the
+/// last pass of the cameras the `led_transfer` of the [`PanelLayout`] selects, before
+/// `ImageCopyDriver` copies their target.
+pub struct LedTransferPlugin;
+
+impl Plugin for LedTransferPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "led_transfer.wgsl");
+        app.add_plugins(ExtractComponentPlugin::<LedTransfer>::default())
+            .add_plugins(UniformComponentPlugin::<LedTransferUniform>::default())
+            .add_systems(PostUpdate, select_led_transfer_cameras);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedRenderPipelines<LedTransferPipeline>>()
+            .add_systems(RenderStartup, init_led_transfer_pipeline)
+            .add_systems(
+                Render,
+                prepare_led_transfer_pipelines.in_set(RenderSystems::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<LedTransferNode>>(Core2d, LedTransferLabel)
+            .add_render_graph_edges(
+                Core2d,
+                (
+                    PostProcessLabel,
+                    LedTransferLabel,
+                    Node2d::EndMainPassPostProcessing,
+                ),
+            )
+            .add_render_graph_node::<ViewNodeRunner<LedTransferNode>>(Core3d, LedTransferLabel)
+            .add_render_graph_edges(
+                Core3d,
+                (
+                    MirrorSymmetryLabel,
+                    LedTransferLabel,
+                    Node3d::EndMainPassPostProcessing,
+                ),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::PanelSize;
+
+    /// What `led_transfer.wgsl` does to one channel of a pixel
+    fn transfer(config: &LedTransferConfig, value: f32, max_brightness: f32) -> f32 {
+        let knee = config.shoulder.clamp(0.0, 0.999);
+        let range = 1.0 - knee;
+        let value = value.max(0.0);
+        let compressed = match value > knee {
+            true => knee + range * (1.0 - (-(value - knee) / range).exp()),
+            false => value,
+        };
+        match compressed <= config.black_cutoff {
+            true => 0.0,
+            false => config.black_level + (max_brightness - config.black_level) * compressed,
+        }
+    }
+
+    fn config() -> LedTransferConfig {
+        LedTransferConfig {
+            black_level: 0.05,
+            black_cutoff: 0.01,
+            shoulder: 0.75,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn black_level_and_cutoff() {
+        let config = config();
+        assert_eq!(transfer(&config, 0.0, 1.0), 0.0);
+        assert_eq!(transfer(&config, 0.01, 1.0), 0.0);
+        assert_eq!(transfer(&config, -1.0, 1.0), 0.0);
+        // Just above the cutoff a lit LED starts at the black level
+        let dim = transfer(&config, 0.011, 1.0);
+        assert!(dim >= config.black_level && dim < config.black_level + 0.02);
+        // Linear below the shoulder
+        assert!((transfer(&config, 0.5, 1.0) - (0.05 + 0.95 * 0.5)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn shoulder_rolls_off_below_full_brightness() {
+        let config = config();
+        let at_knee = transfer(&config, 0.75, 1.0);
+        assert!((at_knee - (0.05 + 0.95 * 0.75)).abs() < 1e-6);
+        let mut previous = at_knee;
+        for value in [0.8, 1.0, 2.0] {
+            let output = transfer(&config, value, 1.0);
+            assert!(output > previous && output < 1.0, "{value}");
+            previous = output;
+        }
+        // Far above white it saturates instead of clipping past it
+        assert!((transfer(&config, 100.0, 1.0) - 1.0).abs() < 1e-6);
+        // Continuous at the knee, the curve starts out with slope 1
+        let above = transfer(&config, 0.7501, 1.0);
+        assert!((above - at_knee - 0.95 * 0.0001).abs() < 1e-6);
+        // The panel brightness scales the top of the range
+        assert!(transfer(&config, 2.0, 0.5) < 0.5);
+    }
+
+    #[test]
+    fn validation() {
+        assert_eq!(config().validate(), Ok(()));
+        for invalid in [
+            LedTransferConfig {
+                shoulder: 1.0,
+                ..config()
+            },
+            LedTransferConfig {
+                black_level: 1.5,
+                ..config()
+            },
+            LedTransferConfig {
+                black_cutoff: -0.1,
+                ..config()
+            },
+            LedTransferConfig {
+                max_brightness: -1.0,
+                ..config()
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn panel_brightness_rects() {
+        let mut layout = PanelLayout::single(PanelSize {
+            width: 128,
+            height: 32,
+        });
+        let mut right = layout.regions[0].clone();
+        layout.regions[0].rect = [0, 0, 64, 32];
+        right.rect = [64, 0, 64, 32];
+        right.max_brightness = Some(0.5);
+        layout.regions.push(right);
+
+        let transfer = LedTransfer::from_layout(&config(), &layout);
+        assert_eq!(transfer.panels, [(Rect::new(0.5, 0.0, 1.0, 1.0), 0.5)]);
+
+        let regions = layout.regions[1].clone();
+        layout.regions = vec![regions; MAX_PANELS + 2];
+        let transfer = LedTransfer::from_layout(&config(), &layout);
+        assert_eq!(transfer.panels.len(), MAX_PANELS);
+    }
+}
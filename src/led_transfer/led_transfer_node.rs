@@ -0,0 +1,167 @@
+use bevy::{
+    asset::load_embedded_asset,
+    core_pipeline::FullscreenShader,
+    ecs::query::QueryItem,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        extract_component::{ComponentUniforms, DynamicUniformIndex},
+        render_graph::{NodeRunError, RenderGraphContext, ViewNode},
+        render_resource::{
+            BindGroupEntries, BindGroupLayoutDescriptor, BindGroupLayoutEntries,
+            CachedRenderPipelineId, ColorTargetState, ColorWrites, FragmentState, Operations,
+            PipelineCache, RenderPassColorAttachment, RenderPassDescriptor,
+            RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
+            SpecializedRenderPipeline, SpecializedRenderPipelines, TextureFormat,
+            TextureSampleType,
+            binding_types::{sampler, texture_2d, uniform_buffer},
+        },
+        renderer::{RenderContext, RenderDevice},
+        view::{ExtractedView, ViewTarget},
+    },
+};
+
+use crate::led_transfer::led_transfer_curve::LedTransferUniform;
+
+#[derive(Resource)]
+pub struct LedTransferPipeline {
+    layout: BindGroupLayoutDescriptor,
+    sampler: Sampler,
+    fullscreen_shader: FullscreenShader,
+    fragment_shader: Handle<Shader>,
+}
+
+pub fn init_led_transfer_pipeline(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    fullscreen_shader: Res<FullscreenShader>,
+    asset_server: Res<AssetServer>,
+) {
+    let layout = BindGroupLayoutDescriptor::new(
+        "led_transfer_bind_group_layout",
+        &BindGroupLayoutEntries::sequential(
+            ShaderStages::FRAGMENT,
+            (
+                texture_2d(TextureSampleType::Float { filterable: true }),
+                sampler(SamplerBindingType::Filtering),
+                uniform_buffer::<LedTransferUniform>(true),
+            ),
+        ),
+    );
+    // Source and destination are the same size, pixels are sampled exactly
+    let sampler = render_device.create_sampler(&SamplerDescriptor::default());
+
+    commands.insert_resource(LedTransferPipeline {
+        layout,
+        sampler,
+        fullscreen_shader: fullscreen_shader.clone(),
+        fragment_shader: load_embedded_asset!(asset_server.as_ref(), "led_transfer.wgsl"),
+    });
+}
+
+impl SpecializedRenderPipeline for LedTransferPipeline {
+    type Key = TextureFormat;
+
+    fn specialize(&self, texture_format: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("led_transfer".into()),
+            layout: vec![self.layout.clone()],
+            vertex: self.fullscreen_shader.to_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.fragment_shader.clone(),
+                targets: vec![Some(ColorTargetState {
+                    format: texture_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+                ..default()
+            }),
+            ..default()
+        }
+    }
+}
+
+/// Pipeline for the texture format of the view
+#[derive(Component)]
+pub struct LedTransferPipelineId(CachedRenderPipelineId);
+
+pub fn prepare_led_transfer_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<LedTransferPipeline>>,
+    led_transfer_pipeline: Res<LedTransferPipeline>,
+    views: Query<(Entity, &ExtractedView), With<LedTransferUniform>>,
+) {
+    for (entity, view) in views.iter() {
+        let texture_format = match view.hdr {
+            true => ViewTarget::TEXTURE_FORMAT_HDR,
+            false => TextureFormat::bevy_default(),
+        };
+        let pipeline =
+            pipelines.specialize(&pipeline_cache, &led_transfer_pipeline, texture_format);
+        commands
+            .entity(entity)
+            .insert(LedTransferPipelineId(pipeline));
+    }
+}
+
+/// Maps the main texture of the view through the LED transfer curve
+#[derive(Default)]
+pub struct LedTransferNode;
+
+impl ViewNode for LedTransferNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static LedTransferPipelineId,
+        &'static DynamicUniformIndex<LedTransferUniform>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (target, pipeline, uniform_index): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let led_transfer_pipeline = world.resource::<LedTransferPipeline>();
+        let uniforms = world.resource::<ComponentUniforms<LedTransferUniform>>();
+        let Some(uniform_binding) = uniforms.uniforms().binding() else {
+            return Ok(());
+        };
+        // Still compiling, the render goes out untouched until it is ready
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline.0) else {
+            return Ok(());
+        };
+
+        let post_process = target.post_process_write();
+        let bind_group = render_context.render_device().create_bind_group(
+            None,
+            &pipeline_cache.get_bind_group_layout(&led_transfer_pipeline.layout),
+            &BindGroupEntries::sequential((
+                post_process.source,
+                &led_transfer_pipeline.sampler,
+                uniform_binding,
+            )),
+        );
+        let mut render_pass =
+            render_context
+                .command_encoder()
+                .begin_render_pass(&RenderPassDescriptor {
+                    label: Some("led_transfer"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: post_process.destination,
+                        depth_slice: None,
+                        resolve_target: None,
+                        ops: Operations::default(),
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[uniform_index.index()]);
+        render_pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
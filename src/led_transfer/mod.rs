@@ -0,0 +1,3 @@
+mod led_transfer_curve;
+pub use led_transfer_curve::{LedTransferConfig, LedTransferPlugin};
+mod led_transfer_node;
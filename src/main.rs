@@ -5,8 +5,8 @@
 //! 1. Render from camera to gpu-image render target
 //! 2. Copy from gpu image to buffer using `ImageCopyDriver` node in `RenderGraph`
 //! 3. Copy from buffer to channel using `receive_image_from_buffer` after `RenderSystems::Render`
-//! 4. Save from channel to random named file using `scene::update` at `PostUpdate` in `MainWorld`
-//! 5. Exit if `single_image` setting is set
+//! 4. Save from channel to numbered file, with its frame metadata, using `save_frame` at `PostUpdate` in `MainWorld`
+//! 5. Exit if `single_image` is set or `frame_limit` frames were saved
 //!
 //! If your goal is to capture a single “screenshot” as opposed to every single rendered frame
 //! without gaps, it is simpler to use [`bevy::render::view::window::screenshot::Screenshot`]
@@ -14,45 +14,401 @@
 
 use bevy::{
     app::{AppExit, ScheduleRunnerPlugin},
-    core_pipeline::tonemapping::Tonemapping,
     image::TextureFormatPixelInfo,
+    log::LogPlugin,
+    platform::time::Instant,
     prelude::*,
     render::renderer::RenderDevice,
     window::ExitCondition,
 };
-use std::{
-    ops::{Deref, DerefMut},
-    path::PathBuf,
-    time::Duration,
-};
+use std::{path::PathBuf, time::Duration};
 
+mod audio;
+mod bake;
+use audio::{AudioInputPlugin, AudioSmoothing, PcmSource};
+use bake::{BakePlaybackPlugin, LoopPoints};
+mod control;
+use control::{ControlPlugin, ControlSource};
+mod face;
+use face::{
+    EyeBehaviour, EyeBehaviourPlugin, FaceAtlasPlugin, FaceScene, FaceScenePlugin, MorphPlugin,
+};
+mod layers;
+use layers::LayersPlugin;
+mod led_transfer;
+use led_transfer::LedTransferPlugin;
 mod scene;
-use scene::{SceneController, SceneState};
+use scene::{
+    DeterministicPlugin, HotReloadPlugin, Playlist, PlaylistPlugin, RandomSeed, ReadinessGate,
+    ReadinessPlugin, RuntimeConfig, SceneController, SceneState,
+};
+mod output;
+mod patterns;
+mod post_process;
+use output::{
+    Calibration, LinearPanelFrame, OutputSinks, PanelFrame, PanelLayout, PanelSize, PixelMap,
+    PreviewStyle, SinkConfig,
+};
+use patterns::{PatternScenePlugin, TestPattern};
+use post_process::PostProcessPlugin;
+mod symmetry;
+use symmetry::SymmetryPlugin;
+mod transition;
+use transition::SceneTransitionPlugin;
 mod image_grab;
-use image_grab::{ImageCopyPlugin, ImageToSave, MainWorldReceiver};
+use image_grab::{
+    CaptureConversion, CaptureFormat, CaptureTarget, CapturedFrame, ImageCopyPlugin, ImageToSave,
+    MainWorldReceiver, PrimaryCaptureTarget,
+};
 
 // Parameters of resulting image
 struct AppConfig {
     width: u32,
     height: u32,
+    // Pixel format rendered into, `rgba8`, `rgb10a2` or `rgba16f`
+    capture_format: CaptureFormat,
+    // How wider formats are brought to 8 bits for the PNGs and sinks
+    conversion: CaptureConversion,
     single_image: bool,
+    // Folder the numbered frames and their metadata are saved to
+    output: PathBuf,
+    // glTF/GLB face asset, relative to the `assets` folder
+    face_scene: String,
+    // Frames rendered after the scene is ready and before capture starts
+    settle_frames: u32,
+    // Time the scene gets to become ready before giving up, in seconds on the command line
+    ready_timeout: Duration,
+    // Stdin (`-`), file or named pipe to read runtime commands from
+    control: Option<String>,
+    // WAV file, or stdin (`-`), file or named pipe with raw 16 bit PCM driving the mouth
+    audio: Option<String>,
+    // Sample rate of raw PCM input
+    audio_rate: u32,
+    audio_smoothing: AudioSmoothing,
+    // Blink and saccade timing
+    eye_behaviour: EyeBehaviour,
+    // Seeds all randomness, the same seed renders the same eye motion
+    seed: u64,
+    // Fixed time step and every frame saved, so frame N is identical across runs
+    deterministic: bool,
+    // Frames per second of the deterministic time step
+    fps: f64,
+    // Frames to save before exiting, a single image otherwise
+    frames: Option<u32>,
+    // Seconds of animation to render in batch, sets `frames` from `fps` and implies `deterministic`
+    duration: Option<f64>,
+    // LED panel resolution frames are scaled to for the output sinks
+    panel: PanelSize,
+    // JSON panel regions and the layers drawn in them, overrides `panel` when it sets a size
+    layout: Option<PathBuf>,
+    // Where panel frames go, `--sink png:<folder>`, `preview:<folder>`, `previewraw:<path>`,
+    // `raw:<path>`, `hub75:<path>`, `rawf:<path>`, `leds:<path>` or `bake:<file>`
+    sinks: Vec<SinkConfig>,
+    // Look of the simulated panels of `preview:` and `previewraw:` sinks
+    preview_style: Option<PreviewStyle>,
+    // CSV/JSON positions of LEDs off the panel grid, sampled for `leds:` sinks
+    pixel_maps: Vec<PathBuf>,
+    // JSON color correction per panel region, applied to the frames of the sinks
+    calibration: Option<PathBuf>,
+    // Test pattern shown instead of the face
+    pattern: Option<TestPattern>,
+    // Frames of a baked animation that repeat on playback
+    loop_points: Option<LoopPoints>,
+    // Baked animation to play to the sinks instead of rendering
+    play: Option<PathBuf>,
+    // Times the loop of a baked animation repeats, forever by default
+    loops: Option<u32>,
+    // JSON playlist of faces and expressions to cycle through while idle
+    playlist: Option<PathBuf>,
+    // JSON `RuntimeConfig`, applied over the flags
+    config: Option<PathBuf>,
+    // Reload the face, layout and config when their files change
+    watch: bool,
+}
+
+impl AppConfig {
+    /// Default config overridden by command line arguments
+    fn from_args() -> AppConfig {
+        let mut config = AppConfig {
+            width: 1920,
+            height: 1080,
+            capture_format: CaptureFormat::default(),
+            conversion: CaptureConversion::Clamp,
+            single_image: true,
+            // test_images in bevy folder is used here for example
+            // You should choose the path depending on your needs
+            output: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_images"),
+            face_scene: String::from("faces/default.gltf"),
+            settle_frames: 2,
+            ready_timeout: Duration::from_secs(30),
+            control: None,
+            audio: None,
+            audio_rate: 16000,
+            audio_smoothing: AudioSmoothing::default(),
+            eye_behaviour: EyeBehaviour::default(),
+            seed: 0,
+            deterministic: false,
+            fps: 60.0,
+            frames: None,
+            duration: None,
+            panel: PanelSize {
+                width: 128,
+                height: 32,
+            },
+            layout: None,
+            sinks: Vec::new(),
+            preview_style: None,
+            pixel_maps: Vec::new(),
+            calibration: None,
+            pattern: None,
+            loop_points: None,
+            play: None,
+            loops: None,
+            playlist: None,
+            config: None,
+            watch: false,
+        };
+
+        // Logging is not set up yet, so problems are reported straight to stderr
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--width" => parse_arg(&arg, args.next(), &mut config.width),
+                "--height" => parse_arg(&arg, args.next(), &mut config.height),
+                "--capture-format" => parse_arg(&arg, args.next(), &mut config.capture_format),
+                "--exposure" => {
+                    let mut exposure = 1.0;
+                    parse_arg(&arg, args.next(), &mut exposure);
+                    config.conversion = CaptureConversion::ToneMapped { exposure };
+                }
+                "--output" => parse_arg(&arg, args.next(), &mut config.output),
+                "--face" => parse_arg(&arg, args.next(), &mut config.face_scene),
+                "--settle-frames" => parse_arg(&arg, args.next(), &mut config.settle_frames),
+                "--ready-timeout" => {
+                    let mut seconds = config.ready_timeout.as_secs_f64();
+                    parse_arg(&arg, args.next(), &mut seconds);
+                    // Negative, infinite or too long to represent
+                    match Duration::try_from_secs_f64(seconds) {
+                        Ok(timeout) => config.ready_timeout = timeout,
+                        Err(_) => eprintln!("Invalid value for {arg}, keeping the default"),
+                    }
+                }
+                "--control" => config.control = args.next(),
+                "--audio" => config.audio = args.next(),
+                "--audio-rate" => {
+                    let mut audio_rate = config.audio_rate;
+                    parse_arg(&arg, args.next(), &mut audio_rate);
+                    // The analysis divides by the rate
+                    match audio_rate {
+                        0 => eprintln!("Invalid value for {arg}, keeping the default"),
+                        rate => config.audio_rate = rate,
+                    }
+                }
+                "--audio-attack" => {
+                    parse_arg(&arg, args.next(), &mut config.audio_smoothing.attack)
+                }
+                "--audio-release" => {
+                    parse_arg(&arg, args.next(), &mut config.audio_smoothing.release)
+                }
+                "--audio-gain" => parse_arg(&arg, args.next(), &mut config.audio_smoothing.gain),
+                "--seed" => parse_arg(&arg, args.next(), &mut config.seed),
+                "--deterministic" => config.deterministic = true,
+                "--fps" => parse_arg(&arg, args.next(), &mut config.fps),
+                "--frames" => {
+                    let mut frames = 1;
+                    parse_arg(&arg, args.next(), &mut frames);
+                    config.frames = Some(frames);
+                }
+                "--duration" => {
+                    let mut duration = 0.0;
+                    parse_arg(&arg, args.next(), &mut duration);
+                    config.duration = Some(duration);
+                }
+                "--blink-interval" => {
+                    parse_arg(&arg, args.next(), &mut config.eye_behaviour.blink_interval)
+                }
+                "--saccade-interval" => parse_arg(
+                    &arg,
+                    args.next(),
+                    &mut config.eye_behaviour.saccade_interval,
+                ),
+                "--panel" => parse_arg(&arg, args.next(), &mut config.panel),
+                "--layout" => config.layout = args.next().map(PathBuf::from),
+                "--sink" => match args.next().map(|sink| SinkConfig::from_arg(&sink)) {
+                    Some(Ok(sink)) => config.sinks.push(sink),
+                    Some(Err(e)) => eprintln!("Ignoring {arg}: {e}"),
+                    None => eprintln!("{arg} expects a value"),
+                },
+                "--preview-style" => {
+                    match args.next().map(|path| PreviewStyle::load(path.as_ref())) {
+                        Some(Ok(style)) => config.preview_style = Some(style),
+                        Some(Err(e)) => eprintln!("Ignoring {arg}: {e}"),
+                        None => eprintln!("{arg} expects a value"),
+                    }
+                }
+                "--pixel-map" => match args.next() {
+                    Some(path) => config.pixel_maps.push(PathBuf::from(path)),
+                    None => eprintln!("{arg} expects a value"),
+                },
+                "--calibration" => config.calibration = args.next().map(PathBuf::from),
+                "--pattern" => match args.next().map(|pattern| pattern.parse()) {
+                    Some(Ok(pattern)) => config.pattern = Some(pattern),
+                    Some(Err(e)) => eprintln!("Ignoring {arg}: {e}"),
+                    None => eprintln!("{arg} expects a value"),
+                },
+                "--loop" => {
+                    let mut loop_points = LoopPoints { start: 0, end: 0 };
+                    parse_arg(&arg, args.next(), &mut loop_points);
+                    config.loop_points = Some(loop_points);
+                }
+                "--config" => config.config = args.next().map(PathBuf::from),
+                "--watch" => config.watch = true,
+                "--playlist" => config.playlist = args.next().map(PathBuf::from),
+                "--play" => config.play = args.next().map(PathBuf::from),
+                "--loops" => {
+                    let mut loops = 0;
+                    parse_arg(&arg, args.next(), &mut loops);
+                    config.loops = Some(loops);
+                }
+                other => eprintln!("Ignoring unknown argument: {other}"),
+            }
+        }
+        if let Some(path) = config.config.clone() {
+            match RuntimeConfig::load(&path) {
+                Ok(runtime) => config.apply(runtime),
+                Err(e) => eprintln!("Ignoring config {e}"),
+            }
+        }
+        // Batch render, every frame of the animation stepped exactly and saved
+        if let Some(duration) = config.duration {
+            config.frames = Some((duration * config.fps).round().max(1.0) as u32);
+            config.deterministic = true;
+        }
+        for sink in config.sinks.iter_mut() {
+            match sink {
+                SinkConfig::Bake { loop_points, .. } => *loop_points = config.loop_points,
+                SinkConfig::Preview { style, .. } | SinkConfig::PreviewRaw { style, .. } => {
+                    if let Some(preview_style) = &config.preview_style {
+                        *style = preview_style.clone();
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(frames) = config.frames {
+            config.single_image = frames <= 1;
+        }
+        config
+    }
+
+    /// Values set in the config file replace the flags
+    fn apply(&mut self, runtime: RuntimeConfig) {
+        self.width = runtime.width.unwrap_or(self.width);
+        self.height = runtime.height.unwrap_or(self.height);
+        self.face_scene = runtime.face.unwrap_or(self.face_scene.clone());
+    }
+}
+
+/// Parses the value following `flag` into `target`, keeping the default on errors
+fn parse_arg<T: std::str::FromStr>(flag: &str, value: Option<String>, target: &mut T) {
+    match value.map(|value| value.parse()) {
+        Some(Ok(value)) => *target = value,
+        Some(Err(_)) => eprintln!("Invalid value for {flag}, keeping the default"),
+        None => eprintln!("{flag} expects a value"),
+    }
 }
 
 fn main() {
-    let config = AppConfig {
-        width: 1920,
-        height: 1080,
-        single_image: true,
+    let mut config = AppConfig::from_args();
+
+    let layout = match &config.layout {
+        Some(path) => match PanelLayout::load(path, config.panel) {
+            Ok(layout) => layout,
+            Err(e) => {
+                eprintln!("Failed to load panel layout {e}");
+                std::process::exit(1);
+            }
+        },
+        None => PanelLayout::single(config.panel),
+    };
+    for sink in config.sinks.iter_mut() {
+        sink.set_layout(&layout);
+    }
+    let calibration = match config.calibration.as_deref() {
+        Some(path) => match Calibration::load(path, &layout) {
+            Ok(calibration) => Some(calibration),
+            Err(e) => {
+                eprintln!("Failed to load calibration {e}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
     };
 
+    // Baked playback streams frames to the sinks, nothing is rendered
+    if let Some(play) = config.play {
+        App::new()
+            .add_plugins(MinimalPlugins)
+            .add_plugins(LogPlugin::default())
+            .add_plugins(BakePlaybackPlugin {
+                path: play,
+                sinks: config.sinks,
+                loops: config.loops,
+                calibration,
+            })
+            .run();
+        return;
+    }
+
+    let pixel_maps = match config
+        .pixel_maps
+        .iter()
+        .map(|path| PixelMap::load(path))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(pixel_maps) => pixel_maps,
+        Err(e) => {
+            eprintln!("Failed to load pixel map {e}");
+            std::process::exit(1);
+        }
+    };
+    let sinks = match OutputSinks::open(layout.size.width, layout.size.height, &config.sinks) {
+        Ok(sinks) => sinks
+            .with_pixel_maps(pixel_maps)
+            .with_calibration(calibration),
+        Err(e) => {
+            eprintln!("Failed to open output sink {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let playlist = match config.playlist.as_deref().map(Playlist::load).transpose() {
+        Ok(playlist) => playlist,
+        Err(e) => {
+            eprintln!("Failed to load playlist {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut scene_controller =
+        SceneController::new(config.width, config.height, config.single_image);
+    scene_controller.capture_format = config.capture_format;
+    scene_controller.frame_limit = config.frames;
+    scene_controller.every_frame = config.deterministic;
+    let frame_time = Duration::from_secs_f64(1.0 / config.fps.max(1.0));
+
     // setup frame capture
-    App::new()
-        .insert_resource(SceneController::new(
-            config.width,
-            config.height,
-            config.single_image,
+    let mut app = App::new();
+    app.insert_resource(scene_controller)
+        .insert_resource(FaceScene::new(config.face_scene))
+        .insert_resource(ReadinessGate::new(
+            config.settle_frames,
+            config.ready_timeout,
         ))
+        .insert_resource(config.eye_behaviour)
+        .insert_resource(RandomSeed(config.seed))
         .insert_resource(ClearColor(Color::srgb_u8(0, 0, 0)))
+        .insert_resource(layout)
         .add_plugins(
             DefaultPlugins
                 .set(ImagePlugin::default_nearest())
@@ -67,148 +423,301 @@ fn main() {
                 }),
         )
         .add_plugins(ImageCopyPlugin)
-        // ScheduleRunnerPlugin provides an alternative to the default bevy_winit app runner, which
-        // manages the loop without creating a window.
-        .add_plugins(ScheduleRunnerPlugin::run_loop(
-            // Run 60 times per second.
-            Duration::from_secs_f64(1.0 / 60.0),
-        ))
+        .add_plugins(ReadinessPlugin)
+        .add_plugins(ControlPlugin {
+            source: config.control.as_deref().map(ControlSource::from_arg),
+        })
+        .add_plugins(FaceAtlasPlugin)
+        .add_plugins(MorphPlugin)
+        .add_plugins(EyeBehaviourPlugin)
+        .add_plugins(LayersPlugin)
+        .add_plugins(PostProcessPlugin)
+        .add_plugins(SymmetryPlugin)
+        .add_plugins(LedTransferPlugin)
+        .add_plugins(SceneTransitionPlugin)
         .init_resource::<SceneController>()
         .add_systems(Startup, setup)
-        .add_systems(PostUpdate, save_frame)
-        .run();
+        .insert_resource(CaptureOutput {
+            folder: config.output,
+            sinks,
+            frame_duration: frame_time,
+            conversion: config.conversion,
+        })
+        .add_systems(PostUpdate, save_frame);
+
+    // A test pattern replaces the face, the face scene is never spawned
+    match config.pattern {
+        Some(pattern) => app.add_plugins(PatternScenePlugin { pattern }),
+        None => app.add_plugins(FaceScenePlugin),
+    };
+
+    if config.deterministic {
+        // Simulated time no longer depends on the wall clock, so frames are rendered
+        // as fast as possible
+        app.add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO))
+            .add_plugins(DeterministicPlugin { step: frame_time });
+    } else {
+        // ScheduleRunnerPlugin provides an alternative to the default bevy_winit app runner, which
+        // manages the loop without creating a window.
+        app.add_plugins(ScheduleRunnerPlugin::run_loop(frame_time));
+    }
+
+    if let Some(audio) = config.audio.as_deref() {
+        app.add_plugins(AudioInputPlugin {
+            source: PcmSource::from_arg(audio, config.audio_rate),
+            smoothing: config.audio_smoothing,
+        });
+    }
+
+    if config.watch {
+        app.add_plugins(HotReloadPlugin {
+            layout: config.layout,
+            config: config.config,
+        });
+    }
+
+    if let Some(playlist) = playlist.filter(|_| config.pattern.is_none()) {
+        app.add_plugins(PlaylistPlugin {
+            playlist,
+            deterministic: config.deterministic,
+        });
+    }
+
+    app.run();
 }
 
 fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut images: ResMut<Assets<Image>>,
     mut scene_controller: ResMut<SceneController>,
     render_device: Res<RenderDevice>,
 ) {
-    let render_target = ImageCopyPlugin::setup_render_target(
+    // Cameras of the face scene are pointed at this target once the scene is spawned,
+    // see `FaceScenePlugin`. Capture starts once the `ReadinessGate` opens.
+    ImageCopyPlugin::setup_render_target(
         &mut commands,
         &mut images,
         &render_device,
         &mut scene_controller,
-        // pre_roll_frames should be big enough for full scene render,
-        // but the bigger it is, the longer example will run.
-        // To visualize stages of scene rendering change this param to 0
-        // and change AppConfig::single_image to false in main
-        // Stages are:
-        // 1. Transparent image
-        // 2. Few black box images
-        // 3. Fully rendered scene images
-        // Exact number depends on device speed, device load and scene size
-        40,
         "main_scene".into(),
     );
+}
 
-    // Scene example for non black box picture
-    // circular base
-    commands.spawn((
-        Mesh3d(meshes.add(Circle::new(4.0))),
-        MeshMaterial3d(materials.add(Color::WHITE)),
-        Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
-    ));
-    // cube
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(1.0, 1.0, 1.0))),
-        MeshMaterial3d(materials.add(Color::srgb_u8(124, 144, 255))),
-        Transform::from_xyz(0.0, 0.5, 0.0),
-    ));
-    // light
-    commands.spawn((
-        PointLight {
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform::from_xyz(4.0, 8.0, 4.0),
-    ));
-
-    commands.spawn((
-        Camera3d::default(),
-        render_target,
-        Tonemapping::None,
-        Transform::from_xyz(-2.5, 4.5, 9.0).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
+/// Where `save_frame` writes to
+#[derive(Resource)]
+struct CaptureOutput {
+    // Numbered full resolution frames and their metadata
+    folder: PathBuf,
+    // Frames scaled to panel resolution
+    sinks: OutputSinks,
+    // How long each frame is shown on the panels
+    frame_duration: Duration,
+    // How captures wider than 8 bits are brought down to it
+    conversion: CaptureConversion,
+}
+
+/// Frames saved so far, reported when the capture is done
+#[derive(Default)]
+struct CaptureProgress {
+    file_number: u32,
+    // Wall clock, to report how long rendering took
+    started: Option<Instant>,
+    // Simulated time of the last saved frame
+    last_frame_time: f32,
 }
 
 // Takes from channel image content sent from render world and saves it to disk
 fn save_frame(
-    images_to_save: Query<&ImageToSave>,
+    images_to_save: Query<(&ImageToSave, &CaptureTarget, Has<PrimaryCaptureTarget>)>,
     receiver: Res<MainWorldReceiver>,
     mut images: ResMut<Assets<Image>>,
-    mut scene_controller: ResMut<SceneController>,
+    scene_controller: Res<SceneController>,
+    mut capture_output: ResMut<CaptureOutput>,
     mut app_exit_writer: MessageWriter<AppExit>,
-    mut file_number: Local<u32>,
+    mut progress: Local<CaptureProgress>,
 ) {
-    if let SceneState::Render(n) = scene_controller.state {
-        if n < 1 {
+    match scene_controller.state {
+        SceneState::Render => {
             // We don't want to block the main world on this,
             // so we use try_recv which attempts to receive without blocking
-            let mut image_data = Vec::new();
-            while let Ok(data) = receiver.try_recv() {
-                // image generation could be faster than saving to fs,
-                // that's why use only last of them
-                image_data = data;
+            let mut captured = Vec::new();
+            while let Ok(frame) = receiver.try_recv() {
+                // Frames rendered while the scene was still being built are dropped
+                if frame.metadata.frame.is_none() {
+                    continue;
+                }
+                if !scene_controller.every_frame {
+                    // image generation could be faster than saving to fs,
+                    // that's why use only last of them, per capture target
+                    captured.retain(|captured: &CapturedFrame| captured.target != frame.target);
+                }
+                captured.push(frame);
             }
-            if !image_data.is_empty() {
-                for image in images_to_save.iter() {
-                    // Fill correct data from channel to image
-                    let img_bytes = images.get_mut(image.id()).unwrap();
-
-                    // We need to ensure that this works regardless of the image dimensions
-                    // If the image became wider when copying from the texture to the buffer,
-                    // then the data is reduced to its original size when copying from the buffer to the image.
-                    let row_bytes = img_bytes.width() as usize
-                        * img_bytes.texture_descriptor.format.pixel_size().unwrap();
-                    let aligned_row_bytes = RenderDevice::align_copy_bytes_per_row(row_bytes);
-                    if row_bytes == aligned_row_bytes {
-                        img_bytes.data.as_mut().unwrap().clone_from(&image_data);
-                    } else {
-                        // shrink data to original image size
-                        img_bytes.data = Some(
-                            image_data
-                                .chunks(aligned_row_bytes)
-                                .take(img_bytes.height() as usize)
-                                .flat_map(|row| &row[..row_bytes.min(row.len())])
-                                .cloned()
-                                .collect(),
+            let frame_limit = match scene_controller.single_image {
+                true => Some(1),
+                false => scene_controller.frame_limit,
+            };
+            progress.started.get_or_insert_with(Instant::now);
+            for CapturedFrame {
+                data: image_data,
+                metadata,
+                target,
+                width,
+                height,
+                format,
+            } in captured
+            {
+                // The target was removed, resized or reformatted after the frame was rendered
+                let Ok((image, capture_target, primary)) = images_to_save.get(target) else {
+                    continue;
+                };
+                if capture_target.format != format {
+                    debug!(
+                        "Dropping a {format:?} frame of {}, now {:?}",
+                        capture_target.name, capture_target.format
+                    );
+                    continue;
+                }
+                // Fill correct data from channel to image
+                let img_bytes = images.get_mut(image.id()).unwrap();
+                if (img_bytes.width(), img_bytes.height()) != (width, height) {
+                    continue;
+                }
+
+                // We need to ensure that this works regardless of the image dimensions
+                // If the image became wider when copying from the texture to the buffer,
+                // then the data is reduced to its original size when copying from the buffer to the image.
+                let row_bytes = img_bytes.width() as usize
+                    * img_bytes.texture_descriptor.format.pixel_size().unwrap();
+                let aligned_row_bytes = RenderDevice::align_copy_bytes_per_row(row_bytes);
+                if row_bytes == aligned_row_bytes {
+                    img_bytes.data.as_mut().unwrap().clone_from(&image_data);
+                } else {
+                    // shrink data to original image size
+                    img_bytes.data = Some(
+                        image_data
+                            .chunks(aligned_row_bytes)
+                            .take(img_bytes.height() as usize)
+                            .flat_map(|row| &row[..row_bytes.min(row.len())])
+                            .cloned()
+                            .collect(),
+                    );
+                }
+
+                // Create RGBA Image Buffer, wider formats tone mapped or clamped to 8 bits
+                let pixels = img_bytes.data.as_deref().unwrap_or_default();
+                let img = match image::RgbaImage::from_raw(
+                    width,
+                    height,
+                    format.to_rgba8(pixels, capture_output.conversion),
+                ) {
+                    Some(img) => img,
+                    None => {
+                        error!(
+                            "Dropping a frame of {}, {} bytes are not a {width}x{height} {format:?} image",
+                            capture_target.name,
+                            pixels.len()
                         );
+                        continue;
                     }
+                };
 
-                    // Create RGBA Image Buffer
-                    let img = match img_bytes.clone().try_into_dynamic() {
-                        Ok(img) => img.to_rgba8(),
-                        Err(e) => panic!("Failed to create image buffer {e:?}"),
-                    };
-
-                    // Prepare directory for images, test_images in bevy folder is used here for example
-                    // You should choose the path depending on your needs
-                    let images_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_images");
-                    info!("Saving image to: {images_dir:?}");
+                // Extra targets are saved on their own, by frame index
+                if !primary {
+                    let images_dir = capture_output.folder.join(&capture_target.name);
                     std::fs::create_dir_all(&images_dir).unwrap();
+                    let frame = metadata.frame.unwrap_or_default();
+                    if let Err(e) = img.save(images_dir.join(format!("{frame:03}.png"))) {
+                        error!("Failed to save image of {}: {e}", capture_target.name);
+                    }
+                    continue;
+                }
 
-                    // Choose filename starting from 000.png
-                    let image_path = images_dir.join(format!("{:03}.png", file_number.deref()));
-                    *file_number.deref_mut() += 1;
+                // Prepare directory for images
+                let images_dir = capture_output.folder.clone();
+                info!("Saving image to: {images_dir:?}");
+                std::fs::create_dir_all(&images_dir).unwrap();
 
-                    // Finally saving image to file, this heavy blocking operation is kept here
-                    // for example simplicity, but in real app you should move it to a separate task
-                    if let Err(e) = img.save(image_path) {
-                        panic!("Failed to save image: {e}");
-                    };
+                // Choose filename starting from 000.png
+                let image_path = images_dir.join(format!("{:03}.png", progress.file_number));
+                // Metadata of the frame goes next to it, 000.json
+                let metadata_path = image_path.with_extension("json");
+                progress.file_number += 1;
+                progress.last_frame_time = metadata.time;
+
+                // Finally saving image to file, this heavy blocking operation is kept here
+                // for example simplicity, but in real app you should move it to a separate task
+                if let Err(e) = img.save(image_path) {
+                    panic!("Failed to save image: {e}");
+                };
+                if let Err(e) =
+                    std::fs::write(metadata_path, serde_json::to_vec_pretty(&metadata).unwrap())
+                {
+                    panic!("Failed to save frame metadata: {e}");
+                };
+
+                // Same frame at panel resolution for the LED outputs
+                let CaptureOutput {
+                    sinks,
+                    frame_duration,
+                    ..
+                } = &mut *capture_output;
+                if !sinks.is_empty() {
+                    let panel_frame = PanelFrame::from_rgba(
+                        img.as_raw(),
+                        img.width(),
+                        img.height(),
+                        sinks.width,
+                        sinks.height,
+                    );
+                    for e in sinks.write_frame(&panel_frame, *frame_duration) {
+                        error!("{e}");
+                    }
+                    // LEDs off the panel grid, sampled from the full resolution frame
+                    for e in sinks.write_leds(
+                        img.as_raw(),
+                        img.width(),
+                        img.height(),
+                        4,
+                        *frame_duration,
+                    ) {
+                        error!("{e}");
+                    }
+                    // Linear light with its headroom, for sinks tone mapping on their own
+                    if sinks.wants_linear() {
+                        let linear_frame = LinearPanelFrame::from_linear(
+                            &capture_target.format.to_linear(pixels),
+                            width,
+                            height,
+                            sinks.width,
+                            sinks.height,
+                        );
+                        for e in sinks.write_linear_frame(&linear_frame, *frame_duration) {
+                            error!("{e}");
+                        }
+                    }
                 }
-                if scene_controller.single_image {
+                if frame_limit.is_some_and(|frame_limit| progress.file_number >= frame_limit) {
+                    info!(
+                        "Wrote {} frames to {:?}, last one at {:.3}s of animation, in {:.1}s",
+                        progress.file_number,
+                        capture_output.folder,
+                        progress.last_frame_time,
+                        progress
+                            .started
+                            .map_or(0.0, |started| started.elapsed().as_secs_f32())
+                    );
+                    for e in capture_output.sinks.finish() {
+                        error!("{e}");
+                    }
                     app_exit_writer.write(AppExit::Success);
+                    break;
                 }
             }
-        } else {
-            // clears channel for skipped frames
+        }
+        SceneState::BuildScene => {
+            // clears channel for frames rendered before the scene was ready
             while receiver.try_recv().is_ok() {}
-            scene_controller.state = SceneState::Render(n - 1);
         }
     }
 }
@@ -0,0 +1,280 @@
+use bevy::platform::collections::HashMap;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::output::{PanelFrame, PanelLayout};
+
+/// Color gain of a panel, per channel or a full matrix mixing them
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Gain {
+    Channels([f32; 3]),
+    // Rows produce red, green and blue from the input red, green and blue
+    Matrix([[f32; 3]; 3]),
+}
+
+impl Gain {
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Gain::Channels([r, g, b]) => [[r, 0.0, 0.0], [0.0, g, 0.0], [0.0, 0.0, b]],
+            Gain::Matrix(matrix) => matrix,
+        }
+    }
+}
+
+/// Correction of one panel, so panels of different batches match
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PanelCalibration {
+    gain: Gain,
+    // Exponent of the panel response, above 1 darkens the mid tones
+    gamma: f32,
+    // Lowest 8 bit drive per channel at which the LEDs light, black stays 0
+    black_offset: [f32; 3],
+    max_brightness: f32,
+}
+
+impl Default for PanelCalibration {
+    fn default() -> Self {
+        PanelCalibration {
+            gain: Gain::Channels([1.0; 3]),
+            gamma: 1.0,
+            black_offset: [0.0; 3],
+            max_brightness: 1.0,
+        }
+    }
+}
+
+impl PanelCalibration {
+    fn validate(&self) -> Result<(), String> {
+        if !self
+            .gain
+            .matrix()
+            .iter()
+            .flatten()
+            .all(|gain| gain.is_finite())
+        {
+            return Err(String::from("gain must be finite"));
+        }
+        if !self.gamma.is_finite() || self.gamma <= 0.0 {
+            return Err(format!("gamma {} must be positive", self.gamma));
+        }
+        if !(0.0..=1.0).contains(&self.max_brightness) {
+            return Err(format!(
+                "max_brightness {} is outside 0..1",
+                self.max_brightness
+            ));
+        }
+        if !self
+            .black_offset
+            .iter()
+            .all(|offset| (0.0..=255.0).contains(offset))
+        {
+            return Err(format!(
+                "black_offset {:?} is outside 0..255",
+                self.black_offset
+            ));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let input = rgb.map(|value| value as f32 / 255.0);
+        let matrix = self.gain.matrix();
+        std::array::from_fn(|channel| {
+            let value = matrix[channel]
+                .iter()
+                .zip(input)
+                .map(|(gain, value)| gain * value)
+                .sum::<f32>()
+                .clamp(0.0, 1.0)
+                * self.max_brightness;
+            if value <= 0.0 {
+                return 0;
+            }
+            let offset = self.black_offset[channel];
+            (offset + (255.0 - offset) * value.powf(self.gamma))
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+    }
+}
+
+/// Calibration of every panel, keyed by the region names of the [`PanelLayout`].
+///
+/// ```json
+/// {
+///     "left": { "gain": [1.0, 0.92, 0.95], "gamma": 1.1, "black_offset": [2, 2, 3] },
+///     "right": { "gain": [[0.98, 0.02, 0], [0, 0.9, 0], [0, 0, 1]], "max_brightness": 0.9 }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    // Region rect in panel pixels and its correction
+    panels: Vec<([u32; 4], PanelCalibration)>,
+}
+
+impl Calibration {
+    pub fn load(path: &Path, layout: &PanelLayout) -> Result<Calibration, String> {
+        let file = std::fs::read_to_string(path).map_err(|e| format!("{path:?}: {e}"))?;
+        let profiles: HashMap<String, PanelCalibration> =
+            serde_json::from_str(&file).map_err(|e| format!("{path:?}: {e}"))?;
+        let mut panels = Vec::new();
+        for (name, profile) in profiles {
+            let region = layout
+                .regions
+                .iter()
+                .find(|region| region.name == name)
+                .ok_or_else(|| format!("{path:?}: the layout has no panel `{name}`"))?;
+            profile
+                .validate()
+                .map_err(|e| format!("{path:?}: panel `{name}`: {e}"))?;
+            panels.push((region.rect, profile));
+        }
+        Ok(Calibration { panels })
+    }
+
+    /// The frame as the calibrated panels should be driven, pixels outside every calibrated
+    /// panel are left as they are
+    pub fn apply(&self, frame: &PanelFrame) -> PanelFrame {
+        let mut calibrated = frame.clone();
+        for ([x, y, width, height], profile) in self.panels.iter() {
+            let rows = (*y).min(frame.height)..(y + height).min(frame.height);
+            let columns = (*x).min(frame.width)..(x + width).min(frame.width);
+            for row in rows {
+                for column in columns.clone() {
+                    let offset = (row * frame.width + column) as usize * 3;
+                    let pixel = &mut calibrated.rgb[offset..offset + 3];
+                    pixel.copy_from_slice(&profile.apply([pixel[0], pixel[1], pixel[2]]));
+                }
+            }
+        }
+        calibrated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::default;
+
+    #[test]
+    fn default_leaves_colors_alone() {
+        let profile = PanelCalibration::default();
+        for rgb in [[0, 0, 0], [255, 255, 255], [12, 128, 250]] {
+            assert_eq!(profile.apply(rgb), rgb);
+        }
+    }
+
+    #[test]
+    fn gain_channels_and_matrix() {
+        let channels = PanelCalibration {
+            gain: Gain::Channels([1.0, 0.5, 0.0]),
+            ..default()
+        };
+        assert_eq!(channels.apply([200, 200, 200]), [200, 100, 0]);
+
+        // Red leaks into green, blue is doubled and clipped
+        let matrix = PanelCalibration {
+            gain: Gain::Matrix([[1.0, 0.0, 0.0], [0.2, 1.0, 0.0], [0.0, 0.0, 2.0]]),
+            ..default()
+        };
+        assert_eq!(matrix.apply([255, 0, 200]), [255, 51, 255]);
+    }
+
+    #[test]
+    fn black_offset_gamma_and_brightness() {
+        let offset = PanelCalibration {
+            black_offset: [10.0, 0.0, 0.0],
+            ..default()
+        };
+        // Black stays off, the darkest lit value starts at the offset
+        assert_eq!(offset.apply([0, 0, 0]), [0, 0, 0]);
+        assert_eq!(offset.apply([1, 1, 1]), [11, 1, 1]);
+        assert_eq!(offset.apply([255, 255, 255]), [255, 255, 255]);
+
+        let gamma = PanelCalibration {
+            gamma: 2.0,
+            ..default()
+        };
+        assert_eq!(gamma.apply([255, 128, 0]), [255, 64, 0]);
+
+        let dimmed = PanelCalibration {
+            max_brightness: 0.5,
+            ..default()
+        };
+        assert_eq!(dimmed.apply([255, 128, 0]), [128, 64, 0]);
+    }
+
+    #[test]
+    fn validation() {
+        assert_eq!(PanelCalibration::default().validate(), Ok(()));
+        for invalid in [
+            PanelCalibration {
+                gamma: 0.0,
+                ..default()
+            },
+            PanelCalibration {
+                gamma: f32::NAN,
+                ..default()
+            },
+            PanelCalibration {
+                gamma: f32::INFINITY,
+                ..default()
+            },
+            PanelCalibration {
+                max_brightness: -0.1,
+                ..default()
+            },
+            PanelCalibration {
+                max_brightness: 1.5,
+                ..default()
+            },
+            PanelCalibration {
+                max_brightness: f32::NAN,
+                ..default()
+            },
+            PanelCalibration {
+                black_offset: [0.0, 256.0, 0.0],
+                ..default()
+            },
+            PanelCalibration {
+                black_offset: [-1.0, 0.0, 0.0],
+                ..default()
+            },
+            PanelCalibration {
+                black_offset: [0.0, 0.0, f32::NAN],
+                ..default()
+            },
+            PanelCalibration {
+                gain: Gain::Channels([1.0, f32::INFINITY, 1.0]),
+                ..default()
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn only_calibrated_panels_change() {
+        let dark = PanelCalibration {
+            gain: Gain::Channels([0.0; 3]),
+            ..default()
+        };
+        // The second rect reaches past the frame and is clipped to it
+        let calibration = Calibration {
+            panels: vec![([0, 0, 1, 1], dark.clone()), ([3, 1, 4, 4], dark)],
+        };
+        let mut frame = PanelFrame::new(4, 2);
+        frame.rgb.fill(100);
+
+        let calibrated = calibration.apply(&frame);
+        let lit = |x: u32, y: u32| calibrated.rgb[(y * 4 + x) as usize * 3] != 0;
+        let expected = [[false, true, true, true], [true, true, true, false]];
+        for (y, row) in expected.iter().enumerate() {
+            for (x, expected) in row.iter().enumerate() {
+                assert_eq!(lit(x as u32, y as u32), *expected, "{x},{y}");
+            }
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use serde::Deserialize;
+
+use crate::output::{PanelFrame, PanelLayout};
+
+/// Clockwise turn of a panel as mounted, from the orientation its driver scans it in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum PanelRotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl TryFrom<u32> for PanelRotation {
+    type Error = String;
+
+    fn try_from(degrees: u32) -> Result<Self, Self::Error> {
+        match degrees {
+            0 => Ok(PanelRotation::None),
+            90 => Ok(PanelRotation::Cw90),
+            180 => Ok(PanelRotation::Cw180),
+            270 => Ok(PanelRotation::Cw270),
+            _ => Err(format!("rotation {degrees} is not 0, 90, 180 or 270")),
+        }
+    }
+}
+
+impl PanelRotation {
+    /// Size of the panel as its driver sees it, from its size as mounted
+    pub fn native_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            PanelRotation::None | PanelRotation::Cw180 => (width, height),
+            PanelRotation::Cw90 | PanelRotation::Cw270 => (height, width),
+        }
+    }
+
+    /// Where a pixel of the panel in its native orientation is seen once mounted
+    fn mounted(self, x: u32, y: u32, native_width: u32, native_height: u32) -> (u32, u32) {
+        match self {
+            PanelRotation::None => (x, y),
+            PanelRotation::Cw90 => (native_height - 1 - y, x),
+            PanelRotation::Cw180 => (native_width - 1 - x, native_height - 1 - y),
+            PanelRotation::Cw270 => (y, native_width - 1 - x),
+        }
+    }
+}
+
+/// Multiplexing of a panel, how the rows the driver shifts out land on the LEDs.
+///
+/// `stripe` and `checkered` are the 1/8 scan outdoor panels of rpi-rgb-led-matrix: the driver
+/// sees them twice as wide and half as high, each shifted row lighting two rows a quarter of
+/// the panel apart, in halves (`stripe`) or alternating quarters of the row (`checkered`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanOrder {
+    #[default]
+    Direct,
+    Stripe,
+    Checkered,
+}
+
+impl ScanOrder {
+    /// `width` and `height` of the panel in its native orientation
+    pub fn validate(self, width: u32, height: u32) -> Result<(), String> {
+        match self {
+            ScanOrder::Direct => Ok(()),
+            ScanOrder::Stripe | ScanOrder::Checkered if !height.is_multiple_of(4) => Err(format!(
+                "{self:?} scan needs a panel height divisible by 4, not {height}"
+            )),
+            ScanOrder::Checkered if !width.is_multiple_of(2) => Err(format!(
+                "Checkered scan needs an even panel width, not {width}"
+            )),
+            ScanOrder::Stripe | ScanOrder::Checkered => Ok(()),
+        }
+    }
+
+    /// Size of the panel in the chain buffer
+    fn driver_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            ScanOrder::Direct => (width, height),
+            ScanOrder::Stripe | ScanOrder::Checkered => (width * 2, height / 2),
+        }
+    }
+
+    /// Position in the chain buffer of a native panel pixel
+    fn driver_position(self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        if self == ScanOrder::Direct {
+            return (x, y);
+        }
+        let (half, quarter) = (height / 2, height / 4);
+        let top = y % half < quarter;
+        let driver_y = y / half * quarter + y % quarter;
+        let driver_x = match (self, top, x < width / 2) {
+            (ScanOrder::Stripe, true, _) => x + width,
+            (ScanOrder::Stripe, false, _) => x,
+            (_, true, true) => x + width / 2,
+            (_, true, false) => x + width,
+            (_, false, true) => x,
+            (_, false, false) => x + width / 2,
+        };
+        (driver_x, driver_y)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MappedPanel {
+    // Where the panel is mounted, in panel pixels
+    rect: [u32; 4],
+    rotation: PanelRotation,
+    scan: ScanOrder,
+    // Index into the chain buffers and first column of the panel in it
+    chain: usize,
+    column: u32,
+}
+
+impl MappedPanel {
+    /// Every LED of the panel as its position in the frame and in the chain buffer
+    fn pixels(&self) -> impl Iterator<Item = ((u32, u32), (u32, u32))> + '_ {
+        let [x, y, width, height] = self.rect;
+        let (native_width, native_height) = self.rotation.native_size(width, height);
+        (0..native_height).flat_map(move |native_y| {
+            (0..native_width).map(move |native_x| {
+                let (mounted_x, mounted_y) =
+                    self.rotation
+                        .mounted(native_x, native_y, native_width, native_height);
+                let (driver_x, driver_y) =
+                    self.scan
+                        .driver_position(native_x, native_y, native_width, native_height);
+                (
+                    (x + mounted_x, y + mounted_y),
+                    (self.column + driver_x, driver_y),
+                )
+            })
+        })
+    }
+}
+
+/// How the HUB75 driver sees the panels: every chain is one buffer, its panels side by side
+/// in wiring order, each in its native orientation and scan order.
+#[derive(Debug, Clone)]
+pub struct Hub75Mapping {
+    width: u32,
+    height: u32,
+    panels: Vec<MappedPanel>,
+    // Size of every chain buffer, by ascending chain number
+    chains: Vec<(u32, u32)>,
+}
+
+impl Hub75Mapping {
+    /// Every region of the layout is a panel, the regions of a chain are listed in wiring order
+    pub fn new(layout: &PanelLayout) -> Hub75Mapping {
+        let mut numbers = layout
+            .regions
+            .iter()
+            .map(|region| region.chain)
+            .collect::<Vec<_>>();
+        numbers.sort_unstable();
+        numbers.dedup();
+
+        let mut chains = vec![(0, 0); numbers.len()];
+        let panels = layout
+            .regions
+            .iter()
+            .map(|region| {
+                let [_, _, width, height] = region.rect;
+                let (native_width, native_height) = region.rotation.native_size(width, height);
+                let (driver_width, driver_height) =
+                    region.scan.driver_size(native_width, native_height);
+                let chain = numbers.binary_search(&region.chain).unwrap_or_default();
+                let (chain_width, chain_height) = &mut chains[chain];
+                let column = *chain_width;
+                *chain_width += driver_width;
+                *chain_height = (*chain_height).max(driver_height);
+                MappedPanel {
+                    rect: region.rect,
+                    rotation: region.rotation,
+                    scan: region.scan,
+                    chain,
+                    column,
+                }
+            })
+            .collect();
+        Hub75Mapping {
+            width: layout.size.width,
+            height: layout.size.height,
+            panels,
+            chains,
+        }
+    }
+
+    /// The chain buffers the driver shifts out for a frame, by ascending chain number
+    pub fn map(&self, frame: &PanelFrame) -> Result<Vec<PanelFrame>, String> {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            return Err(format!(
+                "the frame is {}x{} but the panels are {}x{}",
+                frame.width, frame.height, self.width, self.height
+            ));
+        }
+        let mut chains = self
+            .chains
+            .iter()
+            .map(|(width, height)| PanelFrame::new(*width, *height))
+            .collect::<Vec<_>>();
+        for panel in self.panels.iter() {
+            let chain = &mut chains[panel.chain];
+            for ((x, y), (driver_x, driver_y)) in panel.pixels() {
+                let from = (y * frame.width + x) as usize * 3;
+                let to = (driver_y * chain.width + driver_x) as usize * 3;
+                chain.rgb[to..to + 3].copy_from_slice(&frame.rgb[from..from + 3]);
+            }
+        }
+        Ok(chains)
+    }
+
+    /// What the mounted panels show when the driver shifts out `chains`, dark where there is
+    /// no panel
+    pub fn display(&self, chains: &[PanelFrame]) -> PanelFrame {
+        let mut frame = PanelFrame::new(self.width, self.height);
+        for panel in self.panels.iter() {
+            let chain = &chains[panel.chain];
+            for ((x, y), (driver_x, driver_y)) in panel.pixels() {
+                let from = (driver_y * chain.width + driver_x) as usize * 3;
+                let to = (y * frame.width + x) as usize * 3;
+                frame.rgb[to..to + 3].copy_from_slice(&chain.rgb[from..from + 3]);
+            }
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::PanelSize;
+
+    /// A frame whose every pixel holds its own position
+    fn numbered(width: u32, height: u32) -> PanelFrame {
+        let mut frame = PanelFrame::new(width, height);
+        for (index, pixel) in frame.rgb.chunks_exact_mut(3).enumerate() {
+            pixel.copy_from_slice(&[
+                (index % width as usize) as u8,
+                (index / width as usize) as u8,
+                1,
+            ]);
+        }
+        frame
+    }
+
+    fn pixel(frame: &PanelFrame, x: u32, y: u32) -> [u8; 3] {
+        let offset = (y * frame.width + x) as usize * 3;
+        [
+            frame.rgb[offset],
+            frame.rgb[offset + 1],
+            frame.rgb[offset + 2],
+        ]
+    }
+
+    fn layout(width: u32, height: u32) -> PanelLayout {
+        PanelLayout::single(PanelSize { width, height })
+    }
+
+    #[test]
+    fn rotation_turns_the_panel_clockwise() {
+        let mut layout = layout(4, 2);
+        for (rotation, first, last) in [
+            // Native top left pixel, native bottom right pixel, as mounted
+            (PanelRotation::None, [0, 0], [3, 1]),
+            (PanelRotation::Cw180, [3, 1], [0, 0]),
+        ] {
+            layout.regions[0].rotation = rotation;
+            let chains = Hub75Mapping::new(&layout).map(&numbered(4, 2)).unwrap();
+            assert_eq!((chains[0].width, chains[0].height), (4, 2));
+            assert_eq!(pixel(&chains[0], 0, 0)[..2], first);
+            assert_eq!(pixel(&chains[0], 3, 1)[..2], last);
+        }
+
+        // Mounted 4x2, natively 2x4
+        for (rotation, first, last) in [
+            (PanelRotation::Cw90, [3, 0], [0, 1]),
+            (PanelRotation::Cw270, [0, 1], [3, 0]),
+        ] {
+            layout.regions[0].rotation = rotation;
+            let chains = Hub75Mapping::new(&layout).map(&numbered(4, 2)).unwrap();
+            assert_eq!((chains[0].width, chains[0].height), (2, 4));
+            assert_eq!(pixel(&chains[0], 0, 0)[..2], first);
+            assert_eq!(pixel(&chains[0], 1, 3)[..2], last);
+        }
+    }
+
+    #[test]
+    fn chains_follow_wiring_order() {
+        // Three 2x2 panels, the right one wired first on chain 3, the others on chain 1
+        let mut layout = layout(6, 2);
+        layout.regions[0].rect = [0, 0, 2, 2];
+        layout.regions[0].chain = 3;
+        let mut middle = layout.regions[0].clone();
+        middle.rect = [2, 0, 2, 2];
+        middle.chain = 1;
+        let mut right = middle.clone();
+        right.rect = [4, 0, 2, 2];
+        layout.regions.insert(0, right);
+        layout.regions.push(middle);
+
+        let chains = Hub75Mapping::new(&layout).map(&numbered(6, 2)).unwrap();
+        assert_eq!(chains.len(), 2);
+        // Chain 1 first, the right panel then the middle one
+        assert_eq!((chains[0].width, chains[0].height), (4, 2));
+        assert_eq!(pixel(&chains[0], 0, 0)[..2], [4, 0]);
+        assert_eq!(pixel(&chains[0], 2, 1)[..2], [2, 1]);
+        assert_eq!((chains[1].width, chains[1].height), (2, 2));
+        assert_eq!(pixel(&chains[1], 1, 1)[..2], [1, 1]);
+    }
+
+    #[test]
+    fn scan_orders_fold_the_panel() {
+        let mut layout = layout(4, 8);
+        layout.regions[0].scan = ScanOrder::Stripe;
+        let chains = Hub75Mapping::new(&layout).map(&numbered(4, 8)).unwrap();
+        assert_eq!((chains[0].width, chains[0].height), (8, 4));
+        // Rows 0 and 2 share a shifted row, the upper one in its second half
+        assert_eq!(pixel(&chains[0], 5, 0)[..2], [1, 0]);
+        assert_eq!(pixel(&chains[0], 1, 0)[..2], [1, 2]);
+        // Rows 5 and 7 of the lower half
+        assert_eq!(pixel(&chains[0], 7, 3)[..2], [3, 5]);
+        assert_eq!(pixel(&chains[0], 3, 3)[..2], [3, 7]);
+
+        layout.regions[0].scan = ScanOrder::Checkered;
+        let chains = Hub75Mapping::new(&layout).map(&numbered(4, 8)).unwrap();
+        // Quarters of a shifted row: lower left, upper left, lower right, upper right
+        let row = (0..8)
+            .map(|x| pixel(&chains[0], x, 1)[..2].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            row,
+            [
+                [0, 3],
+                [1, 3],
+                [0, 1],
+                [1, 1],
+                [2, 3],
+                [3, 3],
+                [2, 1],
+                [3, 1]
+            ]
+        );
+    }
+
+    #[test]
+    fn display_undoes_the_mapping() {
+        let mut layout = layout(24, 8);
+        layout.regions[0].rect = [0, 0, 8, 8];
+        layout.regions[0].scan = ScanOrder::Checkered;
+        let mut rotated = layout.regions[0].clone();
+        rotated.rect = [8, 0, 8, 4];
+        rotated.rotation = PanelRotation::Cw90;
+        rotated.scan = ScanOrder::Stripe;
+        let mut other_chain = layout.regions[0].clone();
+        other_chain.rect = [16, 2, 6, 4];
+        other_chain.rotation = PanelRotation::Cw180;
+        other_chain.scan = ScanOrder::Direct;
+        other_chain.chain = 1;
+        layout.regions.extend([rotated, other_chain]);
+
+        let frame = numbered(24, 8);
+        let mapping = Hub75Mapping::new(&layout);
+        let shown = mapping.display(&mapping.map(&frame).unwrap());
+        for y in 0..8 {
+            for x in 0..24 {
+                let on_panel = x < 8
+                    || ((8..16).contains(&x) && y < 4)
+                    || ((16..22).contains(&x) && (2..6).contains(&y));
+                let expected = match on_panel {
+                    true => pixel(&frame, x, y),
+                    false => [0; 3],
+                };
+                assert_eq!(pixel(&shown, x, y), expected, "at {x}, {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn mismatched_frames_and_scans_are_rejected() {
+        let mapping = Hub75Mapping::new(&layout(4, 4));
+        assert!(mapping.map(&PanelFrame::new(4, 4)).is_ok());
+        assert!(mapping.map(&PanelFrame::new(8, 4)).is_err());
+
+        assert!(ScanOrder::Direct.validate(3, 3).is_ok());
+        assert!(ScanOrder::Stripe.validate(3, 8).is_ok());
+        assert!(ScanOrder::Stripe.validate(4, 6).is_err());
+        assert!(ScanOrder::Checkered.validate(3, 8).is_err());
+        assert!(PanelRotation::try_from(45).is_err());
+        assert_eq!(PanelRotation::try_from(270), Ok(PanelRotation::Cw270));
+    }
+}
@@ -0,0 +1,16 @@
+mod calibration;
+pub use calibration::Calibration;
+mod hub75_mapping;
+pub use hub75_mapping::{Hub75Mapping, PanelRotation, ScanOrder};
+mod output_sink;
+pub use output_sink::{
+    LedFrame, LinearPanelFrame, OutputSink, OutputSinks, PanelFrame, PanelSize, SinkConfig,
+};
+mod panel_layout;
+pub use panel_layout::PanelLayout;
+mod pixel_map;
+pub use pixel_map::PixelMap;
+mod png_sink;
+mod preview_sink;
+pub use preview_sink::PreviewStyle;
+mod raw_sink;
@@ -0,0 +1,449 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    bake::{BakeSink, LoopPoints},
+    output::{
+        Calibration, Hub75Mapping, PanelLayout, PixelMap,
+        png_sink::PngSink,
+        preview_sink::{PreviewSink, PreviewStyle},
+        raw_sink::{RawFloatSink, RawHub75Sink, RawLedSink, RawSink},
+    },
+};
+
+/// One frame at panel resolution, tightly packed 8 bit RGB rows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanelFrame {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+impl PanelFrame {
+    pub fn new(width: u32, height: u32) -> PanelFrame {
+        PanelFrame {
+            width,
+            height,
+            rgb: vec![0; width as usize * height as usize * 3],
+        }
+    }
+
+    /// Box filters a captured RGBA image down (or nearest samples it up) to panel resolution
+    pub fn from_rgba(
+        rgba: &[u8],
+        source_width: u32,
+        source_height: u32,
+        width: u32,
+        height: u32,
+    ) -> PanelFrame {
+        let mut frame = PanelFrame::new(width, height);
+        let source_width = source_width as usize;
+        for y in 0..height as usize {
+            let y0 = y * source_height as usize / height as usize;
+            let y1 = ((y + 1) * source_height as usize / height as usize).max(y0 + 1);
+            for x in 0..width as usize {
+                let x0 = x * source_width / width as usize;
+                let x1 = ((x + 1) * source_width / width as usize).max(x0 + 1);
+                let mut sum = [0u32; 3];
+                for row in y0..y1 {
+                    for pixel in rgba[(row * source_width + x0) * 4..(row * source_width + x1) * 4]
+                        .chunks_exact(4)
+                    {
+                        for (sum, value) in sum.iter_mut().zip(pixel) {
+                            *sum += *value as u32;
+                        }
+                    }
+                }
+                let count = ((y1 - y0) * (x1 - x0)) as u32;
+                let offset = (y * width as usize + x) * 3;
+                for (channel, sum) in sum.iter().enumerate() {
+                    frame.rgb[offset + channel] = ((sum + count / 2) / count) as u8;
+                }
+            }
+        }
+        frame
+    }
+}
+
+/// One frame at panel resolution before it is brought to 8 bits, tightly packed linear RGB
+/// rows that keep values above 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearPanelFrame {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<f32>,
+}
+
+impl LinearPanelFrame {
+    /// Box filters a linear RGBA capture down (or nearest samples it up) to panel resolution
+    pub fn from_linear(
+        rgba: &[[f32; 4]],
+        source_width: u32,
+        source_height: u32,
+        width: u32,
+        height: u32,
+    ) -> LinearPanelFrame {
+        let mut rgb = Vec::with_capacity(width as usize * height as usize * 3);
+        let source_width = source_width as usize;
+        for y in 0..height as usize {
+            let y0 = y * source_height as usize / height as usize;
+            let y1 = ((y + 1) * source_height as usize / height as usize).max(y0 + 1);
+            for x in 0..width as usize {
+                let x0 = x * source_width / width as usize;
+                let x1 = ((x + 1) * source_width / width as usize).max(x0 + 1);
+                let mut sum = [0.0f32; 3];
+                for row in y0..y1 {
+                    for pixel in &rgba[row * source_width + x0..row * source_width + x1] {
+                        for (sum, value) in sum.iter_mut().zip(pixel) {
+                            *sum += *value;
+                        }
+                    }
+                }
+                let count = ((y1 - y0) * (x1 - x0)) as f32;
+                rgb.extend(sum.map(|sum| sum / count));
+            }
+        }
+        LinearPanelFrame { width, height, rgb }
+    }
+}
+
+/// Colors of the LEDs of one pixel map, 8 bit RGB in the order of the map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedFrame {
+    pub map: String,
+    pub rgb: Vec<u8>,
+}
+
+/// Resolution of the LED panels, `128x32` on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PanelSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::str::FromStr for PanelSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("`{s}` is not a size like `128x32`"))?;
+        let parse = |value: &str| match value.parse() {
+            Ok(0) | Err(_) => Err(format!("`{value}` is not a valid size")),
+            Ok(value) => Ok(value),
+        };
+        Ok(PanelSize {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+/// Destination for panel frames: the LED driver, a preview, a baked animation...
+pub trait OutputSink: Send + Sync {
+    /// `duration` is how long the frame is meant to be shown
+    fn write_frame(&mut self, frame: &PanelFrame, duration: Duration) -> Result<(), String>;
+
+    /// The same frame in linear light with its headroom, for sinks doing their own tone
+    /// mapping. Only rendered frames have one, baked playback is 8 bit.
+    fn write_linear_frame(
+        &mut self,
+        _frame: &LinearPanelFrame,
+        _duration: Duration,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Whether `write_linear_frame` is worth computing the linear frame for
+    fn wants_linear(&self) -> bool {
+        false
+    }
+
+    /// LEDs off the panel grid, one frame per pixel map in the order they were given.
+    /// Only rendered frames are sampled for them.
+    fn write_leds(&mut self, _leds: &[LedFrame], _duration: Duration) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Whether `write_leds` is worth sampling the pixel maps for
+    fn wants_leds(&self) -> bool {
+        false
+    }
+
+    /// Whether the sink drives panels and takes frames with their calibration applied
+    fn calibrated(&self) -> bool {
+        true
+    }
+
+    /// Flushes whatever the sink buffered, called once when output ends
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Output sink selected on the command line as `kind:target`
+#[derive(Debug, Clone)]
+pub enum SinkConfig {
+    // Numbered PNG files in a folder
+    Png(PathBuf),
+    // Numbered PNG files of the panels as they would look lit, in a folder. Drawn from what
+    // the panels show of the HUB75 mapping of the frame once the layout set one
+    Preview {
+        folder: PathBuf,
+        style: PreviewStyle,
+        mapping: Option<Hub75Mapping>,
+    },
+    // The same preview as raw RGB frames for a preview server, same targets as `Raw`
+    PreviewRaw {
+        path: PathBuf,
+        style: PreviewStyle,
+        mapping: Option<Hub75Mapping>,
+    },
+    // Raw RGB frames to a file, a named pipe or stdout (`-`)
+    Raw(PathBuf),
+    // Raw RGB of the HUB75 chain buffers, same targets as `Raw`. The mapping is set from the
+    // layout before opening
+    Hub75 {
+        path: PathBuf,
+        mapping: Option<Hub75Mapping>,
+    },
+    // Raw linear RGB frames as little endian f32, same targets as `Raw`
+    RawFloat(PathBuf),
+    // Raw RGB of the pixel map LEDs, every map one after another, same targets as `Raw`
+    Leds(PathBuf),
+    // Baked animation file
+    Bake {
+        path: PathBuf,
+        loop_points: Option<LoopPoints>,
+    },
+}
+
+impl std::fmt::Display for SinkConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SinkConfig::Png(folder) => write!(f, "png:{}", folder.display()),
+            SinkConfig::Preview { folder, .. } => write!(f, "preview:{}", folder.display()),
+            SinkConfig::PreviewRaw { path, .. } => write!(f, "previewraw:{}", path.display()),
+            SinkConfig::Raw(path) => write!(f, "raw:{}", path.display()),
+            SinkConfig::Hub75 { path, .. } => write!(f, "hub75:{}", path.display()),
+            SinkConfig::RawFloat(path) => write!(f, "rawf:{}", path.display()),
+            SinkConfig::Leds(path) => write!(f, "leds:{}", path.display()),
+            SinkConfig::Bake { path, .. } => write!(f, "bake:{}", path.display()),
+        }
+    }
+}
+
+impl SinkConfig {
+    /// `png:<folder>`, `preview:<folder>`, `previewraw:<path>`, `raw:<path>`, `raw:-`,
+    /// `hub75:<path>`, `rawf:<path>`, `leds:<path>` or `bake:<file>`
+    pub fn from_arg(arg: &str) -> Result<SinkConfig, String> {
+        match arg.split_once(':') {
+            Some(("png", folder)) => Ok(SinkConfig::Png(PathBuf::from(folder))),
+            Some(("preview", folder)) => Ok(SinkConfig::Preview {
+                folder: PathBuf::from(folder),
+                style: PreviewStyle::default(),
+                mapping: None,
+            }),
+            Some(("previewraw", path)) => Ok(SinkConfig::PreviewRaw {
+                path: PathBuf::from(path),
+                style: PreviewStyle::default(),
+                mapping: None,
+            }),
+            Some(("raw", path)) => Ok(SinkConfig::Raw(PathBuf::from(path))),
+            Some(("hub75", path)) => Ok(SinkConfig::Hub75 {
+                path: PathBuf::from(path),
+                mapping: None,
+            }),
+            Some(("rawf", path)) => Ok(SinkConfig::RawFloat(PathBuf::from(path))),
+            Some(("leds", path)) => Ok(SinkConfig::Leds(PathBuf::from(path))),
+            Some(("bake", path)) => Ok(SinkConfig::Bake {
+                path: PathBuf::from(path),
+                loop_points: None,
+            }),
+            _ => Err(format!(
+                "unknown output sink `{arg}`, expected png:<folder>, preview:<folder>, previewraw:<path>, raw:<path>, hub75:<path>, rawf:<path>, leds:<path> or bake:<file>"
+            )),
+        }
+    }
+
+    /// Maps the frames of the sinks showing or driving the panels through the wiring of `layout`
+    pub fn set_layout(&mut self, layout: &PanelLayout) {
+        match self {
+            SinkConfig::Preview { mapping, .. }
+            | SinkConfig::PreviewRaw { mapping, .. }
+            | SinkConfig::Hub75 { mapping, .. } => *mapping = Some(Hub75Mapping::new(layout)),
+            _ => {}
+        }
+    }
+
+    fn open(&self, width: u32, height: u32) -> Result<Box<dyn OutputSink>, String> {
+        Ok(match self {
+            SinkConfig::Png(folder) => Box::new(PngSink::new(folder.clone())?),
+            SinkConfig::Preview {
+                folder,
+                style,
+                mapping,
+            } => Box::new(PreviewSink::new(
+                folder.clone(),
+                style.clone(),
+                mapping.clone(),
+            )?),
+            SinkConfig::PreviewRaw {
+                path,
+                style,
+                mapping,
+            } => Box::new(PreviewSink::stream(path, style.clone(), mapping.clone())?),
+            SinkConfig::Raw(path) => Box::new(RawSink::open(path)?),
+            SinkConfig::Hub75 { path, mapping } => {
+                let mapping = mapping
+                    .clone()
+                    .ok_or_else(|| String::from("no HUB75 mapping was set"))?;
+                Box::new(RawHub75Sink::open(path, mapping)?)
+            }
+            SinkConfig::RawFloat(path) => Box::new(RawFloatSink::open(path)?),
+            SinkConfig::Leds(path) => Box::new(RawLedSink::open(path)?),
+            SinkConfig::Bake { path, loop_points } => {
+                Box::new(BakeSink::create(path, width, height, *loop_points)?)
+            }
+        })
+    }
+}
+
+/// Every sink frames are currently written to, at one panel resolution
+pub struct OutputSinks {
+    pub width: u32,
+    pub height: u32,
+    sinks: Vec<(String, Box<dyn OutputSink>)>,
+    // LEDs off the panel grid, sampled for the sinks wanting them
+    pixel_maps: Vec<PixelMap>,
+    // Per panel correction of the frames of calibrated sinks
+    calibration: Option<Calibration>,
+}
+
+impl OutputSinks {
+    pub fn open(width: u32, height: u32, configs: &[SinkConfig]) -> Result<OutputSinks, String> {
+        let sinks = configs
+            .iter()
+            .map(|config| {
+                let sink = config
+                    .open(width, height)
+                    .map_err(|e| format!("{config}: {e}"))?;
+                Ok((config.to_string(), sink))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(OutputSinks {
+            width,
+            height,
+            sinks,
+            pixel_maps: Vec::new(),
+            calibration: None,
+        })
+    }
+
+    pub fn with_pixel_maps(mut self, pixel_maps: Vec<PixelMap>) -> OutputSinks {
+        self.pixel_maps = pixel_maps;
+        self
+    }
+
+    pub fn with_calibration(mut self, calibration: Option<Calibration>) -> OutputSinks {
+        self.calibration = calibration;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Writes to every sink, a sink that fails is reported and dropped
+    pub fn write_frame(&mut self, frame: &PanelFrame, duration: Duration) -> Vec<String> {
+        let calibrated = self
+            .calibration
+            .as_ref()
+            .map(|calibration| calibration.apply(frame));
+        let mut errors = Vec::new();
+        self.sinks.retain_mut(|(name, sink)| {
+            let frame = match &calibrated {
+                Some(calibrated) if sink.calibrated() => calibrated,
+                _ => frame,
+            };
+            match sink.write_frame(frame, duration) {
+                Ok(()) => true,
+                Err(e) => {
+                    errors.push(format!("Output sink `{name}` failed: {e}"));
+                    false
+                }
+            }
+        });
+        errors
+    }
+
+    pub fn wants_linear(&self) -> bool {
+        self.sinks.iter().any(|(_, sink)| sink.wants_linear())
+    }
+
+    /// Writes the linear frame to every sink, a sink that fails is reported and dropped
+    pub fn write_linear_frame(
+        &mut self,
+        frame: &LinearPanelFrame,
+        duration: Duration,
+    ) -> Vec<String> {
+        let mut errors = Vec::new();
+        self.sinks.retain_mut(
+            |(name, sink)| match sink.write_linear_frame(frame, duration) {
+                Ok(()) => true,
+                Err(e) => {
+                    errors.push(format!("Output sink `{name}` failed: {e}"));
+                    false
+                }
+            },
+        );
+        errors
+    }
+
+    /// Samples every pixel map from a tightly packed RGB or RGBA image of the whole panel
+    /// layout and writes the LEDs to the sinks wanting them
+    pub fn write_leds(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        channels: usize,
+        duration: Duration,
+    ) -> Vec<String> {
+        if self.pixel_maps.is_empty() || !self.sinks.iter().any(|(_, sink)| sink.wants_leds()) {
+            return Vec::new();
+        }
+        let panel = PanelSize {
+            width: self.width,
+            height: self.height,
+        };
+        let leds = self
+            .pixel_maps
+            .iter()
+            .map(|map| LedFrame {
+                map: map.name.clone(),
+                rgb: map.sample(pixels, width, height, channels, panel),
+            })
+            .collect::<Vec<_>>();
+        let mut errors = Vec::new();
+        self.sinks
+            .retain_mut(|(name, sink)| match sink.write_leds(&leds, duration) {
+                Ok(()) => true,
+                Err(e) => {
+                    errors.push(format!("Output sink `{name}` failed: {e}"));
+                    false
+                }
+            });
+        errors
+    }
+
+    pub fn finish(&mut self) -> Vec<String> {
+        self.sinks
+            .drain(..)
+            .filter_map(|(name, mut sink)| {
+                sink.finish()
+                    .err()
+                    .map(|e| format!("Output sink `{name}` failed to finish: {e}"))
+            })
+            .collect()
+    }
+}
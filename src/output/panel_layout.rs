@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::{
+    layers::{EffectLayerConfig, SpriteLayerConfig, TextLayerConfig},
+    led_transfer::LedTransferConfig,
+    output::{PanelRotation, PanelSize, ScanOrder},
+    post_process::PostProcessStage,
+    symmetry::SymmetryConfig,
+};
+
+/// Named rectangle of the panels, in panel pixels from the top left corner
+#[derive(Debug, Clone, Deserialize)]
+pub struct PanelRegion {
+    pub name: String,
+    // x, y, width, height
+    pub rect: [u32; 4],
+    // Sprite layers composited over the render inside this region, back to front
+    #[serde(default)]
+    pub sprites: Vec<SpriteLayerConfig>,
+    // WGSL effects drawn under the sprites, back to front
+    #[serde(default)]
+    pub effects: Vec<EffectLayerConfig>,
+    // Bitmap font text drawn over the sprites
+    pub text: Option<TextLayerConfig>,
+    // Output of full white on this panel, the `led_transfer` one otherwise
+    pub max_brightness: Option<f32>,
+    // Face camera rendering into this region alone, its own projection at its own aspect
+    pub camera: Option<String>,
+    // HUB75 chain the panel is wired on, the panels of a chain are listed in wiring order
+    #[serde(default)]
+    pub chain: u32,
+    // Clockwise turn of the panel as mounted, 0, 90, 180 or 270 degrees
+    #[serde(default)]
+    pub rotation: PanelRotation,
+    // Multiplexing of the panel, `direct`, `stripe` or `checkered`
+    #[serde(default)]
+    pub scan: ScanOrder,
+}
+
+impl PanelRegion {
+    /// Center of the region in layer space, panel pixels with the origin at the bottom left
+    pub fn layer_center(&self, panel_height: u32) -> Vec2 {
+        let [x, y, width, height] = self.rect.map(|value| value as f32);
+        Vec2::new(x + width / 2.0, panel_height as f32 - y - height / 2.0)
+    }
+
+    /// Bottom left and top right corners in layer space
+    pub fn layer_bounds(&self, panel_height: u32) -> Rect {
+        let [_, _, width, height] = self.rect.map(|value| value as f32);
+        Rect::from_center_size(self.layer_center(panel_height), Vec2::new(width, height))
+    }
+}
+
+/// How the LED panels are arranged and what is drawn on top of the render per region.
+///
+/// ```json
+/// {
+///     "size": [128, 32],
+///     "regions": [
+///         { "name": "left", "rect": [0, 0, 64, 32], "camera": "EyeCamera", "sprites": [{ "sheet": "sprites/heart.png" }] },
+///         { "name": "right", "rect": [64, 0, 64, 32], "chain": 1, "rotation": 180, "effects": [{ "shader": "effects/plasma.wgsl" }] },
+///         { "name": "banner", "rect": [0, 24, 128, 8], "text": { "font": "fonts/5x8.bdf", "text": "hello" } }
+///     ],
+///     "post_process": [{ "effect": "bloom", "strength": 0.4 }, { "effect": "scanlines" }],
+///     "led_transfer": { "black_level": 0.03, "shoulder": 0.75 },
+///     "symmetry": { "source": "left", "overrides": ["wink_r"] }
+/// }
+/// ```
+#[derive(Debug, Clone, Resource)]
+pub struct PanelLayout {
+    pub size: PanelSize,
+    pub regions: Vec<PanelRegion>,
+    // Effects run over the whole composited image, in order
+    pub post_process: Vec<PostProcessStage>,
+    // Response of the panels, mapped onto after post-processing
+    pub led_transfer: Option<LedTransferConfig>,
+    // Render one half of the face and mirror it onto the other
+    pub symmetry: Option<SymmetryConfig>,
+}
+
+#[derive(Deserialize)]
+struct LayoutFile {
+    size: Option<[u32; 2]>,
+    #[serde(default)]
+    regions: Vec<PanelRegion>,
+    #[serde(default)]
+    post_process: Vec<PostProcessStage>,
+    led_transfer: Option<LedTransferConfig>,
+    symmetry: Option<SymmetryConfig>,
+}
+
+impl PanelLayout {
+    /// A single region covering every panel
+    pub fn single(size: PanelSize) -> PanelLayout {
+        PanelLayout {
+            size,
+            regions: vec![PanelRegion {
+                name: String::from("panel"),
+                rect: [0, 0, size.width, size.height],
+                sprites: Vec::new(),
+                effects: Vec::new(),
+                text: None,
+                max_brightness: None,
+                camera: None,
+                chain: 0,
+                rotation: PanelRotation::None,
+                scan: ScanOrder::Direct,
+            }],
+            post_process: Vec::new(),
+            led_transfer: None,
+            symmetry: None,
+        }
+    }
+
+    /// Reads a JSON layout, `size` falls back to `default_size` when the file has none
+    pub fn load(path: &Path, default_size: PanelSize) -> Result<PanelLayout, String> {
+        let file = std::fs::read_to_string(path).map_err(|e| format!("{path:?}: {e}"))?;
+        let file: LayoutFile = serde_json::from_str(&file).map_err(|e| format!("{path:?}: {e}"))?;
+        let size = match file.size {
+            Some([0, _] | [_, 0]) => return Err(format!("{path:?}: size can not be zero")),
+            Some([width, height]) => PanelSize { width, height },
+            None => default_size,
+        };
+
+        for region in &file.regions {
+            let [x, y, width, height] = region.rect;
+            let inside = |start: u32, length: u32, limit: u32| {
+                length > 0 && start.checked_add(length).is_some_and(|end| end <= limit)
+            };
+            if !inside(x, width, size.width) || !inside(y, height, size.height) {
+                return Err(format!(
+                    "{path:?}: region `{}` {:?} is outside the {}x{} panels",
+                    region.name, region.rect, size.width, size.height
+                ));
+            }
+            if region
+                .max_brightness
+                .is_some_and(|brightness| brightness < 0.0)
+            {
+                return Err(format!(
+                    "{path:?}: region `{}` max_brightness can not be negative",
+                    region.name
+                ));
+            }
+            let (native_width, native_height) = region.rotation.native_size(width, height);
+            region
+                .scan
+                .validate(native_width, native_height)
+                .map_err(|e| format!("{path:?}: region `{}`: {e}", region.name))?;
+            for (index, sprite) in region.sprites.iter().enumerate() {
+                sprite.validate().map_err(|e| {
+                    format!(
+                        "{path:?}: sprite layer {index} of region `{}`: {e}",
+                        region.name
+                    )
+                })?;
+            }
+            for (index, effect) in region.effects.iter().enumerate() {
+                effect.validate().map_err(|e| {
+                    format!(
+                        "{path:?}: effect layer {index} of region `{}`: {e}",
+                        region.name
+                    )
+                })?;
+            }
+            if let Some(text) = &region.text {
+                text.validate()
+                    .map_err(|e| format!("{path:?}: region `{}`: {e}", region.name))?;
+            }
+        }
+        for stage in &file.post_process {
+            stage.validate().map_err(|e| format!("{path:?}: {e}"))?;
+        }
+        // Both narrow the viewports of the face cameras
+        if file.symmetry.is_some() && file.regions.iter().any(|region| region.camera.is_some()) {
+            return Err(format!(
+                "{path:?}: symmetry can not be used with regions naming a camera"
+            ));
+        }
+        if let Some(led_transfer) = &file.led_transfer {
+            led_transfer
+                .validate()
+                .map_err(|e| format!("{path:?}: {e}"))?;
+        }
+
+        let regions = match file.regions.is_empty() {
+            true => PanelLayout::single(size).regions,
+            false => file.regions,
+        };
+        Ok(PanelLayout {
+            size,
+            regions,
+            post_process: file.post_process,
+            led_transfer: file.led_transfer,
+            symmetry: file.symmetry,
+        })
+    }
+}
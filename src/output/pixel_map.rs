@@ -0,0 +1,239 @@
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::output::PanelSize;
+
+/// Axis 3D positions are projected along onto the panels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectionAxis {
+    X,
+    Y,
+    // Looking at the visor from the front, `x` right and `y` down
+    #[default]
+    Z,
+}
+
+/// Positions of LEDs off the panel grid, like strips in the ears and fins, sampled from the
+/// captured frame.
+///
+/// Positions are in panel pixels, whole numbers at pixel centers, from a CSV file of `x,y`
+/// or `x,y,z` rows or a JSON file that can also place them:
+///
+/// ```json
+/// { "axis": "z", "scale": [0.5, 0.5], "offset": [96, 4], "leds": [[0, 0, 10], [4, 0, 12]] }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PixelMap {
+    // File stem, tells the maps apart in the sinks
+    pub name: String,
+    // Projected positions in panel pixels
+    pub points: Vec<[f32; 2]>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PixelMapFile {
+    Leds(Vec<Vec<f32>>),
+    Placed {
+        #[serde(default)]
+        axis: ProjectionAxis,
+        // Scales positions, from millimeters for example, to panel pixels
+        scale: Option<[f32; 2]>,
+        // Panel pixel the origin of the positions lands on
+        #[serde(default)]
+        offset: [f32; 2],
+        leds: Vec<Vec<f32>>,
+    },
+}
+
+impl PixelMap {
+    /// Reads a `.csv` or `.json` pixel map
+    pub fn load(path: &Path) -> Result<PixelMap, String> {
+        let file = std::fs::read_to_string(path).map_err(|e| format!("{path:?}: {e}"))?;
+        let (axis, scale, offset, leds) = match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => (
+                ProjectionAxis::Z,
+                None,
+                [0.0; 2],
+                parse_csv(&file).map_err(|e| format!("{path:?}: {e}"))?,
+            ),
+            _ => match serde_json::from_str(&file).map_err(|e| format!("{path:?}: {e}"))? {
+                PixelMapFile::Leds(leds) => (ProjectionAxis::Z, None, [0.0; 2], leds),
+                PixelMapFile::Placed {
+                    axis,
+                    scale,
+                    offset,
+                    leds,
+                } => (axis, scale, offset, leds),
+            },
+        };
+        if leds.is_empty() {
+            return Err(format!("{path:?}: no LEDs"));
+        }
+        let [scale_x, scale_y] = scale.unwrap_or([1.0; 2]);
+        let points = leds
+            .iter()
+            .enumerate()
+            .map(|(index, led)| {
+                let [u, v] = match (axis, led.as_slice()) {
+                    (ProjectionAxis::Z, [x, y] | [x, y, _]) => [*x, *y],
+                    (ProjectionAxis::Y, [x, _, z]) => [*x, *z],
+                    (ProjectionAxis::X, [_, y, z]) => [*z, *y],
+                    _ => {
+                        return Err(format!(
+                            "{path:?}: LED {index} has {} coordinates, projecting along {axis:?} takes 3",
+                            led.len()
+                        ));
+                    }
+                };
+                Ok([u * scale_x + offset[0], v * scale_y + offset[1]])
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(PixelMap {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            points,
+        })
+    }
+
+    /// Color of every LED, bilinearly sampled from a tightly packed RGB or RGBA image showing
+    /// the whole `panel`, as 8 bit RGB
+    pub fn sample(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        channels: usize,
+        panel: PanelSize,
+    ) -> Vec<u8> {
+        let scale_x = width as f32 / panel.width as f32;
+        let scale_y = height as f32 / panel.height as f32;
+        let texel = |x: i64, y: i64| {
+            let x = x.clamp(0, width as i64 - 1) as usize;
+            let y = y.clamp(0, height as i64 - 1) as usize;
+            let offset = (y * width as usize + x) * channels;
+            [0, 1, 2].map(|channel| pixels[offset + channel] as f32)
+        };
+        self.points
+            .iter()
+            .flat_map(|[x, y]| {
+                // Panel pixel centers to image pixel centers
+                let x = (x + 0.5) * scale_x - 0.5;
+                let y = (y + 0.5) * scale_y - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let [top_left, top_right, bottom_left, bottom_right] = [
+                    texel(x0, y0),
+                    texel(x0 + 1, y0),
+                    texel(x0, y0 + 1),
+                    texel(x0 + 1, y0 + 1),
+                ];
+                [0, 1, 2].map(|channel| {
+                    let top = top_left[channel] + (top_right[channel] - top_left[channel]) * fx;
+                    let bottom =
+                        bottom_left[channel] + (bottom_right[channel] - bottom_left[channel]) * fx;
+                    (top + (bottom - top) * fy).round() as u8
+                })
+            })
+            .collect()
+    }
+}
+
+/// `x,y` or `x,y,z` rows, a header and `#` comments are skipped
+fn parse_csv(file: &str) -> Result<Vec<Vec<f32>>, String> {
+    let mut leds = Vec::new();
+    for (number, line) in file.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values = line
+            .split(',')
+            .map(|value| value.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>();
+        match values {
+            Ok(values) if (2..=3).contains(&values.len()) => leds.push(values),
+            Ok(_) => return Err(format!("line {} is not `x,y` or `x,y,z`", number + 1)),
+            Err(_) if leds.is_empty() => continue,
+            Err(e) => return Err(format!("line {}: {e}", number + 1)),
+        }
+    }
+    Ok(leds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `contents` from a file named `name` in a folder of its own
+    fn load(name: &str, contents: &str) -> Result<PixelMap, String> {
+        let folder = std::env::temp_dir().join(format!("pixel_map_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join(name);
+        std::fs::write(&path, contents).unwrap();
+        let map = PixelMap::load(&path);
+        let _ = std::fs::remove_file(path);
+        map
+    }
+
+    #[test]
+    fn csv_header_and_comments_are_skipped() {
+        let leds = parse_csv("x,y\n# left ear\n1,2\n\n 3.5 , 4 \n").unwrap();
+        assert_eq!(leds, [vec![1.0, 2.0], vec![3.5, 4.0]]);
+        // Text after the first LED is an error, not another header
+        assert!(parse_csv("x,y\n1,2\nx,y\n").is_err());
+        assert!(parse_csv("1,2,3,4\n").is_err());
+
+        let map = load("ears.csv", "x,y\n0,0\n10,5\n").unwrap();
+        assert_eq!(map.name, "ears");
+        assert_eq!(map.points, [[0.0, 0.0], [10.0, 5.0]]);
+    }
+
+    #[test]
+    fn json_positions_are_projected_scaled_and_offset() {
+        let map = load(
+            "fins.json",
+            r#"{ "axis": "y", "scale": [0.5, 2], "offset": [96, 4], "leds": [[0, 7, 0], [4, 7, 2]] }"#,
+        )
+        .unwrap();
+        // Along y, the x and z coordinates land on the panels
+        assert_eq!(map.points, [[96.0, 4.0], [98.0, 8.0]]);
+
+        let map = load("strip.json", r#"{ "axis": "x", "leds": [[9, 1, 2]] }"#).unwrap();
+        assert_eq!(map.points, [[2.0, 1.0]]);
+
+        assert!(load("flat.json", r#"{ "axis": "x", "leds": [[1, 2]] }"#).is_err());
+        assert!(load("empty.json", "[]").is_err());
+    }
+
+    #[test]
+    fn sample_at_pixel_centers_and_edges() {
+        // 2x1 RGBA image, red then blue, shown on 2x1 panels
+        let pixels = [255, 0, 0, 255, 0, 0, 255, 255];
+        let panel = PanelSize {
+            width: 2,
+            height: 1,
+        };
+        let map = |points: Vec<[f32; 2]>| PixelMap {
+            name: String::from("test"),
+            points,
+        };
+
+        // Pixel centers give the pixel itself, halfway between them a blend
+        let colors = map(vec![[0.0, 0.0], [1.0, 0.0], [0.5, 0.0]]).sample(&pixels, 2, 1, 4, panel);
+        assert_eq!(colors, [255, 0, 0, 0, 0, 255, 128, 0, 128]);
+
+        // Past the edges the nearest pixel is repeated
+        let colors = map(vec![[-3.0, -1.0], [5.0, 2.0]]).sample(&pixels, 2, 1, 4, panel);
+        assert_eq!(colors, [255, 0, 0, 0, 0, 255]);
+
+        // A captured frame twice the panel resolution, RGB
+        let pixels = [[10, 20, 30]; 4 * 2].concat();
+        let colors = map(vec![[1.0, 0.0]]).sample(&pixels, 4, 2, 3, panel);
+        assert_eq!(colors, [10, 20, 30]);
+    }
+}
@@ -0,0 +1,34 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::output::{OutputSink, PanelFrame};
+
+/// Writes every frame as `000.png`, `001.png`... into a folder
+pub struct PngSink {
+    folder: PathBuf,
+    file_number: u32,
+}
+
+impl PngSink {
+    pub fn new(folder: PathBuf) -> Result<PngSink, String> {
+        std::fs::create_dir_all(&folder).map_err(|e| format!("{folder:?}: {e}"))?;
+        Ok(PngSink {
+            folder,
+            file_number: 0,
+        })
+    }
+}
+
+impl OutputSink for PngSink {
+    fn write_frame(&mut self, frame: &PanelFrame, _duration: Duration) -> Result<(), String> {
+        let path = self.folder.join(format!("{:03}.png", self.file_number));
+        self.file_number += 1;
+        image::save_buffer(
+            &path,
+            &frame.rgb,
+            frame.width,
+            frame.height,
+            image::ExtendedColorType::Rgb8,
+        )
+        .map_err(|e| format!("{path:?}: {e}"))
+    }
+}
@@ -0,0 +1,374 @@
+use bevy::color::Srgba;
+use serde::Deserialize;
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::output::{Hub75Mapping, OutputSink, PanelFrame, raw_sink::open_writer};
+
+/// Light of the board between the LEDs, in linear RGB
+const BOARD: f32 = 0.004;
+
+/// Look of the simulated panels of a `preview:` sink.
+///
+/// ```json
+/// { "pitch": 12, "emitter": 0.5, "glow": 0.25, "glow_radius": 0.8, "visor_tint": [0.6, 0.6, 0.7], "color_depth": 6 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PreviewStyle {
+    // Preview pixels from one LED to the next
+    pub pitch: u32,
+    // Diameter of the lit emitter as a fraction of the pitch
+    pub emitter: f32,
+    // Light scattered around each emitter, relative to the emitter itself
+    pub glow: f32,
+    // Falloff of the glow, in LED pitches
+    pub glow_radius: f32,
+    // Share of red, green and blue the visor lets through
+    pub visor_tint: [f32; 3],
+    // Bits per channel the panel driver shows, lower values band like the panels do
+    pub color_depth: u32,
+}
+
+impl Default for PreviewStyle {
+    fn default() -> Self {
+        PreviewStyle {
+            pitch: 12,
+            emitter: 0.5,
+            glow: 0.25,
+            glow_radius: 0.8,
+            visor_tint: [1.0; 3],
+            color_depth: 8,
+        }
+    }
+}
+
+impl PreviewStyle {
+    pub fn load(path: &Path) -> Result<PreviewStyle, String> {
+        let file = std::fs::read_to_string(path).map_err(|e| format!("{path:?}: {e}"))?;
+        let style: PreviewStyle =
+            serde_json::from_str(&file).map_err(|e| format!("{path:?}: {e}"))?;
+        style.validate().map_err(|e| format!("{path:?}: {e}"))?;
+        Ok(style)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.pitch == 0 || !(0.0..=1.0).contains(&self.emitter) {
+            return Err(String::from(
+                "preview pitch must be positive and emitter within 0..1",
+            ));
+        }
+        if self.glow < 0.0 || self.glow_radius <= 0.0 {
+            return Err(String::from(
+                "preview glow can not be negative and glow_radius must be positive",
+            ));
+        }
+        if !(1..=8).contains(&self.color_depth) {
+            return Err(format!(
+                "preview color_depth {} is outside 1..8",
+                self.color_depth
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Where a preview goes
+enum PreviewTarget {
+    // Numbered PNG files
+    Folder { folder: PathBuf, file_number: u32 },
+    // Raw RGB frames, for a preview server reading a pipe
+    Stream(BufWriter<Box<dyn Write + Send + Sync>>),
+}
+
+/// Renders every frame as the LED panels would look showing it, either written as `000.png`,
+/// `001.png`... into a folder or streamed as raw RGB rows to a preview server. Frame values
+/// are the PWM duty of the LEDs, so the light they give is linear in them.
+///
+/// With a HUB75 mapping the frame is first mapped to the chain buffers the driver would
+/// shift out and the preview drawn from what the mounted panels show of them, so chain
+/// wiring, rotation and scan order mistakes of the layout show up, and pixels off every
+/// panel stay dark.
+pub struct PreviewSink {
+    target: PreviewTarget,
+    style: PreviewStyle,
+    mapping: Option<Hub75Mapping>,
+    // LEDs around the one under a preview pixel that light it
+    reach: i32,
+    // Light of every LED within `reach`, for every preview pixel of one LED cell
+    kernel: Vec<f32>,
+}
+
+impl PreviewSink {
+    pub fn new(
+        folder: PathBuf,
+        style: PreviewStyle,
+        mapping: Option<Hub75Mapping>,
+    ) -> Result<PreviewSink, String> {
+        std::fs::create_dir_all(&folder).map_err(|e| format!("{folder:?}: {e}"))?;
+        let target = PreviewTarget::Folder {
+            folder,
+            file_number: 0,
+        };
+        Ok(PreviewSink::with_target(target, style, mapping))
+    }
+
+    /// Same targets as `RawSink::open`, frames are `pitch` times the panel size
+    pub fn stream(
+        path: &Path,
+        style: PreviewStyle,
+        mapping: Option<Hub75Mapping>,
+    ) -> Result<PreviewSink, String> {
+        let target = PreviewTarget::Stream(open_writer(path)?);
+        Ok(PreviewSink::with_target(target, style, mapping))
+    }
+
+    fn with_target(
+        target: PreviewTarget,
+        style: PreviewStyle,
+        mapping: Option<Hub75Mapping>,
+    ) -> PreviewSink {
+        // The glow is under 2% this far out
+        let reach = (style.glow_radius * 2.0).ceil() as i32;
+        let pitch = style.pitch as f32;
+        let mut kernel = Vec::new();
+        for y in 0..style.pitch {
+            for x in 0..style.pitch {
+                // Position of the preview pixel in LED pitches from the center of its LED
+                let position = [x, y].map(|value| (value as f32 + 0.5) / pitch - 0.5);
+                for led_y in -reach..=reach {
+                    for led_x in -reach..=reach {
+                        let distance =
+                            (position[0] - led_x as f32).hypot(position[1] - led_y as f32);
+                        // A pixel wide edge keeps the emitter round at small pitches
+                        let emitter =
+                            ((style.emitter / 2.0 - distance) * pitch + 0.5).clamp(0.0, 1.0);
+                        let glow = style.glow * (-(distance / style.glow_radius).powi(2)).exp();
+                        kernel.push(emitter + glow);
+                    }
+                }
+            }
+        }
+        PreviewSink {
+            target,
+            style,
+            mapping,
+            reach,
+            kernel,
+        }
+    }
+
+    fn render(&self, frame: &PanelFrame) -> Vec<u8> {
+        let levels = ((1u32 << self.style.color_depth) - 1) as f32;
+        let light = frame
+            .rgb
+            .iter()
+            .map(|value| (*value as f32 / 255.0 * levels).round() / levels)
+            .collect::<Vec<_>>();
+
+        let pitch = self.style.pitch as usize;
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let leds = (2 * self.reach + 1) as usize;
+        let mut rgb = Vec::with_capacity(width * height * pitch * pitch * 3);
+        for y in 0..height * pitch {
+            for x in 0..width * pitch {
+                let kernel_start = ((y % pitch) * pitch + x % pitch) * leds * leds;
+                let weights = &self.kernel[kernel_start..kernel_start + leds * leds];
+                let mut sum = [BOARD; 3];
+                for (index, weight) in weights.iter().enumerate() {
+                    let led_x = (x / pitch) as i32 + (index % leds) as i32 - self.reach;
+                    let led_y = (y / pitch) as i32 + (index / leds) as i32 - self.reach;
+                    if led_x < 0 || led_y < 0 || led_x >= width as i32 || led_y >= height as i32 {
+                        continue;
+                    }
+                    let offset = (led_y as usize * width + led_x as usize) * 3;
+                    for (channel, sum) in sum.iter_mut().enumerate() {
+                        *sum += weight * light[offset + channel];
+                    }
+                }
+                for (sum, tint) in sum.iter().zip(self.style.visor_tint) {
+                    let value = Srgba::gamma_function_inverse((sum * tint).clamp(0.0, 1.0));
+                    rgb.push((value * 255.0).round() as u8);
+                }
+            }
+        }
+        rgb
+    }
+}
+
+impl OutputSink for PreviewSink {
+    fn write_frame(&mut self, frame: &PanelFrame, _duration: Duration) -> Result<(), String> {
+        let rgb = match &self.mapping {
+            Some(mapping) => self.render(&mapping.display(&mapping.map(frame)?)),
+            None => self.render(frame),
+        };
+        let (width, height) = (
+            frame.width * self.style.pitch,
+            frame.height * self.style.pitch,
+        );
+        match &mut self.target {
+            PreviewTarget::Folder {
+                folder,
+                file_number,
+            } => {
+                let path = folder.join(format!("{file_number:03}.png"));
+                *file_number += 1;
+                image::save_buffer(&path, &rgb, width, height, image::ExtendedColorType::Rgb8)
+                    .map_err(|e| format!("{path:?}: {e}"))
+            }
+            PreviewTarget::Stream(writer) => writer
+                .write_all(&rgb)
+                // Whole frames only, so the server never waits on half of one
+                .and_then(|()| writer.flush())
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(json: &str) -> Result<PreviewStyle, String> {
+        let style: PreviewStyle = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        style.validate()?;
+        Ok(style)
+    }
+
+    /// A sink of a single lit LED and the sRGB preview pixels of its cell
+    fn render(style: PreviewStyle, rgb: [u8; 3]) -> Vec<u8> {
+        let target = PreviewTarget::Folder {
+            folder: PathBuf::new(),
+            file_number: 0,
+        };
+        let sink = PreviewSink::with_target(target, style, None);
+        sink.render(&PanelFrame {
+            width: 1,
+            height: 1,
+            rgb: rgb.to_vec(),
+        })
+    }
+
+    #[test]
+    fn style_parsing_and_validation() {
+        assert_eq!(style("{}"), Ok(PreviewStyle::default()));
+        let parsed =
+            style(r#"{ "pitch": 4, "visor_tint": [0.5, 0.5, 0.6], "color_depth": 6 }"#).unwrap();
+        assert_eq!(parsed.pitch, 4);
+        assert_eq!(parsed.visor_tint, [0.5, 0.5, 0.6]);
+        assert_eq!(parsed.color_depth, 6);
+        assert_eq!(parsed.emitter, PreviewStyle::default().emitter);
+
+        for invalid in [
+            r#"{ "pitch": 0 }"#,
+            r#"{ "emitter": 1.5 }"#,
+            r#"{ "glow": -0.1 }"#,
+            r#"{ "glow_radius": 0 }"#,
+            r#"{ "color_depth": 0 }"#,
+            r#"{ "color_depth": 9 }"#,
+            r#"{ "pitch": "large" }"#,
+        ] {
+            assert!(style(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn emitters_are_lit_and_gaps_show_the_board() {
+        let style = PreviewStyle {
+            pitch: 4,
+            glow: 0.0,
+            ..PreviewStyle::default()
+        };
+        let board = (Srgba::gamma_function_inverse(BOARD) * 255.0).round() as u8;
+
+        let rgb = render(style.clone(), [255; 3]);
+        assert_eq!(rgb.len(), 4 * 4 * 3);
+        let pixel = |x: usize, y: usize| &rgb[(y * 4 + x) * 3..(y * 4 + x) * 3 + 3];
+        // Inside the emitter in the middle of the cell, the corners are between LEDs
+        assert!(pixel(1, 1).iter().all(|value| *value > 200));
+        assert!(pixel(2, 2).iter().all(|value| *value > 200));
+        assert_eq!(pixel(0, 0), [board; 3]);
+        assert_eq!(pixel(3, 3), [board; 3]);
+
+        // An unlit LED is as dark as the board
+        assert!(render(style, [0; 3]).iter().all(|value| *value == board));
+    }
+
+    #[test]
+    fn glow_tint_and_color_depth() {
+        let sharp = PreviewStyle {
+            pitch: 4,
+            glow: 0.0,
+            ..PreviewStyle::default()
+        };
+        let glowing = PreviewStyle {
+            glow: 0.5,
+            ..sharp.clone()
+        };
+        // The glow lights the gap
+        assert!(render(glowing, [255; 3])[0] > render(sharp.clone(), [255; 3])[0]);
+
+        // The visor only lets red through
+        let tinted = PreviewStyle {
+            visor_tint: [1.0, 0.0, 0.0],
+            ..sharp.clone()
+        };
+        let center = &render(tinted, [255; 3])[(4 + 1) * 3..(4 + 1) * 3 + 3];
+        assert!(center[0] > 200);
+        assert_eq!(center[1..], [0, 0]);
+
+        // One bit per channel rounds to off or fully on
+        let banded = PreviewStyle {
+            color_depth: 1,
+            ..sharp.clone()
+        };
+        assert_eq!(
+            render(banded.clone(), [100, 200, 255]),
+            render(sharp.clone(), [0, 255, 255])
+        );
+        assert_ne!(
+            render(sharp.clone(), [100, 200, 255]),
+            render(sharp, [0, 255, 255])
+        );
+    }
+
+    #[test]
+    fn mapped_previews_leave_pixels_off_the_panels_dark() {
+        use crate::output::{PanelLayout, PanelSize};
+
+        let mut layout = PanelLayout::single(PanelSize {
+            width: 2,
+            height: 1,
+        });
+        layout.regions[0].rect = [0, 0, 1, 1];
+        let style = PreviewStyle {
+            pitch: 1,
+            emitter: 1.0,
+            glow: 0.0,
+            ..PreviewStyle::default()
+        };
+        let folder = std::env::temp_dir().join(format!("preview_sink_{}", std::process::id()));
+        let mut sink =
+            PreviewSink::new(folder.clone(), style, Some(Hub75Mapping::new(&layout))).unwrap();
+        let frame = PanelFrame {
+            width: 2,
+            height: 1,
+            rgb: vec![255; 6],
+        };
+        sink.write_frame(&frame, Duration::ZERO).unwrap();
+        // A frame of other panels is not shown
+        assert!(
+            sink.write_frame(&PanelFrame::new(4, 1), Duration::ZERO)
+                .is_err()
+        );
+
+        let preview = image::open(folder.join("000.png")).unwrap().into_rgb8();
+        std::fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(preview.dimensions(), (2, 1));
+        assert!(preview.get_pixel(0, 0).0.iter().all(|value| *value > 200));
+        assert!(preview.get_pixel(1, 0).0.iter().all(|value| *value < 20));
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::output::{Hub75Mapping, LedFrame, LinearPanelFrame, OutputSink, PanelFrame};
+
+/// Streams frames as raw RGB bytes, row by row, for an LED driver reading a pipe
+pub struct RawSink {
+    writer: BufWriter<Box<dyn Write + Send + Sync>>,
+}
+
+impl RawSink {
+    /// `-` writes to stdout, opening a named pipe waits for its reader
+    pub fn open(path: &Path) -> Result<RawSink, String> {
+        Ok(RawSink {
+            writer: open_writer(path)?,
+        })
+    }
+}
+
+pub(super) fn open_writer(path: &Path) -> Result<BufWriter<Box<dyn Write + Send + Sync>>, String> {
+    let output: Box<dyn Write + Send + Sync> = if path == Path::new("-") {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(File::create(path).map_err(|e| format!("{path:?}: {e}"))?)
+    };
+    Ok(BufWriter::new(output))
+}
+
+impl OutputSink for RawSink {
+    fn write_frame(&mut self, frame: &PanelFrame, _duration: Duration) -> Result<(), String> {
+        self.writer
+            .write_all(&frame.rgb)
+            // Whole frames only, so the reader never waits on half of one
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Streams linear frames as little endian f32 RGB, row by row, for a driver doing its own
+/// tone mapping
+pub struct RawFloatSink {
+    writer: BufWriter<Box<dyn Write + Send + Sync>>,
+}
+
+impl RawFloatSink {
+    /// Same targets as `RawSink::open`
+    pub fn open(path: &Path) -> Result<RawFloatSink, String> {
+        Ok(RawFloatSink {
+            writer: open_writer(path)?,
+        })
+    }
+}
+
+impl OutputSink for RawFloatSink {
+    // 8 bit frames have lost the headroom, only linear ones are written
+    fn write_frame(&mut self, _frame: &PanelFrame, _duration: Duration) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn write_linear_frame(
+        &mut self,
+        frame: &LinearPanelFrame,
+        _duration: Duration,
+    ) -> Result<(), String> {
+        let bytes: Vec<u8> = frame
+            .rgb
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        self.writer
+            .write_all(&bytes)
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| e.to_string())
+    }
+
+    fn wants_linear(&self) -> bool {
+        true
+    }
+}
+
+/// Streams the LEDs of the pixel maps as raw RGB bytes, every map one after another in the
+/// order they were given
+pub struct RawLedSink {
+    writer: BufWriter<Box<dyn Write + Send + Sync>>,
+}
+
+impl RawLedSink {
+    /// Same targets as `RawSink::open`
+    pub fn open(path: &Path) -> Result<RawLedSink, String> {
+        Ok(RawLedSink {
+            writer: open_writer(path)?,
+        })
+    }
+}
+
+impl OutputSink for RawLedSink {
+    // The panels themselves go to other sinks
+    fn write_frame(&mut self, _frame: &PanelFrame, _duration: Duration) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn write_leds(&mut self, leds: &[LedFrame], _duration: Duration) -> Result<(), String> {
+        leds.iter()
+            .try_for_each(|frame| self.writer.write_all(&frame.rgb))
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| e.to_string())
+    }
+
+    fn wants_leds(&self) -> bool {
+        true
+    }
+}
+
+/// Streams the chain buffers of the HUB75 driver as raw RGB bytes, every chain one after
+/// another by ascending chain number
+pub struct RawHub75Sink {
+    writer: BufWriter<Box<dyn Write + Send + Sync>>,
+    mapping: Hub75Mapping,
+}
+
+impl RawHub75Sink {
+    /// Same targets as `RawSink::open`
+    pub fn open(path: &Path, mapping: Hub75Mapping) -> Result<RawHub75Sink, String> {
+        Ok(RawHub75Sink {
+            writer: open_writer(path)?,
+            mapping,
+        })
+    }
+}
+
+impl OutputSink for RawHub75Sink {
+    fn write_frame(&mut self, frame: &PanelFrame, _duration: Duration) -> Result<(), String> {
+        let chains = self.mapping.map(frame)?;
+        chains
+            .iter()
+            .try_for_each(|chain| self.writer.write_all(&chain.rgb))
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| e.to_string())
+    }
+}
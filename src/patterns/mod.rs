@@ -0,0 +1,4 @@
+mod pattern_scene;
+pub use pattern_scene::PatternScenePlugin;
+mod test_pattern;
+pub use test_pattern::TestPattern;
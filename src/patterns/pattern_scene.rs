@@ -0,0 +1,98 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{
+    control::ControlCommand, output::PanelLayout, patterns::TestPattern, scene::ReadinessGate,
+};
+
+/// Pattern currently shown, `pattern <name>` commands switch it
+#[derive(Debug, Clone, Copy, Resource)]
+struct ActivePattern(TestPattern);
+
+/// Sprite showing the pattern over the whole panel layout
+#[derive(Debug, Clone, Copy, Component)]
+struct PatternSprite;
+
+/// Shows a [`TestPattern`] instead of the face. It is drawn by the layer camera at panel
+/// resolution, so it goes through capture, post-processing and the sinks like a face would.
+pub struct PatternScenePlugin {
+    pub pattern: TestPattern,
+}
+
+impl Plugin for PatternScenePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ActivePattern(self.pattern))
+            .add_systems(Startup, mark_pattern_spawned)
+            .add_systems(Update, (switch_pattern, draw_pattern).chain());
+    }
+}
+
+/// Nothing to load, the pattern is ready as soon as the app is
+fn mark_pattern_spawned(mut readiness_gate: ResMut<ReadinessGate>) {
+    readiness_gate.mark_scene_spawned();
+}
+
+fn switch_pattern(
+    mut commands: MessageReader<ControlCommand>,
+    mut active_pattern: ResMut<ActivePattern>,
+) {
+    for command in commands.read() {
+        if let ControlCommand::Pattern { pattern } = command {
+            info!("Showing pattern {pattern:?}");
+            active_pattern.0 = *pattern;
+        }
+    }
+}
+
+/// Steps per second of the animated patterns
+const STEP_RATE: f32 = 2.0;
+
+fn draw_pattern(
+    mut commands: Commands,
+    active_pattern: Res<ActivePattern>,
+    layout: Res<PanelLayout>,
+    time: Res<Time>,
+    mut drawn_step: Local<Option<u32>>,
+    mut images: ResMut<Assets<Image>>,
+    sprites: Query<Entity, With<PatternSprite>>,
+) {
+    let step = match active_pattern.0.is_animated() {
+        true => (time.elapsed_secs() * STEP_RATE) as u32,
+        false => 0,
+    };
+    if !active_pattern.is_changed() && !layout.is_changed() && *drawn_step == Some(step) {
+        return;
+    }
+    *drawn_step = Some(step);
+
+    for entity in sprites.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let size = Extent3d {
+        width: layout.size.width,
+        height: layout.size.height,
+        ..default()
+    };
+    let image = Image::new(
+        size,
+        TextureDimension::D2,
+        active_pattern.0.render(&layout, step),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    let size = Vec2::new(size.width as f32, size.height as f32);
+    commands.spawn((
+        PatternSprite,
+        Sprite {
+            image: images.add(image),
+            custom_size: Some(size),
+            ..default()
+        },
+        // Behind the layers and the transition quad
+        Transform::from_translation((size / 2.0).extend(-2.0)),
+    ));
+}
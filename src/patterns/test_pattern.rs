@@ -0,0 +1,388 @@
+use crate::output::PanelLayout;
+
+/// 3x5 digits, one row per byte with the leftmost column in bit 2
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Pattern shown instead of the face to calibrate the panels and check their wiring.
+///
+/// `white`, `red`, `green`, `blue`, `black`, `gray:<0-255>` and `solid:<rrggbb>` fill every
+/// panel, `gradient` ramps each panel from black to white, `ramps` stacks red, green, blue and
+/// white ramps, `bars` draws color bars, `grid` draws lines every 8 pixels and the panel
+/// borders, `ids` numbers the panels in layout order, `arrows` points every panel up with a
+/// mark in its top left corner and `chase` lights the panels of each chain one after another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPattern {
+    Solid([u8; 3]),
+    Gradient,
+    Ramps,
+    ColorBars,
+    Grid,
+    PanelIds,
+    Arrows,
+    Chase,
+}
+
+impl std::str::FromStr for TestPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            None => match s {
+                "white" => TestPattern::Solid([255; 3]),
+                "red" => TestPattern::Solid([255, 0, 0]),
+                "green" => TestPattern::Solid([0, 255, 0]),
+                "blue" => TestPattern::Solid([0, 0, 255]),
+                "black" => TestPattern::Solid([0; 3]),
+                "gradient" => TestPattern::Gradient,
+                "ramps" => TestPattern::Ramps,
+                "bars" => TestPattern::ColorBars,
+                "grid" => TestPattern::Grid,
+                "ids" => TestPattern::PanelIds,
+                "arrows" => TestPattern::Arrows,
+                "chase" => TestPattern::Chase,
+                other => {
+                    return Err(format!(
+                        "unknown pattern `{other}`, expected white, red, green, blue, black, \
+                         gray:<level>, solid:<rrggbb>, gradient, ramps, bars, grid, ids, arrows \
+                         or chase"
+                    ));
+                }
+            },
+            Some(("gray", level)) => {
+                let level = level
+                    .parse()
+                    .map_err(|_| format!("`{level}` is not a gray level 0-255"))?;
+                TestPattern::Solid([level; 3])
+            }
+            Some(("solid", hex)) => {
+                let color = u32::from_str_radix(hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 6)
+                    .ok_or_else(|| format!("`{hex}` is not a color like ff8000"))?;
+                TestPattern::Solid([(color >> 16) as u8, (color >> 8) as u8, color as u8])
+            }
+            Some(_) => return Err(format!("unknown pattern `{s}`")),
+        })
+    }
+}
+
+impl TestPattern {
+    /// Whether the pattern changes with the step passed to [`TestPattern::render`]
+    pub fn is_animated(&self) -> bool {
+        matches!(self, TestPattern::Chase)
+    }
+
+    /// Tightly packed RGBA rows at the resolution of the panel layout, `step` advances the
+    /// animated patterns
+    pub fn render(&self, layout: &PanelLayout, step: u32) -> Vec<u8> {
+        let (width, height) = (layout.size.width, layout.size.height);
+        let mut canvas = Canvas {
+            width,
+            height,
+            rgba: [0, 0, 0, 255].repeat(width as usize * height as usize),
+        };
+        match *self {
+            TestPattern::Solid(color) => canvas.fill([0, 0, width, height], color),
+            TestPattern::Gradient => {
+                for region in layout.regions.iter() {
+                    let [x, y, region_width, region_height] = region.rect;
+                    for column in 0..region_width {
+                        let level = (column * 255 / (region_width - 1).max(1)) as u8;
+                        canvas.fill([x + column, y, 1, region_height], [level; 3]);
+                    }
+                }
+            }
+            TestPattern::Ramps => {
+                const RAMPS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255; 3]];
+                for region in layout.regions.iter() {
+                    let [x, y, region_width, region_height] = region.rect;
+                    for (index, ramp) in RAMPS.iter().enumerate() {
+                        let top = y + region_height * index as u32 / 4;
+                        let bottom = y + region_height * (index as u32 + 1) / 4;
+                        for column in 0..region_width {
+                            let level = column as f32 / (region_width - 1).max(1) as f32;
+                            let color = ramp.map(|value| (value as f32 * level).round() as u8);
+                            canvas.fill([x + column, top, 1, bottom - top], color);
+                        }
+                    }
+                }
+            }
+            TestPattern::ColorBars => {
+                for region in layout.regions.iter() {
+                    let [x, y, region_width, region_height] = region.rect;
+                    for (index, color) in BARS.iter().enumerate() {
+                        let left = x + region_width * index as u32 / BARS.len() as u32;
+                        let right = x + region_width * (index as u32 + 1) / BARS.len() as u32;
+                        canvas.fill([left, y, right - left, region_height], *color);
+                    }
+                }
+            }
+            TestPattern::Grid => {
+                for column in (0..width).step_by(8) {
+                    canvas.fill([column, 0, 1, height], [0, 96, 0]);
+                }
+                for row in (0..height).step_by(8) {
+                    canvas.fill([0, row, width, 1], [0, 96, 0]);
+                }
+                for region in layout.regions.iter() {
+                    canvas.outline(region.rect, [255; 3]);
+                }
+            }
+            TestPattern::PanelIds => {
+                for (index, region) in layout.regions.iter().enumerate() {
+                    canvas.fill(region.rect, hue(index));
+                    canvas.outline(region.rect, [255; 3]);
+                    canvas.number(region.rect, index, [255; 3]);
+                }
+            }
+            TestPattern::Arrows => {
+                for region in layout.regions.iter() {
+                    let [x, y, region_width, region_height] = region.rect;
+                    canvas.outline(region.rect, [64; 3]);
+                    canvas.arrow(region.rect, [255; 3]);
+                    // A flipped panel moves the mark, a rotated one turns the arrow too
+                    let mark = (region_width.min(region_height) / 6).max(1);
+                    canvas.fill([x, y, mark, mark], [255, 0, 0]);
+                }
+            }
+            TestPattern::Chase => {
+                let mut chains = layout
+                    .regions
+                    .iter()
+                    .map(|region| region.chain)
+                    .collect::<Vec<_>>();
+                chains.sort_unstable();
+                chains.dedup();
+                for chain in chains {
+                    let panels = layout
+                        .regions
+                        .iter()
+                        .filter(|region| region.chain == chain)
+                        .collect::<Vec<_>>();
+                    let lit = step as usize % panels.len();
+                    for (index, region) in panels.iter().enumerate() {
+                        match index == lit {
+                            true => {
+                                canvas.fill(region.rect, BARS[1 + chain as usize % 6]);
+                                canvas.number(region.rect, index, [0; 3]);
+                            }
+                            false => canvas.outline(region.rect, [64; 3]),
+                        }
+                    }
+                }
+            }
+        }
+        canvas.rgba
+    }
+}
+
+/// Color bars from white to black, the saturated ones also tell the chains apart
+const BARS: [[u8; 3]; 8] = [
+    [255, 255, 255],
+    [255, 255, 0],
+    [0, 255, 255],
+    [0, 255, 0],
+    [255, 0, 255],
+    [255, 0, 0],
+    [0, 0, 255],
+    [0, 0, 0],
+];
+
+/// Distinct, dim background color of the panel at `index`
+fn hue(index: usize) -> [u8; 3] {
+    const HUES: [[u8; 3]; 6] = [
+        [96, 0, 0],
+        [0, 72, 0],
+        [0, 0, 112],
+        [80, 64, 0],
+        [72, 0, 80],
+        [0, 64, 72],
+    ];
+    HUES[index % HUES.len()]
+}
+
+/// RGBA pixels being drawn into, clipped to its size
+struct Canvas {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Canvas {
+    fn fill(&mut self, [x, y, width, height]: [u32; 4], color: [u8; 3]) {
+        for row in y..(y + height).min(self.height) {
+            for column in x..(x + width).min(self.width) {
+                let offset = (row * self.width + column) as usize * 4;
+                self.rgba[offset..offset + 4].copy_from_slice(&[color[0], color[1], color[2], 255]);
+            }
+        }
+    }
+
+    fn outline(&mut self, [x, y, width, height]: [u32; 4], color: [u8; 3]) {
+        self.fill([x, y, width, 1], color);
+        self.fill([x, y + height - 1, width, 1], color);
+        self.fill([x, y, 1, height], color);
+        self.fill([x + width - 1, y, 1, height], color);
+    }
+
+    /// Arrow pointing up, centered in `rect` and as large as fits in it
+    fn arrow(&mut self, [x, y, width, height]: [u32; 4], color: [u8; 3]) {
+        let size = width.min(height).saturating_sub(4).max(3);
+        let center = x + width / 2;
+        let top = y + height.saturating_sub(size) / 2;
+        let head = size / 2;
+        for row in 0..head {
+            self.fill([center - row.min(center), top + row, 2 * row + 1, 1], color);
+        }
+        let shaft = (size / 4).max(1);
+        self.fill([center - shaft / 2, top + head, shaft, size - head], color);
+    }
+
+    /// `value` in 3x5 digits centered in `rect`, scaled up as far as it fits
+    fn number(&mut self, [x, y, width, height]: [u32; 4], value: usize, color: [u8; 3]) {
+        let digits = value
+            .to_string()
+            .bytes()
+            .map(|digit| DIGITS[(digit - b'0') as usize])
+            .collect::<Vec<_>>();
+        // Digits are 3 wide with a column between them, a pixel of margin on every side
+        let text_width = digits.len() as u32 * 4 - 1;
+        let scale = ((width.saturating_sub(2)) / text_width)
+            .min(height.saturating_sub(2) / 5)
+            .max(1);
+        let left = x + width.saturating_sub(text_width * scale) / 2;
+        let top = y + height.saturating_sub(5 * scale) / 2;
+        for (index, digit) in digits.iter().enumerate() {
+            let digit_left = left + index as u32 * 4 * scale;
+            for (row, bits) in digit.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        self.fill(
+                            [
+                                digit_left + column * scale,
+                                top + row as u32 * scale,
+                                scale,
+                                scale,
+                            ],
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::PanelSize;
+
+    /// Two 16x12 panels side by side on the given chains
+    fn layout(chains: [u32; 2]) -> PanelLayout {
+        let mut layout = PanelLayout::single(PanelSize {
+            width: 32,
+            height: 12,
+        });
+        let mut right = layout.regions[0].clone();
+        layout.regions[0].rect = [0, 0, 16, 12];
+        layout.regions[0].chain = chains[0];
+        right.name = String::from("right");
+        right.rect = [16, 0, 16, 12];
+        right.chain = chains[1];
+        layout.regions.push(right);
+        layout
+    }
+
+    fn pixel(rgba: &[u8], x: u32, y: u32) -> [u8; 3] {
+        let offset = (y * 32 + x) as usize * 4;
+        [rgba[offset], rgba[offset + 1], rgba[offset + 2]]
+    }
+
+    #[test]
+    fn parses_patterns() {
+        assert_eq!("red".parse(), Ok(TestPattern::Solid([255, 0, 0])));
+        assert_eq!("gray:128".parse(), Ok(TestPattern::Solid([128; 3])));
+        assert_eq!(
+            "solid:ff8000".parse(),
+            Ok(TestPattern::Solid([255, 128, 0]))
+        );
+        assert_eq!("chase".parse(), Ok(TestPattern::Chase));
+    }
+
+    #[test]
+    fn rejects_bad_patterns() {
+        for pattern in [
+            "solid:",
+            "solid:ff80",
+            "solid:ff80001",
+            "solid:gg0000",
+            "gray:",
+            "gray:256",
+            "gray:-1",
+            "plaid",
+            "bars:2",
+        ] {
+            assert!(
+                pattern.parse::<TestPattern>().is_err(),
+                "{pattern} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn panel_ids_are_drawn_in_each_panel() {
+        let rgba = TestPattern::PanelIds.render(&layout([0, 0]), 0);
+        assert_eq!(rgba.len(), 32 * 12 * 4);
+        // Both outlined in white
+        assert_eq!(pixel(&rgba, 0, 0), [255; 3]);
+        assert_eq!(pixel(&rgba, 16, 6), [255; 3]);
+
+        // A 0 at twice the size: 6x10 pixels from (5, 1), hollow in the middle
+        assert_eq!(pixel(&rgba, 5, 1), [255; 3]);
+        assert_eq!(pixel(&rgba, 10, 10), [255; 3]);
+        assert_eq!(pixel(&rgba, 7, 5), hue(0));
+        assert_eq!(pixel(&rgba, 4, 5), hue(0));
+
+        // A 1 in the second panel: only the middle column of its top row
+        assert_eq!(pixel(&rgba, 16 + 7, 1), [255; 3]);
+        assert_eq!(pixel(&rgba, 16 + 5, 1), hue(1));
+        assert_eq!(pixel(&rgba, 16 + 9, 1), hue(1));
+        assert_eq!(pixel(&rgba, 16 + 5, 10), [255; 3]);
+    }
+
+    #[test]
+    fn chase_wraps_per_chain() {
+        let lit = |rgba: &[u8], panel: u32| pixel(rgba, panel * 16 + 1, 1) != [0; 3];
+
+        // One chain, its two panels take turns
+        let layout = layout([0, 0]);
+        for step in 0..4 {
+            let rgba = TestPattern::Chase.render(&layout, step);
+            assert_eq!(lit(&rgba, 0), step % 2 == 0, "step {step}");
+            assert_eq!(lit(&rgba, 1), step % 2 == 1, "step {step}");
+        }
+        // Lit in the color of the chain
+        let rgba = TestPattern::Chase.render(&layout, 0);
+        assert_eq!(pixel(&rgba, 1, 1), BARS[1]);
+
+        // One panel per chain, each is the whole chain and always lit
+        let layout = self::layout([0, 3]);
+        for step in 0..3 {
+            let rgba = TestPattern::Chase.render(&layout, step);
+            assert_eq!(pixel(&rgba, 1, 1), BARS[1]);
+            assert_eq!(pixel(&rgba, 17, 1), BARS[4]);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod post_process_node;
+mod post_process_stack;
+pub use post_process_stack::{
+    PostProcessEffect, PostProcessFrame, PostProcessLabel, PostProcessPlugin, PostProcessStage,
+};
@@ -0,0 +1,100 @@
+// One stage of the post-process stack, the effect is selected with a shader def
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+struct StageParams {
+    // Parameters of the effect, in the order `PostProcessEffect::params` lists them
+    values: vec4<f32>,
+    // Size of the LED panels, effects work in panel pixels
+    panel_size: vec2<f32>,
+    // Seconds of animation
+    time: f32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> stage: StageParams;
+
+const TAU: f32 = 6.283185;
+
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn rainbow(t: f32) -> vec3<f32> {
+    return 0.5 + 0.5 * cos(TAU * (t + vec3<f32>(0.0, 0.6667, 0.3333)));
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let uv = in.uv;
+    // Size of one panel pixel in texture coordinates
+    let pixel = 1.0 / stage.panel_size;
+    let panel_position = uv * stage.panel_size;
+
+#ifdef GLITCH
+    let amount = stage.values.x;
+    let step = floor(stage.time * stage.values.y);
+    // Bands of rows jump sideways now and then
+    let band = floor(panel_position.y / 3.0);
+    let jump = select(0.0, (hash(vec2<f32>(band, step)) - 0.5) * 8.0 * amount,
+        hash(vec2<f32>(step, band)) < amount * 0.5);
+    let shifted = uv + vec2<f32>(jump * pixel.x, 0.0);
+    // Channels split apart
+    let split = vec2<f32>(amount * 1.5 * pixel.x, 0.0);
+    let color = textureSample(source, source_sampler, shifted);
+    return vec4<f32>(
+        textureSample(source, source_sampler, shifted + split).r,
+        color.g,
+        textureSample(source, source_sampler, shifted - split).b,
+        color.a,
+    );
+#endif
+
+#ifdef SCANLINES
+    let color = textureSample(source, source_sampler, uv);
+    let spacing = max(stage.values.y, 1.0);
+    let dark = floor(panel_position.y) % spacing >= spacing * 0.5;
+    return vec4<f32>(color.rgb * select(1.0, 1.0 - stage.values.x, dark), color.a);
+#endif
+
+#ifdef BLOOM
+    // Light spreading into neighbouring LEDs through the diffuser
+    let color = textureSample(source, source_sampler, uv);
+    let radius = stage.values.y * pixel;
+    var glow = vec3<f32>(0.0);
+    for (var i = 0; i < 12; i++) {
+        let angle = f32(i) * TAU / 12.0;
+        let offset = vec2<f32>(cos(angle), sin(angle)) * radius * select(1.0, 2.0, i % 2 == 1);
+        glow += textureSample(source, source_sampler, uv + offset).rgb;
+    }
+    return vec4<f32>(color.rgb + glow / 12.0 * stage.values.x, color.a);
+#endif
+
+#ifdef PIXELATE
+    let size = max(stage.values.x, 1.0);
+    let block = (floor(panel_position / size) + 0.5) * size;
+    return textureSample(source, source_sampler, block * pixel);
+#endif
+
+#ifdef HUE_CYCLE
+    let color = textureSample(source, source_sampler, uv);
+    // Rotation around the gray axis
+    let angle = TAU * (stage.time * stage.values.x + stage.values.y);
+    let axis = vec3<f32>(0.57735);
+    let rotated = color.rgb * cos(angle) + cross(axis, color.rgb) * sin(angle)
+        + axis * dot(axis, color.rgb) * (1.0 - cos(angle));
+    return vec4<f32>(max(rotated, vec3<f32>(0.0)), color.a);
+#endif
+
+#ifdef GRADIENT_MAP
+    let color = textureSample(source, source_sampler, uv);
+    let brightness = luminance(color.rgb);
+    let mapped = rainbow(brightness * stage.values.z + stage.time * stage.values.y)
+        * clamp(brightness * 2.0, 0.0, 1.0);
+    return vec4<f32>(mix(color.rgb, mapped, stage.values.x), color.a);
+#endif
+}
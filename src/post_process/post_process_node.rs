@@ -0,0 +1,226 @@
+use bevy::{
+    asset::load_embedded_asset,
+    core_pipeline::FullscreenShader,
+    ecs::query::QueryItem,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        render_graph::{NodeRunError, RenderGraphContext, ViewNode},
+        render_resource::{
+            BindGroupEntries, BindGroupLayoutDescriptor, BindGroupLayoutEntries,
+            CachedRenderPipelineId, ColorTargetState, ColorWrites, DynamicUniformBuffer,
+            FilterMode, FragmentState, Operations, PipelineCache, RenderPassColorAttachment,
+            RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+            SamplerDescriptor, ShaderStages, ShaderType, SpecializedRenderPipeline,
+            SpecializedRenderPipelines, TextureFormat, TextureSampleType,
+            binding_types::{sampler, texture_2d, uniform_buffer},
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        view::{ExtractedView, ViewTarget},
+    },
+};
+
+use crate::{
+    layers::LayerCamera,
+    post_process::{PostProcessEffect, PostProcessFrame},
+};
+
+/// Uniform of one stage, see `post_process.wgsl`
+#[derive(Debug, Clone, ShaderType)]
+struct StageParams {
+    values: Vec4,
+    panel_size: Vec2,
+    time: f32,
+}
+
+#[derive(Resource)]
+pub struct PostProcessPipeline {
+    layout: BindGroupLayoutDescriptor,
+    sampler: Sampler,
+    fullscreen_shader: FullscreenShader,
+    fragment_shader: Handle<Shader>,
+}
+
+pub fn init_post_process_pipeline(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    fullscreen_shader: Res<FullscreenShader>,
+    asset_server: Res<AssetServer>,
+) {
+    let layout = BindGroupLayoutDescriptor::new(
+        "post_process_bind_group_layout",
+        &BindGroupLayoutEntries::sequential(
+            ShaderStages::FRAGMENT,
+            (
+                texture_2d(TextureSampleType::Float { filterable: true }),
+                sampler(SamplerBindingType::Filtering),
+                uniform_buffer::<StageParams>(true),
+            ),
+        ),
+    );
+    // Glitches and bloom sample between pixels of the render
+    let sampler = render_device.create_sampler(&SamplerDescriptor {
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        ..default()
+    });
+
+    commands.insert_resource(PostProcessPipeline {
+        layout,
+        sampler,
+        fullscreen_shader: fullscreen_shader.clone(),
+        fragment_shader: load_embedded_asset!(asset_server.as_ref(), "post_process.wgsl"),
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PostProcessPipelineKey {
+    effect: PostProcessEffect,
+    texture_format: TextureFormat,
+}
+
+impl SpecializedRenderPipeline for PostProcessPipeline {
+    type Key = PostProcessPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("post_process".into()),
+            layout: vec![self.layout.clone()],
+            vertex: self.fullscreen_shader.to_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.fragment_shader.clone(),
+                shader_defs: vec![key.effect.shader_def().into()],
+                targets: vec![Some(ColorTargetState {
+                    format: key.texture_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+                ..default()
+            }),
+            ..default()
+        }
+    }
+}
+
+/// Pipeline of every stage, in stack order
+#[derive(Component)]
+pub struct PostProcessPipelines(Vec<CachedRenderPipelineId>);
+
+/// Parameters of every stage of the frame, bound with a dynamic offset per stage
+#[derive(Resource, Default)]
+pub struct PostProcessUniforms {
+    buffer: DynamicUniformBuffer<StageParams>,
+    offsets: Vec<u32>,
+}
+
+pub fn prepare_post_process_uniforms(
+    frame: Res<PostProcessFrame>,
+    mut uniforms: ResMut<PostProcessUniforms>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let uniforms = &mut *uniforms;
+    uniforms.buffer.clear();
+    uniforms.offsets.clear();
+    for (_, values) in frame.stages.iter() {
+        uniforms.offsets.push(uniforms.buffer.push(&StageParams {
+            values: *values,
+            panel_size: frame.panel_size,
+            time: frame.time,
+        }));
+    }
+    uniforms.buffer.write_buffer(&render_device, &render_queue);
+}
+
+pub fn prepare_post_process_pipelines(
+    mut commands: Commands,
+    frame: Res<PostProcessFrame>,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<PostProcessPipeline>>,
+    post_process_pipeline: Res<PostProcessPipeline>,
+    views: Query<(Entity, &ExtractedView), With<LayerCamera>>,
+) {
+    for (entity, view) in views.iter() {
+        let texture_format = match view.hdr {
+            true => ViewTarget::TEXTURE_FORMAT_HDR,
+            false => TextureFormat::bevy_default(),
+        };
+        let stage_pipelines = frame
+            .stages
+            .iter()
+            .map(|(effect, _)| {
+                pipelines.specialize(
+                    &pipeline_cache,
+                    &post_process_pipeline,
+                    PostProcessPipelineKey {
+                        effect: *effect,
+                        texture_format,
+                    },
+                )
+            })
+            .collect();
+        commands
+            .entity(entity)
+            .insert(PostProcessPipelines(stage_pipelines));
+    }
+}
+
+/// Runs the stages one after another on the composited image of the layer camera
+#[derive(Default)]
+pub struct PostProcessNode;
+
+impl ViewNode for PostProcessNode {
+    type ViewQuery = (&'static ViewTarget, &'static PostProcessPipelines);
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (target, stage_pipelines): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let post_process_pipeline = world.resource::<PostProcessPipeline>();
+        let uniforms = world.resource::<PostProcessUniforms>();
+        let Some(uniform_binding) = uniforms.buffer.binding() else {
+            return Ok(());
+        };
+
+        for (pipeline, offset) in stage_pipelines.0.iter().zip(uniforms.offsets.iter()) {
+            // Still compiling, the stage is left out until it is ready
+            let Some(pipeline) = pipeline_cache.get_render_pipeline(*pipeline) else {
+                continue;
+            };
+
+            let post_process = target.post_process_write();
+            let bind_group = render_context.render_device().create_bind_group(
+                None,
+                &pipeline_cache.get_bind_group_layout(&post_process_pipeline.layout),
+                &BindGroupEntries::sequential((
+                    post_process.source,
+                    &post_process_pipeline.sampler,
+                    uniform_binding.clone(),
+                )),
+            );
+            let mut render_pass =
+                render_context
+                    .command_encoder()
+                    .begin_render_pass(&RenderPassDescriptor {
+                        label: Some("post_process"),
+                        color_attachments: &[Some(RenderPassColorAttachment {
+                            view: post_process.destination,
+                            depth_slice: None,
+                            resolve_target: None,
+                            ops: Operations::default(),
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[*offset]);
+            render_pass.draw(0..3, 0..1);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use bevy::{
+    asset::embedded_asset,
+    core_pipeline::core_2d::graph::{Core2d, Node2d},
+    platform::collections::HashMap,
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderStartup, RenderSystems,
+        extract_component::ExtractComponentPlugin,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_graph::{RenderGraphExt, RenderLabel, ViewNodeRunner},
+        render_resource::SpecializedRenderPipelines,
+    },
+};
+use serde::Deserialize;
+
+use crate::{
+    face::MorphControls,
+    layers::LayerCamera,
+    output::PanelLayout,
+    post_process::post_process_node::{
+        PostProcessNode, PostProcessPipeline, PostProcessUniforms, init_post_process_pipeline,
+        prepare_post_process_pipelines, prepare_post_process_uniforms,
+    },
+};
+
+/// Post-process effects for the look of LED panels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostProcessEffect {
+    // Rows jumping sideways and color channels splitting apart
+    Glitch,
+    // Darkened rows between lit ones
+    Scanlines,
+    // Light bleeding into neighbouring LEDs through the diffuser
+    Bloom,
+    // Blocks of panel pixels showing a single color
+    Pixelate,
+    // Hue rotating over time
+    HueCycle,
+    // Brightness mapped onto a moving rainbow
+    GradientMap,
+}
+
+impl PostProcessEffect {
+    /// Names and defaults of the parameters, in the order the shader receives them
+    pub fn params(self) -> &'static [(&'static str, f32)] {
+        match self {
+            PostProcessEffect::Glitch => &[("amount", 0.3), ("speed", 8.0)],
+            PostProcessEffect::Scanlines => &[("strength", 0.5), ("spacing", 2.0)],
+            PostProcessEffect::Bloom => &[("strength", 0.6), ("radius", 1.5)],
+            PostProcessEffect::Pixelate => &[("size", 2.0)],
+            PostProcessEffect::HueCycle => &[("speed", 0.25), ("offset", 0.0)],
+            PostProcessEffect::GradientMap => &[("mix", 1.0), ("speed", 0.1), ("scale", 1.0)],
+        }
+    }
+
+    /// Shader def selecting the effect in `post_process.wgsl`
+    pub fn shader_def(self) -> &'static str {
+        match self {
+            PostProcessEffect::Glitch => "GLITCH",
+            PostProcessEffect::Scanlines => "SCANLINES",
+            PostProcessEffect::Bloom => "BLOOM",
+            PostProcessEffect::Pixelate => "PIXELATE",
+            PostProcessEffect::HueCycle => "HUE_CYCLE",
+            PostProcessEffect::GradientMap => "GRADIENT_MAP",
+        }
+    }
+}
+
+/// Value of a stage parameter, fixed or following a morph target so expressions animate it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum StageParam {
+    Value(f32),
+    // `offset + weight * scale` of the shape key, `target` or `node/target`
+    Morph {
+        morph: String,
+        #[serde(default = "default_scale")]
+        scale: f32,
+        #[serde(default)]
+        offset: f32,
+    },
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/// One effect of the stack with its parameters.
+///
+/// ```json
+/// { "effect": "glitch", "amount": { "morph": "angry", "scale": 0.8 }, "speed": 12 }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct PostProcessStage {
+    pub effect: PostProcessEffect,
+    // Parameters by name, missing ones keep the effect's defaults
+    #[serde(flatten)]
+    pub params: HashMap<String, StageParam>,
+}
+
+impl PostProcessStage {
+    /// Fails for parameters the effect does not have
+    pub fn validate(&self) -> Result<(), String> {
+        let known = self.effect.params();
+        match self
+            .params
+            .keys()
+            .find(|name| !known.iter().any(|(known, _)| known == name))
+        {
+            Some(name) => Err(format!(
+                "{:?} has no parameter `{name}`, it has {:?}",
+                self.effect,
+                known.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Stages and their parameter values of the current frame, handed to the render world
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub struct PostProcessFrame {
+    pub stages: Vec<(PostProcessEffect, Vec4)>,
+    pub panel_size: Vec2,
+    pub time: f32,
+}
+
+/// Resolves the parameters of every stage, after expressions moved the morph targets
+fn evaluate_post_process(
+    time: Res<Time>,
+    layout: Res<PanelLayout>,
+    morphs: Query<(&MorphControls, Option<&Name>)>,
+    mut frame: ResMut<PostProcessFrame>,
+) {
+    frame.panel_size = Vec2::new(layout.size.width as f32, layout.size.height as f32);
+    frame.time = time.elapsed_secs();
+    frame.stages.clear();
+    for stage in layout.post_process.iter() {
+        let mut values = Vec4::ZERO;
+        for (index, (name, default)) in stage.effect.params().iter().enumerate() {
+            values[index] = match stage.params.get(*name) {
+                Some(StageParam::Value(value)) => *value,
+                Some(StageParam::Morph {
+                    morph,
+                    scale,
+                    offset,
+                }) => offset + MorphControls::find_weight(morphs.iter(), morph) * scale,
+                None => *default,
+            };
+        }
+        frame.stages.push((stage.effect, values));
+    }
+}
+
+/// Render graph node of the stack, passes over the final composite go after it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, RenderLabel)]
+pub struct PostProcessLabel;
+
+/// Runs the post-process stack of the [`PanelLayout`] over the composited image of the
+/// [`LayerCamera`], before `ImageCopyDriver` copies it
+pub struct PostProcessPlugin;
+
+impl Plugin for PostProcessPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "post_process.wgsl");
+        app.init_resource::<PostProcessFrame>()
+            .add_plugins(ExtractResourcePlugin::<PostProcessFrame>::default())
+            .add_plugins(ExtractComponentPlugin::<LayerCamera>::default())
+            .add_systems(PostUpdate, evaluate_post_process);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedRenderPipelines<PostProcessPipeline>>()
+            .init_resource::<PostProcessUniforms>()
+            .add_systems(RenderStartup, init_post_process_pipeline)
+            .add_systems(
+                Render,
+                (
+                    prepare_post_process_uniforms,
+                    prepare_post_process_pipelines,
+                )
+                    .in_set(RenderSystems::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<PostProcessNode>>(Core2d, PostProcessLabel)
+            .add_render_graph_edges(
+                Core2d,
+                (
+                    Node2d::Tonemapping,
+                    PostProcessLabel,
+                    Node2d::EndMainPassPostProcessing,
+                ),
+            );
+    }
+}
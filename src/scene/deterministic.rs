@@ -0,0 +1,71 @@
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::time::Duration;
+
+use crate::scene::{SceneController, SceneState};
+
+/// Root of every random sequence in the app, systems needing randomness derive their own
+/// generator from it so adding one does not shift the numbers another one sees
+#[derive(Debug, Clone, Copy, Default, Resource)]
+pub struct RandomSeed(pub u64);
+
+impl RandomSeed {
+    /// Independent generator for the named stream
+    pub fn rng(&self, stream: &str) -> ChaCha8Rng {
+        // FNV-1a, std hashers are not guaranteed to be stable across releases
+        let hash = stream
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+            });
+        ChaCha8Rng::seed_from_u64(self.0 ^ hash)
+    }
+}
+
+/// Makes frame N of a capture identical across runs.
+///
+/// `Time` advances by exactly `step` per frame instead of by wall clock, and stays paused
+/// while the scene is being built so animations start at zero on the first captured frame
+/// no matter how long loading took.
+pub struct DeterministicPlugin {
+    pub step: Duration,
+}
+
+impl Plugin for DeterministicPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(self.step))
+            .add_systems(Startup, pause_virtual_time)
+            .add_systems(Last, resume_virtual_time);
+    }
+}
+
+fn pause_virtual_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+/// Runs at the end of the frame the scene became ready in, so the next frame is one step later
+fn resume_virtual_time(mut time: ResMut<Time<Virtual>>, scene_controller: Res<SceneController>) {
+    if time.is_paused() && matches!(scene_controller.state, SceneState::Render) {
+        time.unpause();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn numbers(seed: u64, stream: &str) -> Vec<u64> {
+        let mut rng = RandomSeed(seed).rng(stream);
+        (0..16).map(|_| rng.random()).collect()
+    }
+
+    #[test]
+    fn same_seed_and_stream_give_the_same_numbers() {
+        assert_eq!(numbers(42, "eyes"), numbers(42, "eyes"));
+        assert_ne!(numbers(42, "eyes"), numbers(43, "eyes"));
+        // Streams of one seed are independent
+        assert_ne!(numbers(42, "eyes"), numbers(42, "playlist"));
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::{
+    asset::io::file::FileAssetReader, math::curve::easing::EaseFunction, prelude::*,
+    time::common_conditions::on_real_timer,
+};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    face::FaceScene,
+    output::PanelLayout,
+    scene::SceneController,
+    transition::{TransitionKind, TransitionRequest},
+};
+
+/// Time between checks whether a watched file changed
+pub const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Settings that can change while running, read from a JSON file given with `--config`.
+/// Its values take precedence over the matching flags.
+///
+/// ```json
+/// { "width": 1280, "height": 720, "face": "faces/cat.glb" }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RuntimeConfig {
+    // Resolution of the capture target
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    // glTF/GLB face asset, relative to the `assets` folder
+    pub face: Option<String>,
+}
+
+impl RuntimeConfig {
+    pub fn load(path: &Path) -> Result<RuntimeConfig, String> {
+        let file = std::fs::read_to_string(path).map_err(|e| format!("{path:?}: {e}"))?;
+        let config: RuntimeConfig =
+            serde_json::from_str(&file).map_err(|e| format!("{path:?}: {e}"))?;
+        if config.width == Some(0) || config.height == Some(0) {
+            return Err(format!("{path:?}: the resolution can not be zero"));
+        }
+        Ok(config)
+    }
+}
+
+/// File checked for changes by its modification time
+#[derive(Debug)]
+pub struct WatchedFile {
+    path: PathBuf,
+    // Modification time when the file was last checked
+    modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    pub fn new(path: PathBuf) -> WatchedFile {
+        let modified = modified(&path);
+        WatchedFile { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file was written since the last call
+    pub fn changed(&mut self) -> bool {
+        let modified = modified(&self.path);
+        let changed = modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[derive(Debug, Resource)]
+struct WatchedLayout(WatchedFile);
+
+#[derive(Debug, Resource)]
+struct WatchedConfig(WatchedFile);
+
+/// Reloads the face asset, the panel layout and the [`RuntimeConfig`] when their files
+/// change. Capture keeps running: layers are respawned, a new resolution recreates the
+/// capture target and a new face replaces the old one.
+pub struct HotReloadPlugin {
+    pub layout: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+}
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            reload_face_scene.run_if(on_real_timer(RELOAD_INTERVAL)),
+        );
+        if let Some(layout) = self.layout.clone() {
+            app.insert_resource(WatchedLayout(WatchedFile::new(layout)))
+                .add_systems(Update, reload_layout.run_if(on_real_timer(RELOAD_INTERVAL)));
+        }
+        if let Some(config) = self.config.clone() {
+            app.insert_resource(WatchedConfig(WatchedFile::new(config)))
+                .add_systems(Update, reload_config.run_if(on_real_timer(RELOAD_INTERVAL)));
+        }
+    }
+}
+
+/// Reloads the glTF of the current face, the scene spawner respawns it in place and
+/// `FaceScenePlugin` tags its nodes again
+fn reload_face_scene(
+    face_scene: Res<FaceScene>,
+    asset_server: Res<AssetServer>,
+    mut watched: Local<Option<WatchedFile>>,
+) {
+    let path = FileAssetReader::get_base_path()
+        .join("assets")
+        .join(&face_scene.path);
+    // Follows the face when a transition switched to another one
+    let watched = match watched.as_mut() {
+        Some(watched) if watched.path == path => watched,
+        _ => watched.insert(WatchedFile::new(path)),
+    };
+    if watched.changed() {
+        info!("Reloading face scene {}", face_scene.path);
+        asset_server.reload(face_scene.path.clone());
+    }
+}
+
+fn reload_layout(mut watched: ResMut<WatchedLayout>, mut layout: ResMut<PanelLayout>) {
+    if !watched.0.changed() {
+        return;
+    }
+    let path = &watched.0.path;
+    match PanelLayout::load(path, layout.size) {
+        // The output sinks were opened for the panel size
+        Ok(reloaded) if reloaded.size != layout.size => warn!(
+            "Ignoring the reloaded panel layout {path:?}, changing the panel size needs a restart"
+        ),
+        Ok(reloaded) => {
+            info!("Reloaded panel layout {path:?}");
+            *layout = reloaded;
+        }
+        Err(e) => error!("Failed to reload panel layout {e}"),
+    }
+}
+
+fn reload_config(
+    mut watched: ResMut<WatchedConfig>,
+    mut scene_controller: ResMut<SceneController>,
+    face_scene: Res<FaceScene>,
+    mut transitions: MessageWriter<TransitionRequest>,
+) {
+    if !watched.0.changed() {
+        return;
+    }
+    let path = &watched.0.path;
+    let config = match RuntimeConfig::load(path) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to reload config {e}");
+            return;
+        }
+    };
+    info!("Reloaded config {path:?}");
+
+    // `ImageCopyPlugin` recreates the capture target for the new size
+    let width = config.width.unwrap_or(scene_controller.width);
+    let height = config.height.unwrap_or(scene_controller.height);
+    if (width, height) != (scene_controller.width, scene_controller.height) {
+        scene_controller.width = width;
+        scene_controller.height = height;
+    }
+    if let Some(face) = config.face
+        && face != face_scene.path
+    {
+        transitions.write(TransitionRequest {
+            scene: FaceScene::new(face),
+            kind: TransitionKind::Crossfade,
+            duration: 0.0,
+            easing: EaseFunction::Linear,
+        });
+    }
+}
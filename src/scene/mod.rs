@@ -1,2 +1,10 @@
+mod deterministic;
+pub use deterministic::{DeterministicPlugin, RandomSeed};
+mod hot_reload;
+pub use hot_reload::{HotReloadPlugin, RELOAD_INTERVAL, RuntimeConfig, WatchedFile};
+mod playlist;
+pub use playlist::{Playlist, PlaylistAction, PlaylistPlugin, PlaylistStatus};
+mod readiness;
+pub use readiness::{ReadinessGate, ReadinessPlugin};
 mod scene_controller;
 pub use scene_controller::{SceneController, SceneState};
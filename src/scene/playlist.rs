@@ -0,0 +1,473 @@
+use bevy::{
+    ecs::message::{MessageCursor, Messages},
+    math::curve::easing::EaseFunction,
+    prelude::*,
+};
+use rand::{Rng, seq::SliceRandom};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::{
+    path::Path,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    control::ControlCommand,
+    face::FaceScene,
+    scene::{RandomSeed, SceneController, SceneState},
+    transition::{TransitionKind, TransitionRequest},
+};
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+fn default_transition_duration() -> f32 {
+    1.0
+}
+
+fn default_manual_hold() -> f32 {
+    30.0
+}
+
+fn default_start_hour() -> f32 {
+    12.0
+}
+
+/// Order the playlist picks its entries in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistMode {
+    // Entries in file order
+    #[default]
+    Sequence,
+    // Every entry once in random order, then reshuffled
+    Shuffle,
+    // Random entries, more likely the higher their weight
+    Weighted,
+}
+
+/// Face and expression shown for a while
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistEntry {
+    // Face asset to switch to, the current face stays when `None`
+    pub scene: Option<String>,
+    pub expression: Option<String>,
+    // Seconds the entry is shown
+    pub duration: f32,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    #[serde(default)]
+    pub transition: TransitionKind,
+    #[serde(default = "default_transition_duration")]
+    pub transition_duration: f32,
+    // Local hours `[from, to)` the entry can be picked in, past midnight when `from > to`
+    pub hours: Option<[f32; 2]>,
+}
+
+impl PlaylistEntry {
+    fn in_hours(&self, hour: f32) -> bool {
+        match self.hours {
+            None => true,
+            Some([from, to]) if from <= to => hour >= from && hour < to,
+            Some([from, to]) => hour >= from || hour < to,
+        }
+    }
+
+    fn label(&self) -> String {
+        [self.scene.as_deref(), self.expression.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Faces and expressions cycled through while idle, read from a JSON file.
+///
+/// ```json
+/// {
+///     "mode": "weighted",
+///     "manual_hold": 20,
+///     "utc_offset": 2,
+///     "start_hour": 21.5,
+///     "entries": [
+///         { "scene": "faces/cat.glb", "expression": "happy", "duration": 60, "weight": 3 },
+///         { "expression": "angry", "duration": 10, "transition": "dissolve", "hours": [22, 6] }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, Resource, Deserialize)]
+pub struct Playlist {
+    #[serde(default)]
+    pub mode: PlaylistMode,
+    pub entries: Vec<PlaylistEntry>,
+    // Seconds a manual expression or scene command holds the playlist
+    #[serde(default = "default_manual_hold")]
+    pub manual_hold: f32,
+    // Hours local time is ahead of UTC, for the hours of entries
+    #[serde(default)]
+    pub utc_offset: f32,
+    // Local hour at the first frame in deterministic mode, which follows simulated time
+    // instead of the wall clock so the same frames pick the same entries on every run
+    #[serde(default = "default_start_hour")]
+    pub start_hour: f32,
+}
+
+impl Playlist {
+    pub fn load(path: &Path) -> Result<Playlist, String> {
+        let file = std::fs::read_to_string(path).map_err(|e| format!("{path:?}: {e}"))?;
+        let playlist: Playlist =
+            serde_json::from_str(&file).map_err(|e| format!("{path:?}: {e}"))?;
+
+        if playlist.entries.is_empty() {
+            return Err(format!("{path:?}: the playlist has no entries"));
+        }
+        for (index, entry) in playlist.entries.iter().enumerate() {
+            let problem = if entry.scene.is_none() && entry.expression.is_none() {
+                "sets neither a scene nor an expression"
+            } else if entry.duration <= 0.0 {
+                "needs a positive duration"
+            } else if entry.weight < 0.0 {
+                "has a negative weight"
+            } else if entry
+                .hours
+                .is_some_and(|hours| hours.iter().any(|hour| !(0.0..=24.0).contains(hour)))
+            {
+                "has hours outside 0..24"
+            } else {
+                continue;
+            };
+            return Err(format!("{path:?}: entry {index} {problem}"));
+        }
+        if !(0.0..24.0).contains(&playlist.start_hour) {
+            return Err(format!("{path:?}: start_hour is outside 0..24"));
+        }
+        Ok(playlist)
+    }
+
+    /// Hour of the local day, from the wall clock
+    fn local_hour(&self) -> f32 {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since| since.as_secs_f64());
+        (seconds / 3600.0 + self.utc_offset as f64).rem_euclid(24.0) as f32
+    }
+
+    /// Hour of the local day, `elapsed` seconds of simulated time after `start_hour`
+    fn simulated_hour(&self, elapsed: f64) -> f32 {
+        (self.start_hour as f64 + elapsed / 3600.0).rem_euclid(24.0) as f32
+    }
+}
+
+/// Where the playlist is at, see [`SceneController::playlist`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaylistStatus {
+    // Entry shown and seconds until the next one
+    Playing { entry: usize, remaining: f32 },
+    // Held by a manual command, resumes after `remaining` seconds, never when `None`
+    Manual { remaining: Option<f32> },
+}
+
+/// Runtime control of the playlist
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistAction {
+    // Skip to the next entry, ending a manual hold
+    Next,
+    // Hold the playlist until it is resumed
+    Pause,
+    // Continue with the next entry
+    Resume,
+}
+
+impl FromStr for PlaylistAction {
+    type Err = String;
+
+    fn from_str(action: &str) -> Result<PlaylistAction, String> {
+        match action {
+            "next" => Ok(PlaylistAction::Next),
+            "pause" => Ok(PlaylistAction::Pause),
+            "resume" => Ok(PlaylistAction::Resume),
+            other => Err(format!(
+                "unknown playlist action `{other}`, expected next, pause or resume"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Resource)]
+struct PlaylistPlayer {
+    rng: ChaCha8Rng,
+    clock: PlaylistClock,
+    // Entries not shown yet in this round of `PlaylistMode::Shuffle`
+    bag: Vec<usize>,
+    // `PlaylistMode::Sequence` continues after it
+    last: Option<usize>,
+    // Past the control commands the playlist wrote itself, so only manual ones hold it
+    commands: MessageCursor<ControlCommand>,
+}
+
+impl PlaylistPlayer {
+    /// Next entry to show at `hour` of the local day
+    fn pick(&mut self, playlist: &Playlist, hour: f32) -> usize {
+        let count = playlist.entries.len();
+        let eligible = (0..count)
+            .filter(|index| playlist.entries[*index].in_hours(hour))
+            .collect::<Vec<_>>();
+        // Outside the hours of every entry all of them can be shown rather than none
+        let eligible = match eligible.is_empty() {
+            true => (0..count).collect(),
+            false => eligible,
+        };
+
+        let next = match playlist.mode {
+            PlaylistMode::Sequence => {
+                let start = self.last.map_or(0, |last| last + 1);
+                (0..count)
+                    .map(|offset| (start + offset) % count)
+                    .find(|index| eligible.contains(index))
+                    .unwrap_or(0)
+            }
+            PlaylistMode::Shuffle => {
+                if !self.bag.iter().any(|index| eligible.contains(index)) {
+                    self.bag = (0..count).collect();
+                    self.bag.shuffle(&mut self.rng);
+                }
+                let mut positions = (0..self.bag.len())
+                    .rev()
+                    .filter(|position| eligible.contains(&self.bag[*position]));
+                let position = positions.next().unwrap_or(0);
+                // Not the same entry twice in a row across rounds
+                let position = match positions.next() {
+                    Some(other) if Some(self.bag[position]) == self.last => other,
+                    _ => position,
+                };
+                self.bag.remove(position)
+            }
+            PlaylistMode::Weighted => {
+                // Not the same entry twice in a row when there is a choice
+                let candidates = match eligible.len() > 1 {
+                    true => eligible
+                        .into_iter()
+                        .filter(|index| Some(*index) != self.last)
+                        .collect(),
+                    false => eligible,
+                };
+                let weight = |index: &usize| playlist.entries[*index].weight;
+                let total = candidates.iter().map(weight).sum::<f32>();
+                let mut choice = self.rng.random::<f32>() * total;
+                match total > 0.0 {
+                    true => *candidates
+                        .iter()
+                        .find(|index| {
+                            choice -= weight(index);
+                            choice < 0.0
+                        })
+                        .unwrap_or(&candidates[candidates.len() - 1]),
+                    false => candidates[self.rng.random_range(0..candidates.len())],
+                }
+            }
+        };
+        self.last = Some(next);
+        next
+    }
+}
+
+/// Cycles faces and expressions from a [`Playlist`] once capture started. Manual
+/// `expression` and `scene` commands hold it for `manual_hold` seconds, `playlist next`,
+/// `pause` and `resume` control it directly.
+pub struct PlaylistPlugin {
+    pub playlist: Playlist,
+    // Takes the hour from simulated time, see `Playlist::start_hour`
+    pub deterministic: bool,
+}
+
+impl Plugin for PlaylistPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.playlist.clone())
+            .insert_resource(match self.deterministic {
+                true => PlaylistClock::Simulated,
+                false => PlaylistClock::WallClock,
+            })
+            .add_systems(Startup, init_playlist_player)
+            .add_systems(Update, run_playlist);
+    }
+}
+
+/// Where the hour of the day for the hours of entries comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+enum PlaylistClock {
+    WallClock,
+    // Simulated time since `Playlist::start_hour`
+    Simulated,
+}
+
+fn init_playlist_player(
+    mut commands: Commands,
+    random_seed: Res<RandomSeed>,
+    clock: Res<PlaylistClock>,
+) {
+    commands.insert_resource(PlaylistPlayer {
+        rng: random_seed.rng("playlist"),
+        clock: *clock,
+        bag: Vec::new(),
+        last: None,
+        commands: MessageCursor::default(),
+    });
+}
+
+fn run_playlist(
+    time: Res<Time>,
+    playlist: Res<Playlist>,
+    mut player: ResMut<PlaylistPlayer>,
+    mut scene_controller: ResMut<SceneController>,
+    mut control_commands: ResMut<Messages<ControlCommand>>,
+    mut transitions: MessageWriter<TransitionRequest>,
+    face_scene: Res<FaceScene>,
+) {
+    if !matches!(scene_controller.state, SceneState::Render) {
+        return;
+    }
+
+    let player = &mut *player;
+    let status = &mut scene_controller.playlist;
+    let mut next = status.is_none();
+    for command in player.commands.read(&control_commands) {
+        match command {
+            ControlCommand::Expression { .. } | ControlCommand::Scene { .. } => {
+                // A pause stays until it is resumed
+                if *status != Some(PlaylistStatus::Manual { remaining: None }) {
+                    *status = Some(PlaylistStatus::Manual {
+                        remaining: Some(playlist.manual_hold),
+                    });
+                }
+                next = false;
+            }
+            ControlCommand::Playlist { action } => {
+                info!("Playlist {action:?}");
+                match action {
+                    PlaylistAction::Next | PlaylistAction::Resume => next = true,
+                    PlaylistAction::Pause => {
+                        *status = Some(PlaylistStatus::Manual { remaining: None });
+                        next = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(
+        PlaylistStatus::Playing { remaining, .. }
+        | PlaylistStatus::Manual {
+            remaining: Some(remaining),
+        },
+    ) = status
+    {
+        *remaining -= time.delta_secs();
+        next |= *remaining <= 0.0;
+    }
+    if !next {
+        return;
+    }
+
+    let hour = match player.clock {
+        PlaylistClock::WallClock => playlist.local_hour(),
+        PlaylistClock::Simulated => playlist.simulated_hour(time.elapsed_secs_f64()),
+    };
+    let index = player.pick(&playlist, hour);
+    let entry = &playlist.entries[index];
+    info!("Playlist entry {index}: {}", entry.label());
+    if let Some(scene) = entry.scene.as_ref()
+        && *scene != face_scene.path
+    {
+        transitions.write(TransitionRequest {
+            scene: FaceScene::new(scene.clone()),
+            kind: entry.transition,
+            duration: entry.transition_duration,
+            easing: EaseFunction::SmoothStep,
+        });
+    }
+    if let Some(expression) = entry.expression.as_ref() {
+        control_commands.write(ControlCommand::Expression {
+            name: expression.clone(),
+            duration: None,
+        });
+    }
+    *status = Some(PlaylistStatus::Playing {
+        entry: index,
+        remaining: entry.duration,
+    });
+    // The expression above is the playlist's own, not a manual one
+    player.commands.clear(&control_commands);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(mode: &str) -> Playlist {
+        serde_json::from_value(serde_json::json!({
+            "mode": mode,
+            "start_hour": 22,
+            "entries": [
+                { "expression": "happy", "duration": 10, "hours": [8, 20] },
+                { "expression": "sleepy", "duration": 10, "hours": [22, 6] },
+                { "expression": "angry", "duration": 10, "hours": [12, 13] },
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn player() -> PlaylistPlayer {
+        PlaylistPlayer {
+            rng: RandomSeed(0).rng("playlist"),
+            clock: PlaylistClock::Simulated,
+            bag: Vec::new(),
+            last: None,
+            commands: MessageCursor::default(),
+        }
+    }
+
+    #[test]
+    fn simulated_hours_start_at_the_start_hour() {
+        let playlist = playlist("sequence");
+        assert_eq!(playlist.simulated_hour(0.0), 22.0);
+        assert_eq!(playlist.simulated_hour(1800.0), 22.5);
+        // Past midnight
+        assert_eq!(playlist.simulated_hour(3.0 * 3600.0), 1.0);
+    }
+
+    #[test]
+    fn entries_are_picked_by_hour() {
+        for mode in ["sequence", "shuffle", "weighted"] {
+            let playlist = playlist(mode);
+            let mut player = player();
+            for _ in 0..4 {
+                assert_eq!(player.pick(&playlist, 23.0), 1, "{mode}");
+                assert_eq!(player.pick(&playlist, 3.0), 1, "{mode}");
+                assert_eq!(player.pick(&playlist, 9.0), 0, "{mode}");
+            }
+        }
+
+        // Sequence mode moves on to the next entry in hours
+        let playlist = playlist("sequence");
+        let mut player = player();
+        assert_eq!(player.pick(&playlist, 12.5), 0);
+        assert_eq!(player.pick(&playlist, 12.5), 2);
+        assert_eq!(player.pick(&playlist, 12.5), 0);
+    }
+
+    #[test]
+    fn every_entry_is_eligible_outside_all_hours() {
+        let playlist = playlist("sequence");
+        let mut player = player();
+        assert_eq!(
+            (0..3)
+                .map(|_| player.pick(&playlist, 21.0))
+                .collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use bevy::{
+    app::AppExit,
+    asset::{LoadState, RecursiveDependencyLoadState, UntypedHandle},
+    platform::{collections::HashSet, time::Instant},
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderSystems,
+        render_resource::{CachedPipelineState, PipelineCache, PipelineDescriptor},
+    },
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    layers::EFFECT_PIPELINE_LABEL,
+    scene::{SceneController, SceneState},
+};
+
+/// Decides when the scene is fully ready and capture may start.
+///
+/// Frames are only captured once
+/// 1. the scene reported it was spawned (see [`ReadinessGate::mark_scene_spawned`]),
+/// 2. every tracked asset and its dependencies are loaded,
+/// 3. the render world has no pipeline left waiting for compilation or shaders,
+/// 4. `settle_frames` more frames were rendered.
+///
+/// If this does not happen within `timeout` the app exits with an error listing
+/// everything that never became ready.
+/// A failed asset or pipeline exits right away, except for effect layer pipelines,
+/// which only lose their effect.
+#[derive(Debug, Resource)]
+pub struct ReadinessGate {
+    pub settle_frames: u32,
+    pub timeout: Duration,
+    tracked: Vec<(String, UntypedHandle)>,
+    scene_spawned: bool,
+    // Consecutive render frames without waiting pipelines
+    clean_pipeline_frames: u32,
+    last_pipeline_frame: u64,
+    settle_remaining: Option<u32>,
+    gave_up: bool,
+    // Wall clock, `Time` may be paused or stepped manually while loading
+    started: Option<Instant>,
+}
+
+impl ReadinessGate {
+    // A pipeline cache with nothing waiting right after spawning may just mean meshes were
+    // not queued yet, so it has to stay clean for a few render frames
+    const CLEAN_PIPELINE_FRAMES: u32 = 2;
+
+    pub fn new(settle_frames: u32, timeout: Duration) -> ReadinessGate {
+        ReadinessGate {
+            settle_frames,
+            timeout,
+            tracked: Vec::new(),
+            scene_spawned: false,
+            clean_pipeline_frames: 0,
+            last_pipeline_frame: 0,
+            settle_remaining: None,
+            gave_up: false,
+            started: None,
+        }
+    }
+
+    /// Adds an asset that must be loaded, with all its dependencies, before capture
+    pub fn track(&mut self, label: impl Into<String>, handle: impl Into<UntypedHandle>) {
+        self.tracked.push((label.into(), handle.into()));
+    }
+
+    /// Signals that the scene entities exist
+    pub fn mark_scene_spawned(&mut self) {
+        self.scene_spawned = true;
+    }
+
+    fn pending_assets(&self, asset_server: &AssetServer) -> Vec<String> {
+        self.tracked
+            .iter()
+            .filter_map(|(label, handle)| {
+                let state = match asset_server.get_load_states(handle.id()) {
+                    Some((_, _, RecursiveDependencyLoadState::Loaded)) => return None,
+                    Some((LoadState::Failed(err), _, _)) => format!("failed: {err}"),
+                    Some((_, _, RecursiveDependencyLoadState::Failed(err))) => {
+                        format!("dependency failed: {err}")
+                    }
+                    Some((load_state, _, _)) => format!("{load_state:?}"),
+                    None => String::from("unknown to the asset server"),
+                };
+                Some(format!("asset `{label}` ({state})"))
+            })
+            .collect()
+    }
+
+    fn failed_assets(&self, asset_server: &AssetServer) -> bool {
+        self.tracked.iter().any(|(_, handle)| {
+            matches!(
+                asset_server.get_load_states(handle.id()),
+                Some((LoadState::Failed(_), _, _))
+                    | Some((_, _, RecursiveDependencyLoadState::Failed(_)))
+            )
+        })
+    }
+}
+
+impl Default for ReadinessGate {
+    fn default() -> Self {
+        ReadinessGate::new(2, Duration::from_secs(30))
+    }
+}
+
+/// Pipeline compilation state reported by the render world
+#[derive(Debug, Default)]
+struct PipelineStatus {
+    // Incremented every render frame, tells the main world the status is fresh
+    frame: u64,
+    waiting: Vec<String>,
+    failed: Vec<String>,
+}
+
+/// Shared between the main world and the render world, like `ImageCopier::enabled`
+#[derive(Clone, Default, Resource, Deref)]
+struct PipelineReadiness(Arc<Mutex<PipelineStatus>>);
+
+/// Holds capture in `SceneState::BuildScene` until the scene is ready
+pub struct ReadinessPlugin;
+
+impl Plugin for ReadinessPlugin {
+    fn build(&self, app: &mut App) {
+        let pipeline_readiness = PipelineReadiness::default();
+
+        app.init_resource::<ReadinessGate>()
+            .insert_resource(pipeline_readiness.clone())
+            .add_systems(PreUpdate, check_readiness);
+
+        app.sub_app_mut(RenderApp)
+            .insert_resource(pipeline_readiness)
+            .add_systems(Render, report_pipeline_status.after(RenderSystems::Render));
+    }
+}
+
+fn pipeline_label(descriptor: &PipelineDescriptor) -> String {
+    let label = match descriptor {
+        PipelineDescriptor::RenderPipelineDescriptor(descriptor) => descriptor.label.as_deref(),
+        PipelineDescriptor::ComputePipelineDescriptor(descriptor) => descriptor.label.as_deref(),
+    };
+    String::from(label.unwrap_or("unnamed pipeline"))
+}
+
+/// runs in render world after Render stage, pipelines waiting on shaders stay queued
+fn report_pipeline_status(
+    pipeline_cache: Res<PipelineCache>,
+    pipeline_readiness: Res<PipelineReadiness>,
+    mut failed_effects: Local<HashSet<usize>>,
+) {
+    let mut status = pipeline_readiness.lock().unwrap();
+    status.frame += 1;
+    status.waiting.clear();
+    status.failed.clear();
+
+    let waiting = pipeline_cache.waiting_pipelines().collect::<HashSet<_>>();
+    for (id, pipeline) in pipeline_cache.pipelines().enumerate() {
+        let label = pipeline_label(&pipeline.descriptor);
+        match &pipeline.state {
+            CachedPipelineState::Ok(_) => {}
+            // Pipelines whose shaders are still loading report an error
+            // but stay in the waiting set until the shader arrives
+            // An effect naga accepted can still fail to compile for the gpu, that only
+            // loses the effect instead of the whole scene
+            CachedPipelineState::Err(err) if !waiting.contains(&id) => {
+                if label != EFFECT_PIPELINE_LABEL {
+                    status.failed.push(format!("{label} ({err})"));
+                } else if failed_effects.insert(id) {
+                    error!("Effect pipeline failed, the effect is not drawn: {err}");
+                }
+            }
+            _ => status.waiting.push(label),
+        }
+    }
+}
+
+fn check_readiness(
+    mut gate: ResMut<ReadinessGate>,
+    mut scene_controller: ResMut<SceneController>,
+    pipeline_readiness: Res<PipelineReadiness>,
+    asset_server: Res<AssetServer>,
+    mut app_exit_writer: MessageWriter<AppExit>,
+) {
+    if gate.gave_up || !matches!(scene_controller.state, SceneState::BuildScene) {
+        return;
+    }
+    let started = *gate.started.get_or_insert_with(Instant::now);
+
+    let pending_assets = gate.pending_assets(&asset_server);
+    let (pipeline_frame, pipelines_waiting, pipelines_failed) = {
+        let status = pipeline_readiness.lock().unwrap();
+        (status.frame, status.waiting.clone(), status.failed.clone())
+    };
+
+    if gate.scene_spawned && pending_assets.is_empty() && pipelines_failed.is_empty() {
+        if pipeline_frame != gate.last_pipeline_frame {
+            gate.last_pipeline_frame = pipeline_frame;
+            if pipelines_waiting.is_empty() {
+                gate.clean_pipeline_frames += 1;
+            } else {
+                gate.clean_pipeline_frames = 0;
+            }
+        }
+
+        if gate.clean_pipeline_frames >= ReadinessGate::CLEAN_PIPELINE_FRAMES {
+            let settle_frames = gate.settle_frames;
+            let remaining = gate.settle_remaining.get_or_insert(settle_frames);
+            if *remaining == 0 {
+                info!(
+                    "Scene ready after {:.2}s, starting capture",
+                    started.elapsed().as_secs_f32()
+                );
+                scene_controller.state = SceneState::Render;
+                return;
+            }
+            *remaining -= 1;
+            return;
+        }
+    }
+
+    let failed = gate.failed_assets(&asset_server) || !pipelines_failed.is_empty();
+    if !failed && started.elapsed() < gate.timeout {
+        return;
+    }
+
+    // Either something failed for good or the timeout was hit, list what never became ready
+    let mut not_ready = Vec::new();
+    if !gate.scene_spawned {
+        not_ready.push(String::from("scene was never spawned"));
+    }
+    not_ready.extend(pending_assets);
+    not_ready.extend(
+        pipelines_waiting
+            .into_iter()
+            .map(|label| format!("pipeline `{label}` still compiling")),
+    );
+    not_ready.extend(
+        pipelines_failed
+            .into_iter()
+            .map(|label| format!("pipeline `{label}` failed")),
+    );
+    if not_ready.is_empty() {
+        not_ready.push(String::from("render world reported no new frames"));
+    }
+
+    if failed {
+        error!("Scene `{}` failed to load:", scene_controller.name);
+    } else {
+        error!(
+            "Scene `{}` not ready after {:?}:",
+            scene_controller.name, gate.timeout
+        );
+    }
+    for item in &not_ready {
+        error!("  - {item}");
+    }
+    app_exit_writer.write(AppExit::error());
+    gate.gave_up = true;
+}
@@ -1,13 +1,15 @@
-use bevy::ecs::resource::Resource;
+use bevy::{camera::RenderTarget, ecs::resource::Resource};
+
+use crate::{image_grab::CaptureFormat, scene::PlaylistStatus};
 
 /// Capture image state
 #[derive(Debug, Default)]
 pub enum SceneState {
     #[default]
-    // State before any rendering
+    // State before any rendering, waiting for the scene, its assets and pipelines to be ready
     BuildScene,
-    // Rendering state, stores the number of frames remaining before saving the image
-    Render(u32),
+    // Rendering state, received frames are saved
+    Render,
 }
 
 // Capture image settings and state
@@ -17,7 +19,17 @@ pub struct SceneController {
     pub name: String,
     pub width: u32,
     pub height: u32,
+    // Pixel format of the primary capture target
+    pub capture_format: CaptureFormat,
     pub single_image: bool,
+    // Exit after this many frames were saved, when `single_image` is not set
+    pub frame_limit: Option<u32>,
+    // Save every rendered frame in order instead of only the latest one received
+    pub every_frame: bool,
+    // Target the scene cameras render into, set by `ImageCopyPlugin::setup_render_target`
+    pub render_target: Option<RenderTarget>,
+    // Entry the playlist shows or its manual hold, `None` without a playlist
+    pub playlist: Option<PlaylistStatus>,
 }
 
 impl SceneController {
@@ -27,7 +39,12 @@ impl SceneController {
             name: String::from(""),
             width,
             height,
+            capture_format: CaptureFormat::default(),
             single_image,
+            frame_limit: None,
+            every_frame: false,
+            render_target: None,
+            playlist: None,
         }
     }
 }
@@ -0,0 +1,19 @@
+// Copies the rendered half of the face mirrored onto the other half
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let size = textureDimensions(source);
+    let pixel = vec2<u32>(in.position.xy);
+    // Columns of the rendered half, which includes the middle one of odd widths
+    let half = (size.x + 1u) / 2u;
+#ifdef SOURCE_RIGHT
+    let rendered = pixel.x >= size.x - half;
+#else
+    let rendered = pixel.x < half;
+#endif
+    let x = select(size.x - 1u - pixel.x, pixel.x, rendered);
+    return textureLoad(source, vec2<u32>(x, pixel.y), 0);
+}
@@ -0,0 +1,164 @@
+use bevy::{
+    asset::load_embedded_asset,
+    core_pipeline::FullscreenShader,
+    ecs::query::QueryItem,
+    image::BevyDefault,
+    prelude::*,
+    render::{
+        render_graph::{NodeRunError, RenderGraphContext, ViewNode},
+        render_resource::{
+            BindGroupEntries, BindGroupLayoutDescriptor, BindGroupLayoutEntries,
+            CachedRenderPipelineId, ColorTargetState, ColorWrites, FragmentState, Operations,
+            PipelineCache, RenderPassColorAttachment, RenderPassDescriptor,
+            RenderPipelineDescriptor, ShaderStages, SpecializedRenderPipeline,
+            SpecializedRenderPipelines, TextureFormat, TextureSampleType,
+            binding_types::texture_2d,
+        },
+        renderer::RenderContext,
+        view::{ExtractedView, ViewTarget},
+    },
+};
+
+use crate::symmetry::mirror_symmetry::{MirrorSource, MirrorSymmetry};
+
+#[derive(Resource)]
+pub struct MirrorPipeline {
+    layout: BindGroupLayoutDescriptor,
+    fullscreen_shader: FullscreenShader,
+    fragment_shader: Handle<Shader>,
+}
+
+pub fn init_mirror_pipeline(
+    mut commands: Commands,
+    fullscreen_shader: Res<FullscreenShader>,
+    asset_server: Res<AssetServer>,
+) {
+    // Pixels are loaded exactly, no sampler
+    let layout = BindGroupLayoutDescriptor::new(
+        "mirror_bind_group_layout",
+        &BindGroupLayoutEntries::single(
+            ShaderStages::FRAGMENT,
+            texture_2d(TextureSampleType::Float { filterable: false }),
+        ),
+    );
+
+    commands.insert_resource(MirrorPipeline {
+        layout,
+        fullscreen_shader: fullscreen_shader.clone(),
+        fragment_shader: load_embedded_asset!(asset_server.as_ref(), "mirror.wgsl"),
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MirrorPipelineKey {
+    source: MirrorSource,
+    texture_format: TextureFormat,
+}
+
+impl SpecializedRenderPipeline for MirrorPipeline {
+    type Key = MirrorPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let shader_defs = match key.source {
+            MirrorSource::Left => Vec::new(),
+            MirrorSource::Right => vec!["SOURCE_RIGHT".into()],
+        };
+        RenderPipelineDescriptor {
+            label: Some("mirror".into()),
+            layout: vec![self.layout.clone()],
+            vertex: self.fullscreen_shader.to_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.fragment_shader.clone(),
+                shader_defs,
+                targets: vec![Some(ColorTargetState {
+                    format: key.texture_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+                ..default()
+            }),
+            ..default()
+        }
+    }
+}
+
+/// Pipeline for the mirrored side and texture format of the view
+#[derive(Component)]
+pub struct MirrorPipelineId(CachedRenderPipelineId);
+
+pub fn prepare_mirror_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<MirrorPipeline>>,
+    mirror_pipeline: Res<MirrorPipeline>,
+    views: Query<(Entity, &ExtractedView, &MirrorSymmetry)>,
+) {
+    for (entity, view, symmetry) in views.iter() {
+        let texture_format = match view.hdr {
+            true => ViewTarget::TEXTURE_FORMAT_HDR,
+            false => TextureFormat::bevy_default(),
+        };
+        let pipeline = pipelines.specialize(
+            &pipeline_cache,
+            &mirror_pipeline,
+            MirrorPipelineKey {
+                source: symmetry.source,
+                texture_format,
+            },
+        );
+        commands.entity(entity).insert(MirrorPipelineId(pipeline));
+    }
+}
+
+/// Fills the half of the view the camera did not render with the mirror image of the other
+#[derive(Default)]
+pub struct MirrorNode;
+
+impl ViewNode for MirrorNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static MirrorPipelineId,
+        &'static MirrorSymmetry,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (target, pipeline, _): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let mirror_pipeline = world.resource::<MirrorPipeline>();
+        // Still compiling, only the rendered half shows until it is ready
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline.0) else {
+            return Ok(());
+        };
+
+        let post_process = target.post_process_write();
+        let bind_group = render_context.render_device().create_bind_group(
+            None,
+            &pipeline_cache.get_bind_group_layout(&mirror_pipeline.layout),
+            &BindGroupEntries::single(post_process.source),
+        );
+        let mut render_pass =
+            render_context
+                .command_encoder()
+                .begin_render_pass(&RenderPassDescriptor {
+                    label: Some("mirror"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: post_process.destination,
+                        depth_slice: None,
+                        resolve_target: None,
+                        ops: Operations::default(),
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::{
+    asset::embedded_asset,
+    camera::{SubCameraView, Viewport},
+    core_pipeline::core_3d::graph::{Core3d, Node3d},
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderStartup, RenderSystems,
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_graph::{RenderGraphExt, RenderLabel, ViewNodeRunner},
+        render_resource::SpecializedRenderPipelines,
+    },
+};
+use serde::Deserialize;
+
+use crate::{
+    face::{FaceCamera, MorphControls},
+    output::PanelLayout,
+    scene::SceneController,
+    symmetry::mirror_node::{
+        MirrorNode, MirrorPipeline, init_mirror_pipeline, prepare_mirror_pipelines,
+    },
+};
+
+/// Morph weight above which an override counts as showing
+const OVERRIDE_THRESHOLD: f32 = 0.01;
+
+/// Half of the face that is rendered, the other one is its mirror image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MirrorSource {
+    #[default]
+    Left,
+    Right,
+}
+
+/// Left/right symmetry of the face, the `symmetry` of a [`PanelLayout`].
+///
+/// ```json
+/// { "source": "left", "overrides": ["wink_l", "Face/wink_r"] }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SymmetryConfig {
+    pub source: MirrorSource,
+    // Morph targets, `target` or `node/target`, that make the face asymmetric like a wink.
+    // While any of them shows the whole face is rendered instead of mirrored.
+    pub overrides: Vec<String>,
+}
+
+/// Face camera rendering only its source half, the mirror pass fills in the other one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component, ExtractComponent)]
+pub struct MirrorSymmetry {
+    pub source: MirrorSource,
+}
+
+/// Narrows the active face cameras to the source half of their target, unless an override
+/// morph is showing
+fn apply_symmetry(
+    mut commands: Commands,
+    layout: Res<PanelLayout>,
+    scene_controller: Res<SceneController>,
+    morphs: Query<(&MorphControls, Option<&Name>)>,
+    mut cameras: Query<(Entity, &mut Camera, Option<&MirrorSymmetry>), With<FaceCamera>>,
+) {
+    let mirrored = layout.symmetry.as_ref().filter(|symmetry| {
+        symmetry
+            .overrides
+            .iter()
+            .all(|name| MorphControls::find_weight(morphs.iter(), name) <= OVERRIDE_THRESHOLD)
+    });
+
+    let full_size = UVec2::new(scene_controller.width, scene_controller.height);
+    // The middle column of odd widths belongs to the rendered half
+    let half = UVec2::new(full_size.x.div_ceil(2), full_size.y);
+    for (entity, mut camera, symmetry) in cameras.iter_mut() {
+        let Some(config) = mirrored.filter(|_| camera.is_active) else {
+            if symmetry.is_some() {
+                camera.viewport = None;
+                camera.sub_camera_view = None;
+                commands.entity(entity).remove::<MirrorSymmetry>();
+            }
+            continue;
+        };
+
+        let offset = match config.source {
+            MirrorSource::Left => UVec2::ZERO,
+            MirrorSource::Right => UVec2::new(full_size.x - half.x, 0),
+        };
+        let sub_camera_view = SubCameraView {
+            full_size,
+            offset: offset.as_vec2(),
+            size: half,
+        };
+        if camera.sub_camera_view != Some(sub_camera_view) {
+            camera.sub_camera_view = Some(sub_camera_view);
+            camera.viewport = Some(Viewport {
+                physical_position: offset,
+                physical_size: half,
+                ..default()
+            });
+        }
+        let mirror = MirrorSymmetry {
+            source: config.source,
+        };
+        if symmetry != Some(&mirror) {
+            commands.entity(entity).insert(mirror);
+        }
+    }
+}
+
+/// Render graph node of the mirror pass, per camera passes that depend on the whole image go
+/// after it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, RenderLabel)]
+pub struct MirrorSymmetryLabel;
+
+/// Renders one half of the face and mirrors it onto the other, halving the fragment work of
+/// the face cameras. Enabled by the `symmetry` of the [`PanelLayout`].
+pub struct SymmetryPlugin;
+
+impl Plugin for SymmetryPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "mirror.wgsl");
+        app.add_plugins(ExtractComponentPlugin::<MirrorSymmetry>::default())
+            .add_systems(Update, apply_symmetry);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedRenderPipelines<MirrorPipeline>>()
+            .add_systems(RenderStartup, init_mirror_pipeline)
+            .add_systems(
+                Render,
+                prepare_mirror_pipelines.in_set(RenderSystems::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<MirrorNode>>(Core3d, MirrorSymmetryLabel)
+            .add_render_graph_edges(
+                Core3d,
+                (
+                    Node3d::Tonemapping,
+                    MirrorSymmetryLabel,
+                    Node3d::EndMainPassPostProcessing,
+                ),
+            );
+    }
+}
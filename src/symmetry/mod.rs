@@ -0,0 +1,3 @@
+mod mirror_node;
+mod mirror_symmetry;
+pub use mirror_symmetry::{MirrorSymmetryLabel, SymmetryConfig, SymmetryPlugin};
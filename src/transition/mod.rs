@@ -0,0 +1,3 @@
+mod scene_transition;
+pub use scene_transition::{SceneTransitionPlugin, TransitionKind, TransitionRequest};
+mod transition_material;
@@ -0,0 +1,339 @@
+use bevy::{
+    asset::embedded_asset,
+    camera::{RenderTarget, visibility::RenderLayers},
+    math::curve::{Curve, easing::EaseFunction},
+    prelude::*,
+    sprite_render::Material2dPlugin,
+};
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::{
+    control::ControlCommand,
+    face::{FaceCamera, FaceLayer, FaceRoot, FaceScene, FaceSceneReady, FaceSceneTarget},
+    output::PanelLayout,
+    scene::SceneController,
+    transition::transition_material::{TransitionMaterial, TransitionParams},
+};
+
+/// How the incoming face replaces the outgoing one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionKind {
+    // Both faces blended, the outgoing one fading out
+    #[default]
+    Crossfade,
+    // The incoming face sliding in from the left
+    Wipe,
+    // Panel pixels switching over in random order
+    Dissolve,
+    // Columns of pixels sorting themselves into the incoming face
+    PixelSort,
+}
+
+impl FromStr for TransitionKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<TransitionKind, String> {
+        match name {
+            "crossfade" => Ok(TransitionKind::Crossfade),
+            "wipe" => Ok(TransitionKind::Wipe),
+            "dissolve" => Ok(TransitionKind::Dissolve),
+            "pixel_sort" | "pixel-sort" => Ok(TransitionKind::PixelSort),
+            other => Err(format!(
+                "unknown transition `{other}`, expected crossfade, wipe, dissolve or pixel_sort"
+            )),
+        }
+    }
+}
+
+/// Replaces the shown face with another scene, blending between both over `duration`
+#[derive(Debug, Clone, Message)]
+pub struct TransitionRequest {
+    pub scene: FaceScene,
+    pub kind: TransitionKind,
+    // Seconds, 0 switches on the first frame the incoming face is rendered
+    pub duration: f32,
+    pub easing: EaseFunction,
+}
+
+/// Transition in progress, the outgoing and incoming faces each render to their own image
+#[derive(Debug)]
+struct Transition {
+    request: TransitionRequest,
+    // `None` when no face was shown before
+    outgoing: Option<(Entity, FaceLayer)>,
+    incoming: (Entity, FaceLayer),
+    outgoing_image: Handle<Image>,
+    incoming_image: Handle<Image>,
+    // Set once the incoming face is spawned and its cameras are set up
+    incoming_ready: bool,
+    // Quad blending both images into the capture target, spawned once the incoming face is ready
+    quad: Option<(Entity, Handle<TransitionMaterial>)>,
+    elapsed: f32,
+}
+
+#[derive(Debug, Default, Resource)]
+struct ActiveTransition(Option<Transition>);
+
+/// Points the cameras of the face on `layer` at `target`
+fn retarget_cameras(
+    commands: &mut Commands,
+    cameras: &Query<(Entity, &RenderLayers), With<FaceCamera>>,
+    layer: FaceLayer,
+    target: RenderTarget,
+) {
+    let layer = RenderLayers::layer(layer.0);
+    for (entity, _) in cameras.iter().filter(|(_, layers)| **layers == layer) {
+        commands.entity(entity).insert(target.clone());
+    }
+}
+
+/// Image a face renders into during a transition, like the capture target so the blend
+/// keeps its precision and headroom
+fn transition_target(scene_controller: &SceneController) -> Image {
+    Image::new_target_texture(
+        scene_controller.width,
+        scene_controller.height,
+        scene_controller.capture_format.texture_format(),
+        None,
+    )
+}
+
+/// Turns `scene` control commands into [`TransitionRequest`]s
+fn request_scene_transitions(
+    mut control_commands: MessageReader<ControlCommand>,
+    mut requests: MessageWriter<TransitionRequest>,
+) {
+    for command in control_commands.read() {
+        if let ControlCommand::Scene {
+            path,
+            kind,
+            duration,
+        } = command
+        {
+            requests.write(TransitionRequest {
+                scene: FaceScene::new(path.clone()),
+                kind: *kind,
+                duration: *duration,
+                easing: EaseFunction::SmoothStep,
+            });
+        }
+    }
+}
+
+fn start_transitions(
+    mut commands: Commands,
+    mut requests: MessageReader<TransitionRequest>,
+    mut active: ResMut<ActiveTransition>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    scene_controller: Res<SceneController>,
+    roots: Query<(Entity, &FaceLayer), With<FaceRoot>>,
+) {
+    for request in requests.read() {
+        if active.0.is_some() {
+            warn!(
+                "Ignoring transition to {}, another transition is running",
+                request.scene.path
+            );
+            continue;
+        }
+
+        let outgoing = roots.iter().next().map(|(entity, layer)| (entity, *layer));
+        // The faces alternate between two layers so each camera only sees its own face
+        let layer = match outgoing {
+            Some((_, FaceLayer(1))) => FaceLayer(2),
+            _ => FaceLayer(1),
+        };
+        let outgoing_image = images.add(transition_target(&scene_controller));
+        let incoming_image = images.add(transition_target(&scene_controller));
+
+        info!(
+            "Transition to {} ({:?}, {}s)",
+            request.scene.path, request.kind, request.duration
+        );
+        let (incoming, _) = request.scene.spawn(&mut commands, &asset_server, layer);
+        commands
+            .entity(incoming)
+            .insert(FaceSceneTarget(RenderTarget::Image(
+                incoming_image.clone().into(),
+            )));
+        active.0 = Some(Transition {
+            request: request.clone(),
+            outgoing,
+            incoming: (incoming, layer),
+            outgoing_image,
+            incoming_image,
+            incoming_ready: false,
+            quad: None,
+            elapsed: 0.0,
+        });
+    }
+}
+
+fn mark_incoming_ready(ready: On<FaceSceneReady>, mut active: ResMut<ActiveTransition>) {
+    if let Some(transition) = active.0.as_mut()
+        && transition.incoming.0 == ready.root
+    {
+        transition.incoming_ready = true;
+    }
+}
+
+/// Moves the outgoing face off the capture target and blends both faces into it instead
+fn show_incoming_face(
+    mut commands: Commands,
+    mut active: ResMut<ActiveTransition>,
+    layout: Res<PanelLayout>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TransitionMaterial>>,
+    cameras: Query<(Entity, &RenderLayers), With<FaceCamera>>,
+) {
+    let Some(transition) = active.0.as_mut() else {
+        return;
+    };
+    if !transition.incoming_ready || transition.quad.is_some() {
+        return;
+    }
+
+    if let Some((_, layer)) = transition.outgoing {
+        let target = RenderTarget::Image(transition.outgoing_image.clone().into());
+        retarget_cameras(&mut commands, &cameras, layer, target);
+    }
+
+    let size = Vec2::new(layout.size.width as f32, layout.size.height as f32);
+    let material = materials.add(TransitionMaterial {
+        params: TransitionParams {
+            progress: 0.0,
+            kind: transition.request.kind as u32,
+            panel_size: size,
+        },
+        outgoing: transition.outgoing_image.clone(),
+        incoming: transition.incoming_image.clone(),
+    });
+    let quad = commands
+        .spawn((
+            Name::new("scene transition"),
+            Mesh2d(meshes.add(Rectangle::from_size(size))),
+            MeshMaterial2d(material.clone()),
+            // Under every layer of every region
+            Transform::from_translation((size / 2.0).extend(-1.0)),
+        ))
+        .id();
+    transition.quad = Some((quad, material));
+}
+
+/// Follows resolution, capture format and panel size changes made while a transition runs
+fn resize_transition(
+    mut commands: Commands,
+    active: Res<ActiveTransition>,
+    scene_controller: Res<SceneController>,
+    layout: Res<PanelLayout>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TransitionMaterial>>,
+) {
+    let Some(transition) = active.0.as_ref() else {
+        return;
+    };
+
+    let target = transition_target(&scene_controller);
+    for image in [&transition.outgoing_image, &transition.incoming_image] {
+        // Only replaced when needed, the cameras drawing into it keep the same handle
+        let outdated = images.get(image).is_some_and(|image| {
+            image.texture_descriptor.size != target.texture_descriptor.size
+                || image.texture_descriptor.format != target.texture_descriptor.format
+        });
+        if outdated && let Some(image) = images.get_mut(image) {
+            *image = target.clone();
+        }
+    }
+
+    let Some((quad, material)) = transition.quad.as_ref() else {
+        return;
+    };
+    let size = Vec2::new(layout.size.width as f32, layout.size.height as f32);
+    if materials
+        .get(material)
+        .is_some_and(|material| material.params.panel_size != size)
+        && let Some(material) = materials.get_mut(material)
+    {
+        material.params.panel_size = size;
+        commands.entity(*quad).insert((
+            Mesh2d(meshes.add(Rectangle::from_size(size))),
+            Transform::from_translation((size / 2.0).extend(-1.0)),
+        ));
+    }
+}
+
+/// Eases the blend along and hands the capture target to the incoming face once done
+fn advance_transition(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut active: ResMut<ActiveTransition>,
+    mut face_scene: ResMut<FaceScene>,
+    scene_controller: Res<SceneController>,
+    mut materials: ResMut<Assets<TransitionMaterial>>,
+    cameras: Query<(Entity, &RenderLayers), With<FaceCamera>>,
+) {
+    let Some(transition) = active.0.as_mut() else {
+        return;
+    };
+    let Some((quad, material)) = transition.quad.as_ref() else {
+        return;
+    };
+
+    transition.elapsed += time.delta_secs();
+    let request = &transition.request;
+    let t = match request.duration > 0.0 {
+        true => transition.elapsed / request.duration,
+        false => 1.0,
+    };
+    if let Some(material) = materials.get_mut(material) {
+        material.params.progress = request.easing.sample_clamped(t);
+    }
+    if t < 1.0 {
+        return;
+    }
+
+    info!("Transition to {} finished", request.scene.path);
+    if let Some((outgoing, _)) = transition.outgoing {
+        commands.entity(outgoing).despawn();
+    }
+    commands.entity(*quad).despawn();
+    let (incoming, layer) = transition.incoming;
+    commands.entity(incoming).remove::<FaceSceneTarget>();
+    if let Some(target) = scene_controller.render_target.clone() {
+        retarget_cameras(&mut commands, &cameras, layer, target);
+    }
+    *face_scene = request.scene.clone();
+    active.0 = None;
+}
+
+/// Switches faces on [`TransitionRequest`]s. The incoming face is spawned next to the
+/// outgoing one, both render to their own image and a quad drawn by the `LayerCamera`
+/// blends them into the capture target, under the layers and before post-processing.
+pub struct SceneTransitionPlugin;
+
+impl Plugin for SceneTransitionPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "transition.wgsl");
+        app.add_plugins(Material2dPlugin::<TransitionMaterial>::default())
+            .add_message::<TransitionRequest>()
+            .init_resource::<ActiveTransition>()
+            .add_observer(mark_incoming_ready)
+            .add_systems(
+                Update,
+                (
+                    request_scene_transitions,
+                    start_transitions,
+                    resize_transition.run_if(
+                        resource_changed::<SceneController>.or(resource_changed::<PanelLayout>),
+                    ),
+                    advance_transition,
+                    show_incoming_face,
+                )
+                    .chain(),
+            );
+    }
+}
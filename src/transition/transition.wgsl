@@ -0,0 +1,68 @@
+// Blends the outgoing and incoming faces while one scene replaces another
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+struct TransitionParams {
+    // 0 shows only the outgoing face, 1 only the incoming one, already eased
+    progress: f32,
+    // `TransitionKind`, in declaration order
+    kind: u32,
+    // Size of the LED panels, transitions work in panel pixels
+    panel_size: vec2<f32>,
+}
+
+@group(2) @binding(0) var<uniform> params: TransitionParams;
+@group(2) @binding(1) var outgoing_texture: texture_2d<f32>;
+@group(2) @binding(2) var outgoing_sampler: sampler;
+@group(2) @binding(3) var incoming_texture: texture_2d<f32>;
+@group(2) @binding(4) var incoming_sampler: sampler;
+
+const WIPE: u32 = 1u;
+const DISSOLVE: u32 = 2u;
+const PIXEL_SORT: u32 = 3u;
+
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn blend(in: VertexOutput) -> vec3<f32> {
+    let uv = in.uv;
+    let progress = params.progress;
+    let pixel = floor(uv * params.panel_size);
+    let outgoing = textureSample(outgoing_texture, outgoing_sampler, uv).rgb;
+    let incoming = textureSample(incoming_texture, incoming_sampler, uv).rgb;
+
+    switch params.kind {
+        case WIPE: {
+            // Left to right, with an edge a few panel pixels wide
+            let soft = 4.0 / params.panel_size.x;
+            let edge = progress * (1.0 + soft);
+            return mix(incoming, outgoing, smoothstep(edge - soft, edge, uv.x));
+        }
+        case DISSOLVE: {
+            // Panel pixels switch over one by one in random order
+            return select(outgoing, incoming, hash(pixel) < progress);
+        }
+        case PIXEL_SORT: {
+            // Columns start at random times, bright pixels of the outgoing face slide down
+            // and the incoming face streaks in from the top behind them
+            let column = clamp(progress * 1.5 - hash(vec2<f32>(pixel.x, 7.0)) * 0.5, 0.0, 1.0);
+            let sorted_outgoing = textureSampleLevel(outgoing_texture, outgoing_sampler,
+                uv - vec2<f32>(0.0, column * luminance(outgoing)), 0.0).rgb;
+            let sorted_incoming = textureSampleLevel(incoming_texture, incoming_sampler,
+                uv + vec2<f32>(0.0, (1.0 - column) * luminance(incoming)), 0.0).rgb;
+            return select(sorted_outgoing, sorted_incoming, uv.y < column);
+        }
+        default: {
+            return mix(outgoing, incoming, progress);
+        }
+    }
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(blend(in), 1.0);
+}
@@ -0,0 +1,41 @@
+use bevy::{
+    asset::{AssetPath, embedded_path},
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderType},
+    shader::ShaderRef,
+    sprite_render::{AlphaMode2d, Material2d},
+};
+
+#[derive(Debug, Clone, ShaderType)]
+pub struct TransitionParams {
+    // Eased, 0 shows only the outgoing face and 1 only the incoming one
+    pub progress: f32,
+    pub kind: u32,
+    pub panel_size: Vec2,
+}
+
+/// Full panel quad blending the renders of the outgoing and incoming faces
+#[derive(Debug, Clone, Asset, TypePath, AsBindGroup)]
+pub struct TransitionMaterial {
+    #[uniform(0)]
+    pub params: TransitionParams,
+    #[texture(1)]
+    #[sampler(2)]
+    pub outgoing: Handle<Image>,
+    #[texture(3)]
+    #[sampler(4)]
+    pub incoming: Handle<Image>,
+}
+
+impl Material2d for TransitionMaterial {
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Path(
+            AssetPath::from_path_buf(embedded_path!("transition.wgsl")).with_source("embedded"),
+        )
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        // Drawn before the layers, which composite over it like over a face render
+        AlphaMode2d::Opaque
+    }
+}
@@ -0,0 +1,17 @@
+//! Golden image tests, see `harness` for running them and blessing new goldens
+
+mod harness;
+use harness::GoldenCase;
+
+#[test]
+#[ignore = "needs a graphics adapter, run with --ignored"]
+fn default_face_first_frame() {
+    GoldenCase::new("default_face_first_frame").run();
+}
+
+// Two seconds in, blinks and saccades have moved the eyes away from their rest pose
+#[test]
+#[ignore = "needs a graphics adapter, run with --ignored"]
+fn default_face_eye_motion() {
+    GoldenCase::new("default_face_eye_motion").frame(120).run();
+}
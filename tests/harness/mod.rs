@@ -0,0 +1,355 @@
+//! Runs the headless renderer for a scene and compares a captured frame against a golden PNG.
+//!
+//! Goldens live in `tests/golden/<case>.png`. Set `UPDATE_GOLDEN=1` to write the captured
+//! frames as the new goldens instead of comparing. On a mismatch the captured frame and a
+//! diff image are written to `target/tmp/golden/<case>/`.
+//!
+//! A new case has no golden yet and fails until one is generated on a machine with an
+//! adapter and committed:
+//!
+//! ```sh
+//! UPDATE_GOLDEN=1 cargo test --test golden_images -- --ignored
+//! cargo test --test golden_images -- --ignored
+//! git add tests/golden
+//! ```
+//!
+//! The second run checks the fresh goldens are stable. Regenerate them the same way when a
+//! change to the renderer is meant to change the picture, and review the new PNGs.
+//!
+//! Rendering uses the software Vulkan driver (llvmpipe/lavapipe) unless `WGPU_BACKEND` or
+//! `WGPU_ADAPTER_NAME` say otherwise. The cases need a graphics adapter, so they are ignored
+//! by default, run them with `cargo test -- --ignored`. Without any usable adapter a case
+//! fails, `GOLDEN_ALLOW_SKIP=1` skips it instead.
+
+use image::{Rgba, RgbaImage};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// How far a captured frame may be from its golden
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    // Perceptual color distance in 0..1 above which a pixel counts as different
+    pub pixel_threshold: f64,
+    // Fraction of pixels that may differ
+    pub max_diff_ratio: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            pixel_threshold: 0.1,
+            max_diff_ratio: 0.001,
+        }
+    }
+}
+
+/// One golden image comparison
+pub struct GoldenCase {
+    pub name: &'static str,
+    pub face: &'static str,
+    // Index of the captured frame to compare, counted from the first frame after readiness
+    pub frame: u32,
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    pub tolerance: Tolerance,
+}
+
+impl GoldenCase {
+    pub fn new(name: &'static str) -> GoldenCase {
+        GoldenCase {
+            name,
+            face: "faces/default.gltf",
+            frame: 0,
+            width: 320,
+            height: 180,
+            seed: 0,
+            tolerance: Tolerance::default(),
+        }
+    }
+
+    pub fn frame(mut self, frame: u32) -> GoldenCase {
+        self.frame = frame;
+        self
+    }
+
+    /// Renders the case and compares or blesses it, panics on a mismatch
+    pub fn run(&self) {
+        let work_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+            .join("golden")
+            .join(self.name);
+        let output = work_dir.join("frames");
+        // Frames of a previous run must not be mistaken for this one's
+        let _ = std::fs::remove_dir_all(&work_dir);
+
+        let Some(actual) = self.render(&output) else {
+            return;
+        };
+
+        let golden_path = golden_dir().join(format!("{}.png", self.name));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(golden_dir()).unwrap();
+            actual.save(&golden_path).unwrap();
+            eprintln!("Blessed {golden_path:?}");
+            return;
+        }
+
+        let golden = match image::open(&golden_path) {
+            Ok(golden) => golden.to_rgba8(),
+            Err(e) => panic!(
+                "No golden for `{}` at {golden_path:?} ({e}), run with UPDATE_GOLDEN=1 to create it",
+                self.name
+            ),
+        };
+
+        let comparison = compare(&golden, &actual, self.tolerance.pixel_threshold);
+        let allowed = (self.tolerance.max_diff_ratio * comparison.pixels as f64).floor() as u64;
+        if comparison.size_mismatch || comparison.differing > allowed {
+            let actual_path = work_dir.join("actual.png");
+            let diff_path = work_dir.join("diff.png");
+            actual.save(&actual_path).unwrap();
+            if let Some(diff) = &comparison.diff {
+                diff.save(&diff_path).unwrap();
+            }
+            panic!(
+                "`{}` differs from its golden: {comparison}, at most {allowed} pixels allowed\n\
+                 captured: {actual_path:?}\n\
+                 diff: {diff_path:?}",
+                self.name
+            );
+        }
+        eprintln!("`{}` matches its golden: {comparison}", self.name);
+    }
+
+    /// Runs the app in deterministic mode up to the compared frame, `None` when skipped for
+    /// lack of a gpu
+    fn render(&self, output: &Path) -> Option<RgbaImage> {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_protogen_renderer_bevy"));
+        command
+            .args(["--face", self.face])
+            .args(["--width", &self.width.to_string()])
+            .args(["--height", &self.height.to_string()])
+            .args(["--seed", &self.seed.to_string()])
+            .args(["--frames", &(self.frame + 1).to_string()])
+            // Shader compilation on a software rasterizer is slow
+            .args(["--ready-timeout", "300"])
+            .arg("--deterministic")
+            .arg("--output")
+            .arg(output)
+            .env("BEVY_ASSET_ROOT", env!("CARGO_MANIFEST_DIR"));
+        if std::env::var_os("WGPU_BACKEND").is_none()
+            && std::env::var_os("WGPU_ADAPTER_NAME").is_none()
+        {
+            command
+                .env("WGPU_BACKEND", "vulkan")
+                .env("WGPU_ADAPTER_NAME", "llvmpipe");
+        }
+
+        let result = command.output().expect("Failed to run the renderer");
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        if !result.status.success() {
+            if stderr.contains("Unable to find a GPU") {
+                if std::env::var_os("GOLDEN_ALLOW_SKIP").is_some() {
+                    eprintln!("Skipping `{}`, no usable graphics adapter", self.name);
+                    return None;
+                }
+                panic!(
+                    "No usable graphics adapter for `{}`, install lavapipe or set \
+                     GOLDEN_ALLOW_SKIP=1 to skip",
+                    self.name
+                );
+            }
+            panic!(
+                "Renderer failed for `{}` ({}):\n{stderr}",
+                self.name, result.status
+            );
+        }
+
+        let frame_path = output.join(format!("{:03}.png", self.frame));
+        match image::open(&frame_path) {
+            Ok(frame) => Some(frame.to_rgba8()),
+            Err(e) => panic!(
+                "Renderer did not write {frame_path:?} for `{}` ({e}):\n{stderr}",
+                self.name
+            ),
+        }
+    }
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+/// Result of comparing a captured frame with its golden
+struct Comparison {
+    size_mismatch: bool,
+    pixels: u64,
+    differing: u64,
+    // Largest perceptual distance of any pixel, 0..1
+    max_distance: f64,
+    // Peak signal to noise ratio over the RGB channels, infinite for identical images
+    psnr: f64,
+    diff: Option<RgbaImage>,
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.size_mismatch {
+            return write!(f, "image sizes differ");
+        }
+        write!(
+            f,
+            "{} of {} pixels differ ({:.4}%), max distance {:.3}, PSNR {:.2}dB",
+            self.differing,
+            self.pixels,
+            self.differing as f64 * 100.0 / self.pixels.max(1) as f64,
+            self.max_distance,
+            self.psnr
+        )
+    }
+}
+
+fn compare(golden: &RgbaImage, actual: &RgbaImage, pixel_threshold: f64) -> Comparison {
+    if golden.dimensions() != actual.dimensions() {
+        return Comparison {
+            size_mismatch: true,
+            pixels: 0,
+            differing: 0,
+            max_distance: 0.0,
+            psnr: 0.0,
+            diff: None,
+        };
+    }
+
+    let mut diff = RgbaImage::new(golden.width(), golden.height());
+    let mut differing = 0;
+    let mut max_distance: f64 = 0.0;
+    let mut squared_error = 0.0;
+    for ((expected, found), diff_pixel) in
+        golden.pixels().zip(actual.pixels()).zip(diff.pixels_mut())
+    {
+        for channel in 0..3 {
+            squared_error += (expected[channel] as f64 - found[channel] as f64).powi(2);
+        }
+        let distance = perceptual_distance(expected, found);
+        max_distance = max_distance.max(distance);
+        *diff_pixel = if distance > pixel_threshold {
+            differing += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // Faded golden, so differences stand out against the picture
+            let luma = (yiq(expected)[0] * 0.25) as u8;
+            Rgba([luma, luma, luma, 255])
+        };
+    }
+
+    let pixels = golden.width() as u64 * golden.height() as u64;
+    let mse = squared_error / (pixels * 3).max(1) as f64;
+    Comparison {
+        size_mismatch: false,
+        pixels,
+        differing,
+        max_distance,
+        psnr: 10.0 * (255.0 * 255.0 / mse).log10(),
+        diff: Some(diff),
+    }
+}
+
+/// Pixels blended over black, converted to YIQ
+fn yiq(pixel: &Rgba<u8>) -> [f64; 3] {
+    let alpha = pixel[3] as f64 / 255.0;
+    let [r, g, b] = [0, 1, 2].map(|channel| pixel[channel] as f64 * alpha);
+    [
+        0.29889531 * r + 0.58662247 * g + 0.11448223 * b,
+        0.59597799 * r - 0.27417610 * g - 0.32180189 * b,
+        0.21147017 * r - 0.52261711 * g + 0.31114694 * b,
+    ]
+}
+
+/// Color distance weighted like the eye weighs brightness and hue, normalized to 0..1.
+/// From "Measuring perceived color difference using YIQ NTSC transmission color space"
+/// (Kotsarenko and Ramos), as used by pixelmatch.
+fn perceptual_distance(expected: &Rgba<u8>, found: &Rgba<u8>) -> f64 {
+    // Largest possible weighted distance, between black and white
+    const MAX_DELTA: f64 = 35215.0;
+    let [y1, i1, q1] = yiq(expected);
+    let [y2, i2, q2] = yiq(found);
+    let delta = 0.5053 * (y1 - y2).powi(2) + 0.299 * (i1 - i2).powi(2) + 0.1957 * (q1 - q2).powi(2);
+    (delta / MAX_DELTA).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(color))
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let golden = image(4, 3, [200, 120, 40, 255]);
+        let comparison = compare(&golden, &golden.clone(), 0.1);
+        assert!(!comparison.size_mismatch);
+        assert_eq!(comparison.pixels, 12);
+        assert_eq!(comparison.differing, 0);
+        assert_eq!(comparison.max_distance, 0.0);
+        assert!(comparison.psnr.is_infinite());
+    }
+
+    #[test]
+    fn pixels_past_the_threshold_differ() {
+        let golden = image(4, 4, [0, 0, 0, 255]);
+        let mut actual = golden.clone();
+        // A barely visible change and a black to white change
+        actual.put_pixel(0, 0, Rgba([4, 4, 4, 255]));
+        actual.put_pixel(3, 3, Rgba([255, 255, 255, 255]));
+
+        let comparison = compare(&golden, &actual, 0.1);
+        assert_eq!(comparison.differing, 1);
+        assert_eq!(
+            comparison.max_distance,
+            perceptual_distance(&Rgba([0, 0, 0, 255]), &Rgba([255, 255, 255, 255]))
+        );
+        assert!(comparison.psnr.is_finite());
+        let diff = comparison.diff.unwrap();
+        assert_eq!(diff.get_pixel(3, 3), &Rgba([255, 0, 0, 255]));
+        assert_ne!(diff.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+
+        // A loose enough threshold lets both through
+        assert_eq!(compare(&golden, &actual, 1.0).differing, 0);
+    }
+
+    #[test]
+    fn different_sizes_mismatch() {
+        let comparison = compare(&image(4, 3, [0; 4]), &image(3, 4, [0; 4]), 0.1);
+        assert!(comparison.size_mismatch);
+        assert!(comparison.diff.is_none());
+        assert_eq!(comparison.to_string(), "image sizes differ");
+    }
+
+    #[test]
+    fn perceptual_distance_range() {
+        let black = Rgba([0, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        assert_eq!(perceptual_distance(&black, &black), 0.0);
+        // Hue changes can go further than brightness alone, so white is not quite 1
+        let distance = perceptual_distance(&black, &white);
+        assert!(distance > 0.95 && distance <= 1.0);
+        assert_eq!(
+            perceptual_distance(&black, &white),
+            perceptual_distance(&white, &black)
+        );
+        // Transparent pixels count as blended over black
+        assert_eq!(perceptual_distance(&black, &Rgba([255, 255, 255, 0])), 0.0);
+        // Brightness weighs more than hue
+        let gray = Rgba([128, 128, 128, 255]);
+        let reddish = Rgba([148, 118, 118, 255]);
+        let brighter = Rgba([148, 148, 148, 255]);
+        assert!(perceptual_distance(&gray, &brighter) > perceptual_distance(&gray, &reddish));
+    }
+}